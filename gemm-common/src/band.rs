@@ -0,0 +1,158 @@
+//! Products of banded matrices in the LAPACK band storage format with dense matrices.
+
+use crate::{
+    gemm::par_for_each,
+    simd::Simd,
+    sparse::{axpy, dispatch, store, SparseScalar, NC},
+    tiled::n_threads,
+    Parallelism, Ptr,
+};
+
+/// Banded matrix in the LAPACK band storage format.
+///
+/// Only the `kl` subdiagonals, the diagonal and the `ku` superdiagonals may be nonzero. Entry
+/// `(i, j)` of the band is stored at `values[ku + i - j + j * ld]`, so that column `j` of the
+/// matrix is column `j` of a column major `(kl + ku + 1)×ncols` array with leading dimension
+/// `ld >= kl + ku + 1`. The entries of that array outside of the matrix are not read.
+#[derive(Debug)]
+pub struct BandMatrix<'a, T> {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub kl: usize,
+    pub ku: usize,
+    pub ld: usize,
+    pub values: &'a [T],
+}
+
+impl<T> Copy for BandMatrix<'_, T> {}
+impl<T> Clone for BandMatrix<'_, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[inline(always)]
+unsafe fn band_gemm_generic<S: Simd, T: SparseScalar>(
+    _simd: S,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: BandMatrix<'_, T>,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    let m = lhs.nrows;
+    let k = lhs.ncols;
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let kl = lhs.kl;
+    let ku = lhs.ku;
+    // the rows have at most `kl + ku + 1` entries, so they are split evenly
+    let n_threads = n_threads(parallelism, m * (kl + ku + 1) * n).min(m);
+
+    let dst = Ptr(dst);
+    let rhs = Ptr(rhs as *mut T);
+    let values = lhs.values;
+    // entry `(i, j)` of the band is at `values[ku + i + j * (ld - 1)]`
+    let col_stride = lhs.ld - 1;
+
+    let func = |tid: usize| {
+        let row_start = m * tid / n_threads;
+        let row_end = m * (tid + 1) / n_threads;
+        let dst = dst.wrapping_add(0).0;
+        let rhs = rhs.wrapping_add(0).0 as *const T;
+
+        S::vectorize(
+            #[inline(always)]
+            || {
+                let mut acc = [T::zero(); NC];
+                for i in row_start..row_end {
+                    let entries = i.saturating_sub(kl)..Ord::min(k, i + ku + 1);
+                    let dst = dst.offset(i as isize * dst_rs);
+
+                    let mut col = 0;
+                    while col < n {
+                        let nc = NC.min(n - col);
+                        let acc = &mut acc[..nc];
+                        acc.fill(T::zero());
+
+                        for p in entries.clone() {
+                            let a = *values.get_unchecked(ku + i + p * col_stride);
+                            axpy(
+                                acc,
+                                a,
+                                rhs.offset(p as isize * rhs_rs + col as isize * rhs_cs),
+                                rhs_cs,
+                            );
+                        }
+
+                        store(
+                            dst.offset(col as isize * dst_cs),
+                            dst_cs,
+                            acc,
+                            read_dst,
+                            alpha,
+                            beta,
+                        );
+                        col += nc;
+                    }
+                }
+            },
+        );
+    };
+
+    if n_threads <= 1 {
+        func(0);
+    } else {
+        par_for_each(n_threads, func);
+    }
+}
+
+/// Banded × dense matrix product: dst := alpha×dst + beta×lhs×rhs, where `lhs` is an `m×k`
+/// matrix in band storage, `rhs` is `k×n` and `dst` is `m×n`, with `m = lhs.nrows` and
+/// `k = lhs.ncols`.
+///
+/// Only the entries inside the band are read. If `read_dst` is false, `alpha` is ignored and
+/// `dst` is not read. The rows of `dst` are split across threads.
+pub unsafe fn band_gemm<T: SparseScalar>(
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: BandMatrix<'_, T>,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    assert!(lhs.ld > lhs.kl + lhs.ku);
+    if lhs.ncols > 0 {
+        assert!(lhs.values.len() > (lhs.ncols - 1) * lhs.ld + lhs.kl + lhs.ku);
+    }
+    dispatch!(band_gemm_generic::<_, T>(
+        n,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        parallelism,
+    ))
+}
@@ -0,0 +1,357 @@
+//! Products of bit matrices.
+//!
+//! Each row of `lhs` and each column of `rhs` is a vector of `k` bits, stored in
+//! `div_ceil(k, 64)` consecutive `u64` words: bit `p` is bit `p % 64` of word `p / 64`. The bits
+//! past `k` in the last word are ignored. `lhs_rs` and `rhs_cs` are the distances between
+//! consecutive rows of `lhs` and columns of `rhs`, in words.
+
+use crate::{
+    cache::div_ceil,
+    gemm::par_for_each,
+    simd::Simd,
+    tiled::{job_range, n_threads},
+    Parallelism, Ptr,
+};
+
+// rows and columns handled by a single job. a job covers one word of the packed boolean
+// destination, so that no two threads write to the same word
+const MR: usize = 4;
+const NC: usize = 64;
+// words of `k` processed before moving on to the next columns, so that the rhs columns of a
+// job stay in cache
+const KC_WORDS: usize = 64;
+
+#[inline(always)]
+fn and_any(lhs: &[u64], rhs: &[u64], last_mask: u64) -> bool {
+    let (lhs_last, lhs) = lhs.split_last().unwrap();
+    let (rhs_last, rhs) = rhs.split_last().unwrap();
+    let acc = lhs
+        .iter()
+        .zip(rhs)
+        .fold(lhs_last & rhs_last & last_mask, |acc, (&a, &b)| {
+            acc | (a & b)
+        });
+    acc != 0
+}
+
+#[inline(always)]
+fn xnor_popcount(lhs: &[u64], rhs: &[u64], last_mask: u64) -> u32 {
+    let (lhs_last, lhs) = lhs.split_last().unwrap();
+    let (rhs_last, rhs) = rhs.split_last().unwrap();
+    lhs.iter()
+        .zip(rhs)
+        .map(|(&a, &b)| (!(a ^ b)).count_ones())
+        .sum::<u32>()
+        + (!(lhs_last ^ rhs_last) & last_mask).count_ones()
+}
+
+// calls `job(row, col, depth_word, n_words, last_mask, first)` for every `MR×NC` block of the
+// output, and every `KC_WORDS` block of the words of `k`, splitting the output blocks across
+// threads
+#[inline(always)]
+unsafe fn for_each_block<S: Simd>(
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism,
+    job: impl Fn(usize, usize, usize, usize, u64, bool) + Send + Sync,
+) {
+    let k_words = div_ceil(k, 64);
+    let tail = k % 64;
+    let last_mask = if tail == 0 { !0 } else { (1u64 << tail) - 1 };
+
+    let n_row_blocks = div_ceil(m, MR);
+    let n_col_blocks = div_ceil(n, NC);
+    let n_jobs = n_row_blocks * n_col_blocks;
+    let n_threads = n_threads(parallelism, m * n * k_words).min(n_jobs);
+
+    let func = |tid: usize| {
+        let (job_start, job_end) = job_range(tid, n_threads, n_jobs);
+
+        S::vectorize(
+            #[inline(always)]
+            || {
+                for job_id in job_start..job_end {
+                    let row = MR * (job_id % n_row_blocks);
+                    let col = NC * (job_id / n_row_blocks);
+
+                    let mut depth_word = 0;
+                    while depth_word != k_words {
+                        let n_words = KC_WORDS.min(k_words - depth_word);
+                        let mask = if depth_word + n_words == k_words {
+                            last_mask
+                        } else {
+                            !0
+                        };
+                        job(row, col, depth_word, n_words, mask, depth_word == 0);
+                        depth_word += n_words;
+                    }
+                }
+            },
+        );
+    };
+
+    if n_threads <= 1 {
+        func(0);
+    } else {
+        par_for_each(n_threads, func);
+    }
+}
+
+macro_rules! dispatch {
+    ($func: ident, $($arg: expr),* $(,)?) => {{
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            #[cfg(feature = "nightly")]
+            if crate::feature_detected!("avx512vpopcntdq") && crate::feature_detected!("popcnt") {
+                return $func::<crate::simd::Avx512Vpopcntdq>($($arg),*);
+            }
+            if crate::feature_detected!("avx2") && crate::feature_detected!("popcnt") {
+                return $func::<crate::simd::Avx2Popcnt>($($arg),*);
+            } else if crate::feature_detected!("popcnt") {
+                return $func::<crate::simd::Popcnt>($($arg),*);
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            if crate::feature_detected!("neon") {
+                return $func::<crate::simd::Neon>($($arg),*);
+            }
+        }
+
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        {
+            return $func::<crate::simd::Simd128>($($arg),*);
+        }
+
+        #[allow(unreachable_code)]
+        {
+            $func::<crate::simd::Scalar>($($arg),*)
+        }
+    }};
+}
+
+#[inline(always)]
+unsafe fn gemm_and_or_generic<S: Simd>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut u64,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const u64,
+    lhs_rs: isize,
+    rhs: *const u64,
+    rhs_cs: isize,
+    parallelism: Parallelism,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut u64);
+    let rhs = Ptr(rhs as *mut u64);
+
+    if k == 0 {
+        if !read_dst {
+            for i in 0..m {
+                for col in (0..n).step_by(NC) {
+                    let cols = NC.min(n - col);
+                    let mask = if cols == 64 { !0 } else { (1u64 << cols) - 1 };
+                    *dst.wrapping_offset(i as isize * dst_rs)
+                        .wrapping_add(col / 64)
+                        .0 &= !mask;
+                }
+            }
+        }
+        return;
+    }
+
+    for_each_block::<S>(
+        m,
+        n,
+        k,
+        parallelism,
+        #[inline(always)]
+        move |row, col, depth_word, n_words, last_mask, first| {
+            let cols = NC.min(n - col);
+            let valid = if cols == 64 { !0 } else { (1u64 << cols) - 1 };
+
+            for i in row..m.min(row + MR) {
+                let dst = dst
+                    .wrapping_offset(i as isize * dst_rs)
+                    .wrapping_add(col / 64)
+                    .0;
+                let lhs = core::slice::from_raw_parts(
+                    lhs.wrapping_offset(i as isize * lhs_rs)
+                        .wrapping_add(depth_word)
+                        .0,
+                    n_words,
+                );
+
+                let mut word = if first && !read_dst {
+                    *dst & !valid
+                } else {
+                    *dst
+                };
+                for j in 0..cols {
+                    // entries that are already set can't change
+                    if (word >> j) & 1 == 1 {
+                        continue;
+                    }
+                    let rhs = core::slice::from_raw_parts(
+                        rhs.wrapping_offset((col + j) as isize * rhs_cs)
+                            .wrapping_add(depth_word)
+                            .0,
+                        n_words,
+                    );
+                    word |= (and_any(lhs, rhs, last_mask) as u64) << j;
+                }
+                *dst = word;
+            }
+        },
+    );
+}
+
+#[inline(always)]
+unsafe fn gemm_xnor_popcount_generic<S: Simd>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut u32,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const u64,
+    lhs_rs: isize,
+    rhs: *const u64,
+    rhs_cs: isize,
+    parallelism: Parallelism,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut u64);
+    let rhs = Ptr(rhs as *mut u64);
+
+    if k == 0 {
+        if !read_dst {
+            for j in 0..n {
+                for i in 0..m {
+                    *dst.wrapping_offset(i as isize * dst_rs + j as isize * dst_cs)
+                        .0 = 0;
+                }
+            }
+        }
+        return;
+    }
+
+    for_each_block::<S>(
+        m,
+        n,
+        k,
+        parallelism,
+        #[inline(always)]
+        move |row, col, depth_word, n_words, last_mask, first| {
+            for j in col..n.min(col + NC) {
+                let rhs = core::slice::from_raw_parts(
+                    rhs.wrapping_offset(j as isize * rhs_cs)
+                        .wrapping_add(depth_word)
+                        .0,
+                    n_words,
+                );
+                for i in row..m.min(row + MR) {
+                    let lhs = core::slice::from_raw_parts(
+                        lhs.wrapping_offset(i as isize * lhs_rs)
+                            .wrapping_add(depth_word)
+                            .0,
+                        n_words,
+                    );
+                    let dst = dst
+                        .wrapping_offset(i as isize * dst_rs + j as isize * dst_cs)
+                        .0;
+                    let count = xnor_popcount(lhs, rhs, last_mask);
+                    *dst = if first && !read_dst {
+                        count
+                    } else {
+                        *dst + count
+                    };
+                }
+            }
+        },
+    );
+}
+
+/// Boolean matrix product: dst := dst OR (lhs × rhs) if `read_dst`, otherwise
+/// dst := lhs × rhs, where `(lhs × rhs)[i, j]` is the OR over `p` of `lhs[i, p] AND rhs[p, j]`.
+///
+/// `dst` is bit-packed by rows like `lhs`: entry `(i, j)` is bit `j % 64` of word
+/// `i * dst_rs + j / 64`. The bits past `n` in the last word of each row are left unchanged.
+pub unsafe fn gemm_and_or(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut u64,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const u64,
+    lhs_rs: isize,
+    rhs: *const u64,
+    rhs_cs: isize,
+    parallelism: Parallelism,
+) {
+    dispatch!(
+        gemm_and_or_generic,
+        m,
+        n,
+        k,
+        dst,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        parallelism,
+    )
+}
+
+/// XNOR-popcount product: dst := dst + count if `read_dst`, otherwise dst := count, where
+/// `count[i, j]` is the number of indices `p` for which `lhs[i, p] == rhs[p, j]`.
+///
+/// If the bits encode ±1 values, the dot product of row `i` and column `j` is
+/// `2 * count[i, j] - k`.
+pub unsafe fn gemm_xnor_popcount(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut u32,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const u64,
+    lhs_rs: isize,
+    rhs: *const u64,
+    rhs_cs: isize,
+    parallelism: Parallelism,
+) {
+    dispatch!(
+        gemm_xnor_popcount_generic,
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        parallelism,
+    )
+}
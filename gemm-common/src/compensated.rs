@@ -0,0 +1,154 @@
+use crate::{
+    cache::div_ceil,
+    gemm::CACHELINE_ALIGN,
+    microkernel::CompensatedMicroKernelFn,
+    pack_operands::{pack_lhs, pack_rhs},
+    simd::Simd,
+    tiled::{gemm_tiled, tile_params, Tile},
+    Parallelism, Ptr,
+};
+use dyn_stack::{DynStack, GlobalMemBuffer, StackReq};
+use num_traits::{One, Zero};
+
+// the sum of each dot product is kept as an unevaluated pair `hi + lo` in a scratch buffer
+// covering the current column block, so that consecutive `kc` blocks continue the same
+// compensated sum. `dst` is only read and written once, after the last `kc` block.
+//
+// the microkernel always computes the full tile, so the operands are packed with `N` equal to
+// the panel width, which zero-pads partial panels
+#[inline(always)]
+pub unsafe fn gemm_compensated_generic<
+    S: Simd,
+    T: Copy
+        + Zero
+        + One
+        + Send
+        + Sync
+        + core::ops::Add<Output = T>
+        + core::ops::Mul<Output = T>
+        + core::cmp::PartialEq,
+    const N: usize,
+    const MR: usize,
+    const NR: usize,
+>(
+    simd: S,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    mut alpha: T,
+    beta: T,
+    ukr: CompensatedMicroKernelFn<T>,
+    parallelism: Parallelism,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+    if !read_dst {
+        alpha.set_zero();
+    }
+
+    if k == 0 {
+        for j in 0..n {
+            for i in 0..m {
+                let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+                *dst = if alpha.is_zero() {
+                    T::zero()
+                } else {
+                    alpha * *dst
+                };
+            }
+        }
+        return;
+    }
+
+    let params = tile_params::<T, MR, NR>(m, n, k, parallelism);
+    let nc = params.nc;
+
+    let simd_align = CACHELINE_ALIGN;
+
+    let acc_stride = 2 * MR * NR;
+    let n_row_tiles = div_ceil(m, MR);
+
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut T);
+    let rhs = Ptr(rhs as *mut T);
+
+    let mut mem = GlobalMemBuffer::new(StackReq::new_aligned::<T>(
+        acc_stride * n_row_tiles * (nc / NR),
+        simd_align,
+    ));
+    let stack = DynStack::new(&mut mem);
+    let (mut acc_storage, _) =
+        stack.make_aligned_uninit::<T>(acc_stride * n_row_tiles * (nc / NR), simd_align);
+    let acc = Ptr(acc_storage.as_mut_ptr() as *mut T);
+
+    gemm_tiled::<T, MR, NR>(
+        m,
+        n,
+        k,
+        params,
+        parallelism,
+        |packed_lhs, stride, row, m_chunk, depth, k_chunk| {
+            pack_lhs::<T, MR, MR, _>(
+                simd,
+                m_chunk,
+                k_chunk,
+                packed_lhs,
+                lhs.wrapping_offset(row as isize * lhs_rs + depth as isize * lhs_cs),
+                lhs_cs,
+                lhs_rs,
+                stride,
+            )
+        },
+        Some(|packed_rhs, stride, col, n_chunk, depth, k_chunk| {
+            pack_rhs::<T, NR, NR, _>(
+                simd,
+                n_chunk,
+                k_chunk,
+                packed_rhs,
+                rhs.wrapping_offset(depth as isize * rhs_rs + col as isize * rhs_cs),
+                rhs_cs,
+                rhs_rs,
+                stride,
+            )
+        }),
+        |tile: Tile, packed_lhs, packed_rhs| {
+            let acc =
+                acc.wrapping_add(acc_stride * ((tile.col % nc) / NR * n_row_tiles + tile.row / MR));
+
+            if tile.depth == 0 {
+                for idx in 0..acc_stride {
+                    *acc.0.add(idx) = T::zero();
+                }
+            }
+            ukr(tile.k, acc.0, packed_lhs, packed_rhs);
+
+            if tile.depth + tile.k == k {
+                let dst =
+                    dst.wrapping_offset(tile.row as isize * dst_rs + tile.col as isize * dst_cs);
+                for jj in 0..tile.n {
+                    for ii in 0..tile.m {
+                        let hi = *acc.0.add(jj * MR + ii);
+                        let lo = *acc.0.add(MR * NR + jj * MR + ii);
+                        let dst = dst.0.offset(ii as isize * dst_rs + jj as isize * dst_cs);
+                        *dst = if alpha.is_zero() {
+                            beta * (hi + lo)
+                        } else {
+                            alpha * *dst + beta * (hi + lo)
+                        };
+                    }
+                }
+            }
+        },
+    );
+}
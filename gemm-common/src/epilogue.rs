@@ -0,0 +1,555 @@
+use crate::math::{exp_f32, exp_f64};
+
+/// Element-wise function applied to the result of a matrix multiplication
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Activation {
+    Identity,
+    /// max(x, 0)
+    Relu,
+    /// tanh approximation of GELU: `x/2 × (1 + tanh(sqrt(2/π) × (x + 0.044715 x³)))`
+    Gelu,
+    /// x × sigmoid(x)
+    Silu,
+}
+
+/// Bias vector, given as a pointer and a stride between consecutive elements
+#[derive(Debug)]
+pub enum Bias<T> {
+    None,
+    /// one value per row of `dst`
+    PerRow(*const T, isize),
+    /// one value per column of `dst`
+    PerCol(*const T, isize),
+    /// sum of a value per row and a value per column of `dst`, in that order
+    PerRowAndCol(*const T, isize, *const T, isize),
+}
+
+/// Reductions over each row of the final values of `dst`. Each output that is not null points to
+/// `m` contiguous values
+#[derive(Debug)]
+pub struct Reductions<T> {
+    pub max: *mut T,
+    /// smallest column index at which the maximum is reached
+    pub argmax: *mut usize,
+    pub sum: *mut T,
+    /// sum of squares
+    pub sum_sq: *mut T,
+}
+
+/// Position of `dst` in the matrix whose rows are reduced, when `dst` is a transposed or reversed
+/// view of it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReductionView {
+    /// number of rows of `dst`, if they are in reverse order
+    pub reversed_rows: Option<usize>,
+    /// number of columns of `dst`, if they are in reverse order
+    pub reversed_cols: Option<usize>,
+    /// the rows of the reduced matrix are the columns of `dst`
+    pub transposed: bool,
+}
+
+/// Triangle of a matrix
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Triangle {
+    Lower,
+    Upper,
+}
+
+/// Mask of the entries of `dst` outside of a triangle, which are set to `fill` instead of being
+/// computed, or left untouched if it is `None`, and are not included in the row reductions.
+///
+/// The lower triangle keeps the entries with `j <= i + diag_offset`, and the upper triangle the
+/// entries with `j >= i + diag_offset`
+#[derive(Copy, Clone, Debug)]
+pub struct TriangularMask<T> {
+    pub triangle: Triangle,
+    pub diag_offset: isize,
+    pub fill: Option<T>,
+}
+
+/// Running reduction of a row of `dst`
+#[derive(Copy, Clone, Debug)]
+pub struct RowReduction<T> {
+    pub max: T,
+    /// `usize::MAX` if no value has been seen yet
+    pub argmax: usize,
+    pub sum: T,
+    pub sum_sq: T,
+}
+
+/// Operations fused into the store of the product to `dst`.
+///
+/// The product is first scaled element-wise, `dst := alpha×dst + beta×row_scale[i]×col_scale[j]×acc`,
+/// then once it is fully accumulated, `dst[i, j] := activation(dst[i, j] + bias[i or j])`, and the
+/// rows of the result are reduced into `reductions`
+#[derive(Debug)]
+pub struct Epilogue<T> {
+    /// one scaling factor per row of `dst`, given as a pointer and a stride
+    pub row_scale: Option<(*const T, isize)>,
+    /// one scaling factor per column of `dst`, given as a pointer and a stride
+    pub col_scale: Option<(*const T, isize)>,
+    pub bias: Bias<T>,
+    pub activation: Activation,
+    pub reductions: Option<Reductions<T>>,
+    /// set by [`Epilogue::transpose`], [`Epilogue::reverse_rows`] and [`Epilogue::reverse_cols`],
+    /// so that the reductions still apply to the rows of the original `dst`
+    pub reductions_view: ReductionView,
+}
+
+impl<T> Clone for Bias<T> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Bias<T> {}
+
+impl<T: Copy> TriangularMask<T> {
+    #[inline(always)]
+    pub fn keeps(&self, row: usize, col: usize) -> bool {
+        let diff = col as isize - row as isize;
+        match self.triangle {
+            Triangle::Lower => diff <= self.diag_offset,
+            Triangle::Upper => diff >= self.diag_offset,
+        }
+    }
+
+    /// Whether any entry of the `nrows×ncols` block starting at `(row, col)` is kept
+    #[inline(always)]
+    pub fn keeps_any(&self, row: usize, col: usize, nrows: usize, ncols: usize) -> bool {
+        match self.triangle {
+            Triangle::Lower => self.keeps(row + nrows - 1, col),
+            Triangle::Upper => self.keeps(row, col + ncols - 1),
+        }
+    }
+
+    /// Whether all the entries of the `nrows×ncols` block starting at `(row, col)` are kept
+    #[inline(always)]
+    pub fn keeps_all(&self, row: usize, col: usize, nrows: usize, ncols: usize) -> bool {
+        match self.triangle {
+            Triangle::Lower => self.keeps(row, col + ncols - 1),
+            Triangle::Upper => self.keeps(row + nrows - 1, col),
+        }
+    }
+
+    /// Rows of column `col` of an `m×_` matrix that are masked, which are contiguous
+    #[inline]
+    pub fn masked_rows(&self, col: usize, m: usize) -> core::ops::Range<usize> {
+        let first_kept = col as isize - self.diag_offset;
+        match self.triangle {
+            Triangle::Lower => 0..(first_kept.clamp(0, m as isize) as usize),
+            Triangle::Upper => ((first_kept + 1).clamp(0, m as isize) as usize)..m,
+        }
+    }
+}
+
+impl<T> Reductions<T> {
+    #[inline]
+    pub unsafe fn write(&self, row: usize, reduction: &RowReduction<T>)
+    where
+        T: Copy,
+    {
+        if !self.max.is_null() {
+            *self.max.add(row) = reduction.max;
+        }
+        if !self.argmax.is_null() {
+            *self.argmax.add(row) = reduction.argmax;
+        }
+        if !self.sum.is_null() {
+            *self.sum.add(row) = reduction.sum;
+        }
+        if !self.sum_sq.is_null() {
+            *self.sum_sq.add(row) = reduction.sum_sq;
+        }
+    }
+}
+
+impl ReductionView {
+    /// Position in the reduced matrix of the entry `(row, col)` of `dst`
+    #[inline(always)]
+    pub fn map(&self, row: usize, col: usize) -> (usize, usize) {
+        let row = self.reversed_rows.map_or(row, |m| m - 1 - row);
+        let col = self.reversed_cols.map_or(col, |n| n - 1 - col);
+        if self.transposed {
+            (col, row)
+        } else {
+            (row, col)
+        }
+    }
+
+    /// Number of rows of the reduced matrix, if `dst` is `m×n`
+    #[inline(always)]
+    pub fn nrows(&self, m: usize, n: usize) -> usize {
+        if self.transposed {
+            n
+        } else {
+            m
+        }
+    }
+}
+
+impl<T: num_traits::Zero> Default for RowReduction<T> {
+    /// Reduction of an empty row
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max: T::zero(),
+            argmax: usize::MAX,
+            sum: T::zero(),
+            sum_sq: T::zero(),
+        }
+    }
+}
+
+impl<T: Copy + core::ops::Mul<Output = T>> RowReduction<T> {
+    /// Reduction of the single value at column `col`
+    #[inline(always)]
+    pub fn single(value: T, col: usize) -> Self {
+        Self {
+            max: value,
+            argmax: col,
+            sum: value,
+            sum_sq: value * value,
+        }
+    }
+}
+
+impl<T> Clone for Reductions<T> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Reductions<T> {}
+
+impl<T> Clone for Epilogue<T> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for Epilogue<T> {}
+
+unsafe impl<T: Sync> Send for Epilogue<T> {}
+unsafe impl<T: Sync> Sync for Epilogue<T> {}
+
+impl<T> Epilogue<T> {
+    #[inline]
+    pub const fn identity() -> Self {
+        Self {
+            row_scale: None,
+            col_scale: None,
+            bias: Bias::None,
+            activation: Activation::Identity,
+            reductions: None,
+            reductions_view: ReductionView {
+                reversed_rows: None,
+                reversed_cols: None,
+                transposed: false,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn is_identity(&self) -> bool {
+        self.row_scale.is_none()
+            && self.col_scale.is_none()
+            && matches!(self.bias, Bias::None)
+            && self.activation == Activation::Identity
+            && self.reductions.is_none()
+    }
+
+    /// Whether the epilogue has no part that is applied to the full product, apart from the
+    /// reductions
+    #[inline]
+    pub fn is_scale_only(&self) -> bool {
+        matches!(self.bias, Bias::None) && self.activation == Activation::Identity
+    }
+
+    /// Only the scaling part of the epilogue, which applies to every partial product
+    #[inline(always)]
+    pub fn scale_only(&self) -> Self {
+        Self {
+            row_scale: self.row_scale,
+            col_scale: self.col_scale,
+            ..Self::identity()
+        }
+    }
+
+    /// The part of the epilogue that is applied to each tile of `dst`
+    #[inline(always)]
+    pub fn without_reductions(&self) -> Self {
+        Self {
+            reductions: None,
+            ..*self
+        }
+    }
+
+    /// Epilogue of the submatrix of `dst` starting at `(row, col)`
+    #[inline(always)]
+    pub fn offset(&self, row: usize, col: usize) -> Self {
+        Self {
+            row_scale: self
+                .row_scale
+                .map(|(ptr, stride)| (ptr.wrapping_offset(row as isize * stride), stride)),
+            col_scale: self
+                .col_scale
+                .map(|(ptr, stride)| (ptr.wrapping_offset(col as isize * stride), stride)),
+            bias: match self.bias {
+                Bias::None => Bias::None,
+                Bias::PerRow(ptr, stride) => {
+                    Bias::PerRow(ptr.wrapping_offset(row as isize * stride), stride)
+                }
+                Bias::PerCol(ptr, stride) => {
+                    Bias::PerCol(ptr.wrapping_offset(col as isize * stride), stride)
+                }
+                Bias::PerRowAndCol(row_ptr, row_stride, col_ptr, col_stride) => Bias::PerRowAndCol(
+                    row_ptr.wrapping_offset(row as isize * row_stride),
+                    row_stride,
+                    col_ptr.wrapping_offset(col as isize * col_stride),
+                    col_stride,
+                ),
+            },
+            activation: self.activation,
+            reductions: self.reductions,
+            reductions_view: self.reductions_view,
+        }
+    }
+
+    /// Epilogue of `dst` transposed
+    #[inline]
+    pub fn transpose(&self) -> Self {
+        Self {
+            row_scale: self.col_scale,
+            col_scale: self.row_scale,
+            bias: match self.bias {
+                Bias::None => Bias::None,
+                Bias::PerRow(ptr, stride) => Bias::PerCol(ptr, stride),
+                Bias::PerCol(ptr, stride) => Bias::PerRow(ptr, stride),
+                Bias::PerRowAndCol(row_ptr, row_stride, col_ptr, col_stride) => {
+                    Bias::PerRowAndCol(col_ptr, col_stride, row_ptr, row_stride)
+                }
+            },
+            activation: self.activation,
+            reductions: self.reductions,
+            reductions_view: ReductionView {
+                reversed_rows: self.reductions_view.reversed_cols,
+                reversed_cols: self.reductions_view.reversed_rows,
+                transposed: !self.reductions_view.transposed,
+            },
+        }
+    }
+
+    /// Epilogue of `dst` with the order of its rows reversed
+    #[inline]
+    pub fn reverse_rows(&self, m: usize) -> Self {
+        if m == 0 {
+            return *self;
+        }
+        let last = (m - 1) as isize;
+        Self {
+            row_scale: self
+                .row_scale
+                .map(|(ptr, stride)| (ptr.wrapping_offset(last * stride), -stride)),
+            col_scale: self.col_scale,
+            bias: match self.bias {
+                Bias::PerRow(ptr, stride) => {
+                    Bias::PerRow(ptr.wrapping_offset(last * stride), -stride)
+                }
+                Bias::PerRowAndCol(row_ptr, row_stride, col_ptr, col_stride) => Bias::PerRowAndCol(
+                    row_ptr.wrapping_offset(last * row_stride),
+                    -row_stride,
+                    col_ptr,
+                    col_stride,
+                ),
+                bias => bias,
+            },
+            activation: self.activation,
+            reductions: self.reductions,
+            reductions_view: ReductionView {
+                reversed_rows: match self.reductions_view.reversed_rows {
+                    Some(_) => None,
+                    None => Some(m),
+                },
+                ..self.reductions_view
+            },
+        }
+    }
+
+    /// Epilogue of `dst` with the order of its columns reversed
+    #[inline]
+    pub fn reverse_cols(&self, n: usize) -> Self {
+        self.transpose().reverse_rows(n).transpose()
+    }
+}
+
+pub trait EpilogueScalar:
+    Copy + PartialOrd + core::ops::Add<Output = Self> + core::ops::Mul<Output = Self> + 'static
+{
+    fn relu(self) -> Self;
+    fn gelu(self) -> Self;
+    fn silu(self) -> Self;
+}
+
+macro_rules! impl_epilogue_scalar {
+    ($ty: ident, $exp: ident) => {
+        impl EpilogueScalar for $ty {
+            #[inline(always)]
+            fn relu(self) -> Self {
+                self.max(0.0)
+            }
+
+            #[inline(always)]
+            fn gelu(self) -> Self {
+                // x/2 × (1 + tanh(y)) = x × sigmoid(2y)
+                let sqrt_2_over_pi = (2.0 * core::$ty::consts::FRAC_1_PI).sqrt();
+                let y = sqrt_2_over_pi * (self + 0.044715 * self * self * self);
+                self / (1.0 + $exp(-2.0 * y))
+            }
+
+            #[inline(always)]
+            fn silu(self) -> Self {
+                self / (1.0 + $exp(-self))
+            }
+        }
+    };
+}
+
+impl_epilogue_scalar!(f32, exp_f32);
+impl_epilogue_scalar!(f64, exp_f64);
+
+// the `len` values of a per-row vector starting at `row`, followed by copies of the first one
+#[inline(always)]
+unsafe fn load_rows<T: Copy, const N: usize>(
+    ptr: *const T,
+    stride: isize,
+    row: usize,
+    len: usize,
+) -> [T; N] {
+    if stride == 1 && len == N {
+        (ptr.add(row) as *const [T; N]).read_unaligned()
+    } else {
+        let mut values = [*ptr.offset(row as isize * stride); N];
+        for (i, value) in values.iter_mut().enumerate().take(len).skip(1) {
+            *value = *ptr.offset((row + i) as isize * stride);
+        }
+        values
+    }
+}
+
+impl<T: EpilogueScalar> Epilogue<T> {
+    /// Scales a column of the product, whose first `len` elements start at `(row, col)`. The
+    /// other `N - len` elements are left unspecified
+    #[inline(always)]
+    pub unsafe fn scale<const N: usize>(
+        &self,
+        mut values: [T; N],
+        row: usize,
+        col: usize,
+        len: usize,
+    ) -> [T; N] {
+        if let Some((ptr, stride)) = self.row_scale {
+            let scale = load_rows::<T, N>(ptr, stride, row, len);
+            for (value, scale) in values.iter_mut().zip(scale) {
+                *value = *value * scale;
+            }
+        }
+        if let Some((ptr, stride)) = self.col_scale {
+            let scale = *ptr.offset(col as isize * stride);
+            for value in values.iter_mut() {
+                *value = *value * scale;
+            }
+        }
+        values
+    }
+
+    /// Applies the epilogue to a column of `dst`, whose first `len` elements start at
+    /// `(row, col)`. The other `N - len` elements are left unspecified
+    #[inline(always)]
+    pub unsafe fn apply<const N: usize>(
+        &self,
+        mut values: [T; N],
+        row: usize,
+        col: usize,
+        len: usize,
+    ) -> [T; N] {
+        let (row_bias, col_bias) = match self.bias {
+            Bias::None => (None, None),
+            Bias::PerRow(ptr, stride) => (Some((ptr, stride)), None),
+            Bias::PerCol(ptr, stride) => (None, Some((ptr, stride))),
+            Bias::PerRowAndCol(row_ptr, row_stride, col_ptr, col_stride) => {
+                (Some((row_ptr, row_stride)), Some((col_ptr, col_stride)))
+            }
+        };
+        if let Some((ptr, stride)) = row_bias {
+            let bias = load_rows::<T, N>(ptr, stride, row, len);
+            for (value, bias) in values.iter_mut().zip(bias) {
+                *value = *value + bias;
+            }
+        }
+        if let Some((ptr, stride)) = col_bias {
+            let bias = *ptr.offset(col as isize * stride);
+            for value in values.iter_mut() {
+                *value = *value + bias;
+            }
+        }
+
+        // full-width loops over branch-free functions, so that they are vectorized
+        match self.activation {
+            Activation::Identity => {}
+            Activation::Relu => {
+                for value in values.iter_mut() {
+                    *value = value.relu();
+                }
+            }
+            Activation::Gelu => {
+                for value in values.iter_mut() {
+                    *value = value.gelu();
+                }
+            }
+            Activation::Silu => {
+                for value in values.iter_mut() {
+                    *value = value.silu();
+                }
+            }
+        }
+        values
+    }
+
+    /// A column of `dst`, whose first `len` elements start at `(row, col)`, once the partial
+    /// `products` are scaled and added to `values`, which are alpha×dst. The other `N - len`
+    /// elements are left unspecified
+    #[inline(always)]
+    pub unsafe fn store<const N: usize>(
+        &self,
+        products: [T; N],
+        mut values: [T; N],
+        row: usize,
+        col: usize,
+        len: usize,
+    ) -> [T; N] {
+        let products = self.scale(products, row, col, len);
+        for (value, product) in values.iter_mut().zip(products) {
+            *value = *value + product;
+        }
+        self.apply(values, row, col, len)
+    }
+}
+
+impl<T: EpilogueScalar> RowReduction<T> {
+    /// Merges the reduction of other columns of the same row. The result doesn't depend on the
+    /// order in which the columns are merged, except for the rounding of the sums
+    #[inline(always)]
+    pub fn merge(&mut self, other: &Self) {
+        if other.argmax != usize::MAX
+            && (self.argmax == usize::MAX
+                || other.max > self.max
+                || (other.max == self.max && other.argmax < self.argmax))
+        {
+            self.max = other.max;
+            self.argmax = other.argmax;
+        }
+        self.sum = self.sum + other.sum;
+        self.sum_sq = self.sum_sq + other.sum_sq;
+    }
+}
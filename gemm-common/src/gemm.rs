@@ -1,9 +1,15 @@
 use crate::{
     cache::{div_ceil, kernel_params, KernelParams, CACHE_INFO},
+    epilogue::{
+        Activation, Bias, Epilogue, EpilogueScalar, ReductionView, RowReduction, Triangle,
+        TriangularMask,
+    },
     gemv, gevv,
     microkernel::MicroKernelFn,
-    pack_operands::{pack_lhs, pack_rhs},
+    pack_operands::{pack_lhs, pack_lhs_planar, pack_lhs_symmetric, pack_rhs, pack_rhs_planar},
     simd::Simd,
+    sparse::BlockMask,
+    tiled::{job_range, n_threads},
     Parallelism, Ptr,
 };
 use core::cell::RefCell;
@@ -63,6 +69,8 @@ thread_local! {
 
 pub trait Conj: Copy {
     fn conj(self) -> Self;
+    /// The value with its imaginary part set to zero
+    fn real(self) -> Self;
 }
 
 impl Conj for f32 {
@@ -70,12 +78,20 @@ impl Conj for f32 {
     fn conj(self) -> Self {
         self
     }
+    #[inline(always)]
+    fn real(self) -> Self {
+        self
+    }
 }
 impl Conj for f64 {
     #[inline(always)]
     fn conj(self) -> Self {
         self
     }
+    #[inline(always)]
+    fn real(self) -> Self {
+        self
+    }
 }
 
 impl Conj for c32 {
@@ -86,6 +102,13 @@ impl Conj for c32 {
             im: -self.im,
         }
     }
+    #[inline(always)]
+    fn real(self) -> Self {
+        c32 {
+            re: self.re,
+            im: 0.0,
+        }
+    }
 }
 impl Conj for c64 {
     #[inline(always)]
@@ -95,12 +118,20 @@ impl Conj for c64 {
             im: -self.im,
         }
     }
+    #[inline(always)]
+    fn real(self) -> Self {
+        c64 {
+            re: self.re,
+            im: 0.0,
+        }
+    }
 }
 
 pub const DEFAULT_THREADING_THRESHOLD: usize = 48 * 48 * 256;
 pub const DEFAULT_RHS_PACKING_THRESHOLD: usize = 128;
 pub const DEFAULT_LHS_PACKING_THRESHOLD_SINGLE_THREAD: usize = 8;
 pub const DEFAULT_LHS_PACKING_THRESHOLD_MULTI_THREAD: usize = 16;
+pub const DEFAULT_CPLX_3M_THRESHOLD: usize = 256;
 
 static THREADING_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_THREADING_THRESHOLD);
 static RHS_PACKING_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_RHS_PACKING_THRESHOLD);
@@ -108,6 +139,7 @@ static LHS_PACKING_THRESHOLD_SINGLE_THREAD: AtomicUsize =
     AtomicUsize::new(DEFAULT_LHS_PACKING_THRESHOLD_SINGLE_THREAD);
 static LHS_PACKING_THRESHOLD_MULTI_THREAD: AtomicUsize =
     AtomicUsize::new(DEFAULT_LHS_PACKING_THRESHOLD_MULTI_THREAD);
+static CPLX_3M_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_CPLX_3M_THRESHOLD);
 
 #[inline]
 pub fn get_threading_threshold() -> usize {
@@ -145,6 +177,16 @@ pub fn set_lhs_packing_threshold_multi_thread(value: usize) {
     LHS_PACKING_THRESHOLD_MULTI_THREAD.store(value.min(256), Ordering::Relaxed);
 }
 
+/// Smallest dimension of the complex products that `gemm_3m` computes with three real products
+#[inline]
+pub fn get_cplx_3m_threshold() -> usize {
+    CPLX_3M_THRESHOLD.load(Ordering::Relaxed)
+}
+#[inline]
+pub fn set_cplx_3m_threshold(value: usize) {
+    CPLX_3M_THRESHOLD.store(value, Ordering::Relaxed);
+}
+
 #[inline(always)]
 pub fn par_for_each(n_threads: usize, func: impl Fn(usize) + Send + Sync) {
     rayon::scope(|s| {
@@ -155,6 +197,368 @@ pub fn par_for_each(n_threads: usize, func: impl Fn(usize) + Send + Sync) {
     })
 }
 
+/// One of the products computed by [`gemm_multi_generic`]: dst := alpha×dst + beta×lhs×rhs,
+/// where `dst` is `m×n` and `rhs` is `k×n`
+#[derive(Copy, Clone, Debug)]
+pub struct GemmOutput<T> {
+    pub n: usize,
+    pub dst: *mut T,
+    pub dst_cs: isize,
+    pub dst_rs: isize,
+    pub read_dst: bool,
+    pub rhs: *const T,
+    pub rhs_cs: isize,
+    pub rhs_rs: isize,
+    pub alpha: T,
+    pub beta: T,
+    pub epilogue: Epilogue<T>,
+    pub mask: Option<TriangularMask<T>>,
+    /// the entries of `dst` outside of the active blocks are set to zero instead of being
+    /// computed, and are not included in the reductions
+    pub dst_blocks: Option<BlockMask>,
+}
+
+unsafe impl<T: Sync> Send for GemmOutput<T> {}
+unsafe impl<T: Sync> Sync for GemmOutput<T> {}
+
+/// `lhs` of [`gemm_multi_generic`] that is symmetric, or Hermitian if `conj`, and of which only
+/// the `triangle` is read
+#[derive(Copy, Clone, Debug)]
+pub struct SymmetricLhs {
+    pub triangle: Triangle,
+    pub conj: bool,
+}
+
+/// Operands and outputs of [`gemm_multi_generic`] that are not stored as matrices of `T`.
+///
+/// The operands are converted while they are packed, and each tile of an output is converted
+/// once it is fully accumulated, by the thread that computed it
+pub trait GemmHook<T>: Sync {
+    /// Packs the `m×k` block of `lhs` starting at `(row, depth)` in zero-padded panels of `MR`
+    /// rows, laid out like [`pack_lhs`]. Only called if `lhs` is null
+    unsafe fn pack_lhs<const MR: usize>(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut T,
+        row: usize,
+        depth: usize,
+        dst_stride: usize,
+    );
+
+    /// Packs the `k×n` block of the `rhs` of `outputs[output]` starting at `(depth, col)` in
+    /// zero-padded panels of `NR` columns, laid out like [`pack_rhs`]. Only called if that `rhs`
+    /// is null
+    unsafe fn pack_rhs<const NR: usize>(
+        &self,
+        output: usize,
+        n: usize,
+        k: usize,
+        dst: *mut T,
+        depth: usize,
+        col: usize,
+        dst_stride: usize,
+    );
+
+    /// Stores the `m×n` tile of `outputs[output]` starting at `(row, col)`, whose product is in
+    /// `src`. Only called if the `dst` of that output is null
+    unsafe fn store(
+        &self,
+        output: usize,
+        m: usize,
+        n: usize,
+        row: usize,
+        col: usize,
+        src: *const T,
+        src_cs: isize,
+        src_rs: isize,
+    );
+}
+
+/// `apply_epilogue` of the kernels with no epilogue support: each column of the tile is only the
+/// partial products added to alpha×dst
+#[inline(always)]
+pub fn add_products<T: Copy + core::ops::Add<Output = T>, const MR: usize>(
+    _: &Epilogue<T>,
+    products: [T; MR],
+    mut values: [T; MR],
+    _: usize,
+    _: usize,
+    _: usize,
+) -> [T; MR] {
+    for (value, product) in values.iter_mut().zip(products) {
+        *value = *value + product;
+    }
+    values
+}
+
+/// [`GemmHook`] of the products whose operands are stored as matrices of `T`, and whose outputs
+/// that have no `dst` are only reduced
+pub struct NoHook;
+
+impl<T> GemmHook<T> for NoHook {
+    unsafe fn pack_lhs<const MR: usize>(
+        &self,
+        _: usize,
+        _: usize,
+        _: *mut T,
+        _: usize,
+        _: usize,
+        _: usize,
+    ) {
+        unreachable!()
+    }
+
+    unsafe fn pack_rhs<const NR: usize>(
+        &self,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: *mut T,
+        _: usize,
+        _: usize,
+        _: usize,
+    ) {
+        unreachable!()
+    }
+
+    #[inline(always)]
+    unsafe fn store(
+        &self,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: *const T,
+        _: isize,
+        _: isize,
+    ) {
+    }
+}
+
+// state of an output over the current column block
+#[derive(Copy, Clone)]
+struct OutputBlock<T> {
+    index: usize,
+    n_chunk: usize,
+    n_col_mini_chunks: usize,
+    dst: Ptr<T>,
+    dst_cs: isize,
+    dst_rs: isize,
+    rhs: Ptr<T>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    do_pack_rhs: bool,
+    packed_rhs: Ptr<T>,
+    // the tiles are computed in the scratch buffer, and stored by the hook
+    to_scratch: bool,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    epilogue: Option<Epilogue<T>>,
+    mask: Option<TriangularMask<T>>,
+    dst_blocks: Option<BlockMask>,
+    row_reductions: Ptr<RowReduction<T>>,
+    reductions_view: ReductionView,
+    // number of reduced rows, which is also the stride between the slots of the threads
+    n_reduced_rows: usize,
+}
+
+// whether the `nrows×ncols` tile starting at `(row, col)` is fully masked
+#[inline(always)]
+unsafe fn is_masked_tile<T: Copy>(
+    mask: Option<TriangularMask<T>>,
+    dst_blocks: Option<BlockMask>,
+    row: usize,
+    col: usize,
+    nrows: usize,
+    ncols: usize,
+) -> bool {
+    mask.is_some_and(|mask| !mask.keeps_any(row, col, nrows, ncols))
+        || dst_blocks.is_some_and(|blocks| !blocks.any_active(row, col, nrows, ncols))
+}
+
+// sets the entries of the `nrows×ncols` tile starting at `(row, col)` that `mask` drops to its
+// fill value, if it has one, and the other ones that are outside the active blocks of
+// `dst_blocks` to zero
+#[inline(always)]
+unsafe fn fill_masked_tile<T: Copy + Zero>(
+    mask: Option<TriangularMask<T>>,
+    dst_blocks: Option<BlockMask>,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    row: usize,
+    col: usize,
+    nrows: usize,
+    ncols: usize,
+) {
+    if let Some(blocks) = dst_blocks {
+        for block_col in col / blocks.block_cols..div_ceil(col + ncols, blocks.block_cols) {
+            let cols = (block_col * blocks.block_cols).max(col)
+                ..((block_col + 1) * blocks.block_cols).min(col + ncols);
+            for block_row in row / blocks.block_rows..div_ceil(row + nrows, blocks.block_rows) {
+                if blocks.is_active(block_row, block_col) {
+                    continue;
+                }
+                let rows = (block_row * blocks.block_rows).max(row)
+                    ..((block_row + 1) * blocks.block_rows).min(row + nrows);
+                for j in cols.clone() {
+                    for i in rows.clone() {
+                        if mask.is_some_and(|mask| !mask.keeps(i, j)) {
+                            continue;
+                        }
+                        *dst.offset((i - row) as isize * dst_rs + (j - col) as isize * dst_cs) =
+                            T::zero();
+                    }
+                }
+            }
+        }
+    }
+    if let Some((mask, Some(fill))) = mask.map(|mask| (mask, mask.fill)) {
+        if mask.keeps_all(row, col, nrows, ncols) {
+            return;
+        }
+        for jj in 0..ncols {
+            let masked = mask.masked_rows(col + jj, row + nrows);
+            for ii in masked.start.max(row) - row..masked.end.max(row) - row {
+                *dst.offset(ii as isize * dst_rs + jj as isize * dst_cs) = fill;
+            }
+        }
+    }
+}
+
+// calls `f(depth_start, depth_end)` for each range of `depth..depth + k` on which the rows
+// `row..row + nrows` of `lhs` may be nonzero
+#[inline(always)]
+unsafe fn for_each_lhs_depth_range(
+    lhs_blocks: Option<BlockMask>,
+    row: usize,
+    nrows: usize,
+    depth: usize,
+    k: usize,
+    mut f: impl FnMut(usize, usize),
+) {
+    match lhs_blocks {
+        None => f(depth, depth + k),
+        Some(lhs_blocks) => lhs_blocks.for_each_active_col_range(row, depth, nrows, k, f),
+    }
+}
+
+// dst := alpha * conj?(dst), followed by the epilogue, with the masked entries set to the fill
+// value, if any
+#[inline(always)]
+unsafe fn store_without_product<
+    T: Copy + Zero + One + Conj + core::ops::Mul<Output = T> + core::cmp::PartialEq,
+    const MR: usize,
+>(
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    alpha: T,
+    conj_dst: bool,
+    epilogue: *const Epilogue<T>,
+    mask: Option<TriangularMask<T>>,
+    dst_blocks: Option<BlockMask>,
+    apply_epilogue: &impl Fn(&Epilogue<T>, [T; MR], [T; MR], usize, usize, usize) -> [T; MR],
+    merge_reduction: impl Fn(&mut RowReduction<T>, &RowReduction<T>),
+) {
+    if !epilogue.is_null() || mask.is_some() || dst_blocks.is_some() {
+        let identity = Epilogue::identity();
+        let epilogue = if epilogue.is_null() {
+            &identity
+        } else {
+            &*epilogue
+        };
+        let view = epilogue.reductions_view;
+        let mut rows = vec![RowReduction::default(); view.nrows(m, n)];
+        let is_kept = |i: usize, j: usize| {
+            !(mask.is_some_and(|mask| !mask.keeps(i, j))
+                || dst_blocks.is_some_and(|blocks| {
+                    !blocks.is_active(i / blocks.block_rows, j / blocks.block_cols)
+                }))
+        };
+        for j in 0..n {
+            let mut row = 0;
+            while row < m {
+                let len = MR.min(m - row);
+                let dst = dst.wrapping_offset(row as isize * dst_rs + j as isize * dst_cs);
+                let mut values = [T::zero(); MR];
+                if !alpha.is_zero() {
+                    for (ii, value) in values.iter_mut().enumerate().take(len) {
+                        if is_kept(row + ii, j) {
+                            let dst = *dst.offset(ii as isize * dst_rs);
+                            *value = alpha * if conj_dst { dst.conj() } else { dst };
+                        }
+                    }
+                }
+                let values = apply_epilogue(epilogue, [T::zero(); MR], values, row, j, len);
+                for (ii, &value) in values.iter().enumerate().take(len) {
+                    let i = row + ii;
+                    let dst = dst.wrapping_offset(ii as isize * dst_rs);
+                    if !is_kept(i, j) {
+                        // masked entries are filled, and entries of inactive blocks are zeroed
+                        let fill = match mask {
+                            Some(mask) if !mask.keeps(i, j) => mask.fill,
+                            _ => Some(T::zero()),
+                        };
+                        if let (Some(fill), false) = (fill, dst.is_null()) {
+                            *dst = fill;
+                        }
+                        continue;
+                    }
+                    if !dst.is_null() {
+                        *dst = value;
+                    }
+                    if epilogue.reductions.is_some() {
+                        let (row, col) = view.map(i, j);
+                        merge_reduction(&mut rows[row], &RowReduction::single(value, col));
+                    }
+                }
+                row += len;
+            }
+        }
+        if let Some(reductions) = epilogue.reductions {
+            for (i, row) in rows.iter().enumerate() {
+                reductions.write(i, row);
+            }
+        }
+        return;
+    }
+
+    if alpha.is_zero() {
+        for j in 0..n {
+            for i in 0..m {
+                *dst.offset(i as isize * dst_rs + j as isize * dst_cs) = T::zero();
+            }
+        }
+        return;
+    }
+
+    if alpha.is_one() && !conj_dst {
+        return;
+    }
+
+    if conj_dst {
+        for j in 0..n {
+            for i in 0..m {
+                let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+                *dst = alpha * (*dst).conj();
+            }
+        }
+    } else {
+        for j in 0..n {
+            for i in 0..m {
+                let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+                *dst = alpha * *dst;
+            }
+        }
+    }
+}
+
 #[inline(always)]
 pub unsafe fn gemm_basic_generic<
     S: Simd,
@@ -195,6 +599,12 @@ pub unsafe fn gemm_basic_generic<
     mul_add: impl Copy + Fn(T, T, T) -> T,
     dispatcher: &[[MicroKernelFn<T>; NR]; MR_DIV_N],
     parallelism: Parallelism,
+    epilogue: *const Epilogue<T>,
+    apply_epilogue: impl Copy
+        + Send
+        + Sync
+        + Fn(&Epilogue<T>, [T; MR], [T; MR], usize, usize, usize) -> [T; MR],
+    merge_reduction: impl Copy + Send + Sync + Fn(&mut RowReduction<T>, &RowReduction<T>),
 ) {
     if m == 0 || n == 0 {
         return;
@@ -204,40 +614,25 @@ pub unsafe fn gemm_basic_generic<
     }
 
     if k == 0 {
-        // dst = alpha * conj?(dst)
-
-        if alpha.is_zero() {
-            for j in 0..n {
-                for i in 0..m {
-                    *dst.offset(i as isize * dst_rs + j as isize * dst_cs) = T::zero();
-                }
-            }
-            return;
-        }
-
-        if alpha.is_one() && !conj_dst {
-            return;
-        }
-
-        if conj_dst {
-            for j in 0..n {
-                for i in 0..m {
-                    let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
-                    *dst = alpha * (*dst).conj();
-                }
-            }
-        } else {
-            for j in 0..n {
-                for i in 0..m {
-                    let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
-                    *dst = alpha * *dst;
-                }
-            }
-        }
+        store_without_product::<_, MR>(
+            m,
+            n,
+            dst,
+            dst_cs,
+            dst_rs,
+            alpha,
+            conj_dst,
+            epilogue,
+            None,
+            None,
+            &apply_epilogue,
+            merge_reduction,
+        );
         return;
     }
 
-    if !conj_dst && !conj_lhs && !conj_rhs {
+    // the epilogue is only applied by the tiled path
+    if !conj_dst && !conj_lhs && !conj_rhs && epilogue.is_null() {
         if k <= 2 {
             gevv::gevv(
                 simd, m, n, k, dst, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
@@ -261,6 +656,113 @@ pub unsafe fn gemm_basic_generic<
         }
     }
 
+    // plain products skip the per-call bookkeeping of the multi-output driver
+    if epilogue.is_null() {
+        gemm_basic_loop::<S, T, N, MR, NR, MR_DIV_N>(
+            simd,
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            dispatcher,
+            parallelism,
+        );
+        return;
+    }
+
+    gemm_multi_generic::<S, T, N, MR, NR, MR_DIV_N>(
+        simd,
+        m,
+        k,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        None,
+        None,
+        false,
+        &NoHook,
+        &[GemmOutput {
+            n,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            epilogue: if epilogue.is_null() {
+                Epilogue::identity()
+            } else {
+                *epilogue
+            },
+            mask: None,
+            dst_blocks: None,
+        }],
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        dispatcher,
+        parallelism,
+        apply_epilogue,
+        merge_reduction,
+    );
+}
+
+// the baseline tiled loop, for a single output with no hook, mask, blocks or epilogue
+#[inline(always)]
+unsafe fn gemm_basic_loop<
+    S: Simd,
+    T: Copy
+        + Zero
+        + One
+        + Conj
+        + Send
+        + Sync
+        + core::fmt::Debug
+        + core::ops::Add<Output = T>
+        + core::ops::Mul<Output = T>
+        + core::cmp::PartialEq,
+    const N: usize,
+    const MR: usize,
+    const NR: usize,
+    const MR_DIV_N: usize,
+>(
+    simd: S,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    dispatcher: &[[MicroKernelFn<T>; NR]; MR_DIV_N],
+    parallelism: Parallelism,
+) {
     let KernelParams { kc, mc, nc } = if m <= 64 && n <= 64 {
         // skip expensive kernel_params call for small sizes
         let kc = k.min(512);
@@ -611,65 +1113,2394 @@ pub unsafe fn gemm_basic_generic<
     }
 }
 
-#[macro_export]
-macro_rules! __inject_mod {
-    ($module: ident, $ty: ident, $N: expr, $simd: ident) => {
-        mod $module {
-            use super::*;
-            use crate::microkernel::$module::$ty::*;
-            const N: usize = $N;
+/// Computes the products of the same `lhs` with each of the `rhs` of `outputs`.
+///
+/// The columns of all the outputs are processed in the same macro-loop, so that each `kc×mc`
+/// block of `lhs` is only packed once for all of them. If `lhs_blocks` is given, the inactive
+/// blocks of `lhs` are assumed to be zero, and are neither packed nor multiplied.
+///
+/// A null `lhs` or `rhs` is packed by the `hook`, and the tiles of an output with a null `dst`
+/// are passed to the `hook` once they are fully accumulated.
+///
+/// If `rhs_is_lhs_transpose`, the `rhs` of each output is `lhsᵀ`, and is ignored: each depth
+/// block of `lhs` is packed once for all the threads, and the packed panels are read both as the
+/// lhs and the rhs of the microkernel.
+#[inline(always)]
+pub unsafe fn gemm_multi_generic<
+    S: Simd,
+    T: Copy
+        + Zero
+        + One
+        + Conj
+        + Send
+        + Sync
+        + core::fmt::Debug
+        + core::ops::Add<Output = T>
+        + core::ops::Mul<Output = T>
+        + core::cmp::PartialEq,
+    const N: usize,
+    const MR: usize,
+    const NR: usize,
+    const MR_DIV_N: usize,
+>(
+    simd: S,
+    m: usize,
+    k: usize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    lhs_blocks: Option<BlockMask>,
+    lhs_symmetric: Option<SymmetricLhs>,
+    rhs_is_lhs_transpose: bool,
+    hook: &impl GemmHook<T>,
+    outputs: &[GemmOutput<T>],
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    dispatcher: &[[MicroKernelFn<T>; NR]; MR_DIV_N],
+    parallelism: Parallelism,
+    apply_epilogue: impl Copy
+        + Send
+        + Sync
+        + Fn(&Epilogue<T>, [T; MR], [T; MR], usize, usize, usize) -> [T; MR],
+    merge_reduction: impl Copy + Send + Sync + Fn(&mut RowReduction<T>, &RowReduction<T>),
+) {
+    assert!(lhs_blocks.is_none() || lhs_symmetric.is_none());
+    assert!(!lhs.is_null() || lhs_symmetric.is_none());
+    assert!(
+        !rhs_is_lhs_transpose
+            || (!lhs.is_null() && lhs_blocks.is_none() && lhs_symmetric.is_none())
+    );
+    let n_total: usize = outputs.iter().map(|output| output.n).sum();
+    let n_max = outputs.iter().map(|output| output.n).max().unwrap_or(0);
+    if m == 0 || n_total == 0 {
+        return;
+    }
 
-            #[inline(never)]
-            pub unsafe fn gemm_basic(
-                m: usize,
-                n: usize,
-                k: usize,
-                dst: *mut T,
-                dst_cs: isize,
-                dst_rs: isize,
-                read_dst: bool,
-                lhs: *const T,
-                lhs_cs: isize,
-                lhs_rs: isize,
-                rhs: *const T,
-                rhs_cs: isize,
-                rhs_rs: isize,
-                alpha: T,
-                beta: T,
-                conj_dst: bool,
-                conj_lhs: bool,
-                conj_rhs: bool,
-                parallelism: $crate::Parallelism,
-            ) {
-                $crate::gemm::gemm_basic_generic::<_, T, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
-                    $crate::simd::$simd,
-                    m,
-                    n,
-                    k,
-                    dst,
-                    dst_cs,
-                    dst_rs,
-                    read_dst,
+    if k == 0 {
+        let zero = [[T::zero(); MR]; NR];
+        for (index, output) in outputs.iter().enumerate() {
+            if output.n == 0 {
+                continue;
+            }
+            if output.dst.is_null() {
+                for col in (0..output.n).step_by(NR) {
+                    for row in (0..m).step_by(MR) {
+                        hook.store(
+                            index,
+                            MR.min(m - row),
+                            NR.min(output.n - col),
+                            row,
+                            col,
+                            zero.as_ptr() as *const T,
+                            MR as isize,
+                            1,
+                        );
+                    }
+                }
+                if output.epilogue.reductions.is_none() {
+                    continue;
+                }
+            }
+            store_without_product::<_, MR>(
+                m,
+                output.n,
+                output.dst,
+                output.dst_cs,
+                output.dst_rs,
+                if output.read_dst {
+                    output.alpha
+                } else {
+                    T::zero()
+                },
+                conj_dst,
+                if output.epilogue.is_identity() {
+                    core::ptr::null()
+                } else {
+                    &output.epilogue
+                },
+                output.mask,
+                output.dst_blocks,
+                &apply_epilogue,
+                merge_reduction,
+            );
+        }
+        return;
+    }
+
+    let KernelParams { kc, mc, nc } = if m <= 64 && n_total <= 64 {
+        // skip expensive kernel_params call for small sizes
+        let kc = k.min(512);
+        let alloc = CACHE_INFO[1].cache_bytes / core::mem::size_of::<T>();
+        let mc = (alloc / kc) / MR * MR;
+
+        KernelParams {
+            kc,
+            mc,
+            nc: div_ceil(n_max, NR) * NR,
+        }
+    } else {
+        kernel_params(m, n_total, k, MR, NR, core::mem::size_of::<T>())
+    };
+    let nc = if nc > 0 {
+        nc
+    } else {
+        match parallelism {
+            Parallelism::None => 128 * NR,
+            Parallelism::Rayon(_) => div_ceil(n_max, NR) * NR,
+        }
+    };
+    // the row and column blocks start on the shared panels
+    let (mc, nc) = if rhs_is_lhs_transpose {
+        ((mc / MR * MR).max(MR), (nc / MR * MR).max(MR))
+    } else {
+        (mc, nc)
+    };
+    let row_align = if rhs_is_lhs_transpose { MR } else { N };
+
+    // if `dst` is null, only the reductions are computed, and each column block of the product
+    // is stored to a scratch buffer instead
+    let n_scratch = outputs.iter().filter(|output| output.dst.is_null()).count();
+    let nc = if n_scratch == 0 {
+        nc
+    } else {
+        let max_cols = CACHE_INFO[2].cache_bytes / core::mem::size_of::<T>() / m / NR * NR;
+        nc.min(max_cols.max(NR))
+    };
+
+    let simd_align = CACHELINE_ALIGN;
+
+    let packed_rhs_stride = kc * NR;
+    let packed_lhs_stride = kc * MR;
+    let scratch_stride = m * nc.min(n_max);
+
+    let mut scratch_mem = if n_scratch == 0 {
+        None
+    } else {
+        Some(GlobalMemBuffer::new(StackReq::new_aligned::<T>(
+            n_scratch * scratch_stride,
+            simd_align,
+        )))
+    };
+    let mut scratch_storage = scratch_mem.as_mut().map(|mem| {
+        let stack = DynStack::new(mem);
+        stack
+            .make_aligned_uninit::<T>(n_scratch * scratch_stride, simd_align)
+            .0
+    });
+    let scratch = Ptr(scratch_storage
+        .as_mut()
+        .map(|storage| storage.as_mut_ptr() as *mut T)
+        .unwrap_or(core::ptr::null_mut()));
+
+    let lhs = Ptr(lhs as *mut T);
+
+    // each thread reduces the rows of the tiles it computes into its own slot, and the slots are
+    // merged at the end
+    let n_slots = match parallelism {
+        Parallelism::None => 1,
+        Parallelism::Rayon(0) => rayon::current_num_threads(),
+        Parallelism::Rayon(max_threads) => max_threads,
+    };
+    let n_reduced_rows =
+        |output: &GemmOutput<T>| output.epilogue.reductions_view.nrows(m, output.n);
+    let mut reduction_storage = vec![
+        RowReduction::<T>::default();
+        n_slots
+            * outputs
+                .iter()
+                .filter(|output| output.epilogue.reductions.is_some())
+                .map(n_reduced_rows)
+                .sum::<usize>()
+    ];
+    let row_reductions = Ptr(reduction_storage.as_mut_ptr());
+
+    // no need to pack if the lhs is already contiguous-ish
+    let do_pack_rhs = |rhs_rs: isize| {
+        #[cfg(target_arch = "aarch64")]
+        {
+            let _ = rhs_rs;
+            m > get_rhs_packing_threshold() * MR
+        }
+        #[cfg(not(target_arch = "aarch64"))]
+        {
+            (rhs_rs.unsigned_abs() != 1 && m > 2 * MR)
+                || (rhs_rs.unsigned_abs() == 1 && m > get_rhs_packing_threshold() * MR)
+        }
+    };
+    let do_pack_rhs = |output: &GemmOutput<T>| {
+        !rhs_is_lhs_transpose && (output.rhs.is_null() || do_pack_rhs(output.rhs_rs))
+    };
+    let n_packed_rhs = outputs.iter().filter(|output| do_pack_rhs(output)).count();
+
+    let mut mem = if n_packed_rhs > 0 {
+        Some(GlobalMemBuffer::new(StackReq::new_aligned::<T>(
+            n_packed_rhs * packed_rhs_stride * (nc / NR),
+            simd_align,
+        )))
+    } else {
+        None
+    };
+
+    let mut packed_rhs_storage = mem.as_mut().map(|mem| {
+        let stack = DynStack::new(mem);
+        stack
+            .make_aligned_uninit::<T>(n_packed_rhs * packed_rhs_stride * (nc / NR), simd_align)
+            .0
+    });
+
+    let packed_rhs = packed_rhs_storage
+        .as_mut()
+        .map(|storage| storage.as_mut_ptr() as *mut T)
+        .unwrap_or(core::ptr::null_mut());
+    let packed_rhs = Ptr(packed_rhs);
+
+    let n_panels = div_ceil(m, MR);
+    let mut shared_mem = if rhs_is_lhs_transpose {
+        Some(GlobalMemBuffer::new(StackReq::new_aligned::<T>(
+            n_panels * packed_lhs_stride,
+            simd_align,
+        )))
+    } else {
+        None
+    };
+    let mut shared_storage = shared_mem.as_mut().map(|mem| {
+        let stack = DynStack::new(mem);
+        stack
+            .make_aligned_uninit::<T>(n_panels * packed_lhs_stride, simd_align)
+            .0
+    });
+    let shared_panels = Ptr(shared_storage
+        .as_mut()
+        .map(|storage| storage.as_mut_ptr() as *mut T)
+        .unwrap_or(core::ptr::null_mut()));
+
+    // the column tiles of the shared panels are aligned on the panels, so that each one is read
+    // from a single panel, and some of them are empty
+    let tiles_per_panel = div_ceil(MR, NR);
+    let n_col_tiles = move |n_chunk: usize| {
+        if rhs_is_lhs_transpose {
+            div_ceil(n_chunk, MR) * tiles_per_panel
+        } else {
+            div_ceil(n_chunk, NR)
+        }
+    };
+    let col_tile = move |n_chunk: usize, j: usize| -> (usize, usize) {
+        if rhs_is_lhs_transpose {
+            let panel_start = MR * (j / tiles_per_panel);
+            let col = panel_start + NR * (j % tiles_per_panel);
+            let panel_end = n_chunk.min(panel_start + MR);
+            (col, NR.min(panel_end.saturating_sub(col)))
+        } else {
+            (NR * j, NR.min(n_chunk - NR * j))
+        }
+    };
+
+    let mut outputs_state = Vec::with_capacity(outputs.len());
+    {
+        let (mut scratch, mut packed_rhs, mut row_reductions) =
+            (scratch, packed_rhs, row_reductions);
+        for (index, output) in outputs.iter().enumerate() {
+            let do_pack_rhs = do_pack_rhs(output);
+            let (dst, dst_cs, dst_rs) = if output.dst.is_null() {
+                let dst = scratch;
+                scratch = scratch.wrapping_add(scratch_stride);
+                (dst, m as isize, 1)
+            } else {
+                (Ptr(output.dst), output.dst_cs, output.dst_rs)
+            };
+            let state = OutputBlock {
+                index,
+                n_chunk: 0,
+                n_col_mini_chunks: 0,
+                dst,
+                dst_cs,
+                dst_rs,
+                rhs: Ptr(output.rhs as *mut T),
+                rhs_cs: output.rhs_cs,
+                rhs_rs: output.rhs_rs,
+                do_pack_rhs,
+                packed_rhs: if do_pack_rhs {
+                    packed_rhs
+                } else {
+                    Ptr(core::ptr::null_mut())
+                },
+                to_scratch: output.dst.is_null(),
+                alpha: if output.read_dst {
+                    output.alpha
+                } else {
+                    T::zero()
+                },
+                beta: output.beta,
+                conj_dst,
+                epilogue: if output.epilogue.is_identity() {
+                    None
+                } else {
+                    Some(output.epilogue)
+                },
+                mask: output.mask,
+                dst_blocks: output.dst_blocks,
+                row_reductions: if output.epilogue.reductions.is_some() {
+                    row_reductions
+                } else {
+                    Ptr(core::ptr::null_mut())
+                },
+                reductions_view: output.epilogue.reductions_view,
+                n_reduced_rows: n_reduced_rows(output),
+            };
+            if do_pack_rhs {
+                packed_rhs = packed_rhs.wrapping_add(packed_rhs_stride * (nc / NR));
+            }
+            if output.epilogue.reductions.is_some() {
+                row_reductions = row_reductions.wrapping_add(n_slots * n_reduced_rows(output));
+            }
+            outputs_state.push(state);
+        }
+    }
+
+    let mut blocks = Vec::with_capacity(outputs.len());
+
+    let mut col_outer = 0;
+    while col_outer != n_max {
+        blocks.clear();
+        for (output, state) in outputs.iter().zip(&outputs_state) {
+            if col_outer >= output.n {
+                continue;
+            }
+            let n_chunk = nc.min(output.n - col_outer);
+            blocks.push(OutputBlock {
+                n_chunk,
+                n_col_mini_chunks: n_col_tiles(n_chunk),
+                // the scratch buffer only holds the current column block
+                dst: if output.dst.is_null() {
+                    state.dst.wrapping_offset(-((col_outer * m) as isize))
+                } else {
+                    state.dst
+                },
+                ..*state
+            });
+        }
+        let n_chunk: usize = blocks.iter().map(|block| block.n_chunk).sum();
+
+        let mut depth_outer = 0;
+        while depth_outer != k {
+            let k_chunk = kc.min(k - depth_outer);
+            let is_last_depth_chunk = depth_outer + k_chunk == k;
+
+            let n_threads = n_threads(parallelism, m * n_chunk * k_chunk);
+
+            for block in blocks.iter().filter(|block| block.do_pack_rhs) {
+                let OutputBlock {
+                    index,
+                    n_chunk,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    packed_rhs,
+                    ..
+                } = *block;
+
+                // the rows of `rhs` that only meet zero blocks of `lhs` are not needed
+                let pack_rhs_cols = |col_inner: usize, ncols: usize| {
+                    for_each_lhs_depth_range(
+                        lhs_blocks,
+                        0,
+                        m,
+                        depth_outer,
+                        k_chunk,
+                        |depth_start, depth_end| {
+                            let packed_rhs = packed_rhs.wrapping_add(
+                                (col_inner / NR) * packed_rhs_stride
+                                    + (depth_start - depth_outer) * NR,
+                            );
+                            if rhs.0.is_null() {
+                                hook.pack_rhs::<NR>(
+                                    index,
+                                    ncols,
+                                    depth_end - depth_start,
+                                    packed_rhs.0,
+                                    depth_start,
+                                    col_outer + col_inner,
+                                    packed_rhs_stride,
+                                );
+                            } else {
+                                pack_rhs::<T, 1, NR, _>(
+                                    simd,
+                                    ncols,
+                                    depth_end - depth_start,
+                                    packed_rhs,
+                                    rhs.wrapping_offset(
+                                        depth_start as isize * rhs_rs
+                                            + (col_outer + col_inner) as isize * rhs_cs,
+                                    ),
+                                    rhs_cs,
+                                    rhs_rs,
+                                    packed_rhs_stride,
+                                );
+                            }
+                        },
+                    );
+                };
+
+                if n_threads <= 1 {
+                    pack_rhs_cols(0, n_chunk);
+                } else {
+                    let n_tasks = div_ceil(n_chunk, NR);
+                    let base = n_tasks / n_threads;
+                    let rem = n_tasks % n_threads;
+
+                    let tid_to_col_inner = |tid: usize| {
+                        if tid == n_threads {
+                            return n_chunk;
+                        }
+
+                        let col = if tid < rem {
+                            NR * tid * (base + 1)
+                        } else {
+                            NR * (rem + tid * base)
+                        };
+                        col.min(n_chunk)
+                    };
+
+                    let func = |tid: usize| {
+                        let col_inner = tid_to_col_inner(tid);
+                        let ncols = tid_to_col_inner(tid + 1) - col_inner;
+
+                        if ncols > 0 {
+                            pack_rhs_cols(col_inner, ncols);
+                        }
+                    };
+                    par_for_each(n_threads, func);
+                }
+            }
+
+            if rhs_is_lhs_transpose {
+                let pack_panels = |panel_start: usize, panel_end: usize| {
+                    let row = MR * panel_start;
+                    pack_lhs::<T, N, MR, _>(
+                        simd,
+                        m.min(MR * panel_end) - row,
+                        k_chunk,
+                        shared_panels.wrapping_add(panel_start * packed_lhs_stride),
+                        lhs.wrapping_offset(row as isize * lhs_rs + depth_outer as isize * lhs_cs),
+                        lhs_cs,
+                        lhs_rs,
+                        packed_lhs_stride,
+                    );
+                };
+                if n_threads <= 1 {
+                    pack_panels(0, n_panels);
+                } else {
+                    par_for_each(n_threads, |tid| {
+                        let panel_start = tid * n_panels / n_threads;
+                        let panel_end = (tid + 1) * n_panels / n_threads;
+                        if panel_start < panel_end {
+                            pack_panels(panel_start, panel_end);
+                        }
+                    });
+                }
+            }
+
+            let block_list = &*blocks;
+
+            // the jobs are the tiles that are not fully masked, and that either meet nonzero
+            // blocks of `lhs` or still need to be stored, so that the threads are balanced when
+            // the output is triangular or either of them is sparse. the fully masked tiles are
+            // only filled, once the other tiles are done with them
+            let tile_has_work = move |block: &OutputBlock<T>,
+                                      row: usize,
+                                      nrows: usize,
+                                      col: usize,
+                                      ncols: usize|
+                  -> bool {
+                if ncols == 0 {
+                    return false;
+                }
+                let is_filled = block.mask.is_some_and(|mask| mask.fill.is_some())
+                    || block.dst_blocks.is_some();
+                if is_masked_tile(block.mask, block.dst_blocks, row, col, nrows, ncols) {
+                    return is_last_depth_chunk && !block.to_scratch && is_filled;
+                }
+                if is_last_depth_chunk && !block.to_scratch && is_filled {
+                    return true;
+                }
+                match lhs_blocks {
+                    None => true,
+                    Some(lhs_blocks) => {
+                        !block.alpha.is_one()
+                            || block.conj_dst
+                            || (is_last_depth_chunk
+                                && (block.to_scratch
+                                    || !block.row_reductions.0.is_null()
+                                    || block.epilogue.is_some_and(|e| !e.is_scale_only())))
+                            || lhs_blocks.any_active(row, depth_outer, nrows, k_chunk)
+                    }
+                }
+            };
+            let count_jobs = move |row_outer: usize, m_chunk: usize| -> usize {
+                let n_row_mini_chunks = div_ceil(m_chunk, MR);
+                block_list
+                    .iter()
+                    .map(|block| {
+                        if block.mask.is_none()
+                            && block.dst_blocks.is_none()
+                            && lhs_blocks.is_none()
+                            && !rhs_is_lhs_transpose
+                        {
+                            return block.n_col_mini_chunks * n_row_mini_chunks;
+                        }
+                        let mut count = 0;
+                        for j in 0..block.n_col_mini_chunks {
+                            let (col_inner, ncols) = col_tile(block.n_chunk, j);
+                            for i in 0..n_row_mini_chunks {
+                                let nrows = MR.min(m_chunk - MR * i);
+                                if tile_has_work(
+                                    block,
+                                    row_outer + MR * i,
+                                    nrows,
+                                    col_outer + col_inner,
+                                    ncols,
+                                ) {
+                                    count += 1;
+                                }
+                            }
+                        }
+                        count
+                    })
+                    .sum()
+            };
+
+            let mut n_jobs = 0;
+            let mut row_outer = 0;
+            while row_outer != m {
+                let mut m_chunk = mc.min(m - row_outer);
+                if m_chunk > row_align {
+                    m_chunk = m_chunk / row_align * row_align;
+                }
+                n_jobs += count_jobs(row_outer, m_chunk);
+                row_outer += m_chunk;
+            }
+
+            // use a single thread for small workloads
+
+            let func = move |tid| {
+                L2_SLAB.with(|mem| {
+                    let mut mem = mem.borrow_mut();
+                    let stack = DynStack::new(&mut **mem);
+
+                    let (mut packed_lhs_storage, _) =
+                        stack.make_aligned_uninit::<T>(packed_lhs_stride * (mc / MR), simd_align);
+
+                    let packed_lhs = Ptr(packed_lhs_storage.as_mut_ptr() as *mut T);
+
+                    let (job_start, job_end) = job_range(tid, n_threads, n_jobs);
+
+                    let mut row_outer = 0;
+                    let mut job_id = 0;
+                    while row_outer != m {
+                        let mut m_chunk = mc.min(m - row_outer);
+                        if m_chunk > row_align {
+                            m_chunk = m_chunk / row_align * row_align;
+                        }
+                        let n_row_mini_chunks = (m_chunk + (MR - 1)) / MR;
+
+                        let n_mini_jobs = count_jobs(row_outer, m_chunk);
+
+                        if job_id >= job_end {
+                            return;
+                        }
+                        if n_mini_jobs == 0 || job_id + n_mini_jobs < job_start {
+                            row_outer += m_chunk;
+                            job_id += n_mini_jobs;
+                            continue;
+                        }
+
+                        let packing_threshold = if n_threads == 1 {
+                            get_lhs_packing_threshold_single_thread()
+                        } else {
+                            get_lhs_packing_threshold_multi_thread()
+                        };
+                        let do_pack_lhs = rhs_is_lhs_transpose
+                            || lhs.0.is_null()
+                            || lhs_symmetric.is_some()
+                            || (m_chunk % N != 0)
+                            || lhs_rs != 1
+                            || n_chunk > packing_threshold * NR;
+                        let packed_lhs_cs = if do_pack_lhs { MR as isize } else { lhs_cs };
+
+                        // the shared panels are already packed
+                        let packed_lhs = if rhs_is_lhs_transpose {
+                            shared_panels.wrapping_add((row_outer / MR) * packed_lhs_stride)
+                        } else {
+                            packed_lhs
+                        };
+                        if do_pack_lhs && !rhs_is_lhs_transpose {
+                            for_each_lhs_depth_range(
+                                lhs_blocks,
+                                row_outer,
+                                m_chunk,
+                                depth_outer,
+                                k_chunk,
+                                |depth_start, depth_end| match lhs_symmetric {
+                                    None if lhs.0.is_null() => hook.pack_lhs::<MR>(
+                                        m_chunk,
+                                        depth_end - depth_start,
+                                        packed_lhs.wrapping_add((depth_start - depth_outer) * MR).0,
+                                        row_outer,
+                                        depth_start,
+                                        packed_lhs_stride,
+                                    ),
+                                    None => pack_lhs::<T, N, MR, _>(
+                                        simd,
+                                        m_chunk,
+                                        depth_end - depth_start,
+                                        packed_lhs.wrapping_add((depth_start - depth_outer) * MR),
+                                        lhs.wrapping_offset(
+                                            row_outer as isize * lhs_rs
+                                                + depth_start as isize * lhs_cs,
+                                        ),
+                                        lhs_cs,
+                                        lhs_rs,
+                                        packed_lhs_stride,
+                                    ),
+                                    Some(SymmetricLhs { triangle, conj }) => {
+                                        pack_lhs_symmetric::<T, N, MR, _>(
+                                            simd,
+                                            m_chunk,
+                                            depth_end - depth_start,
+                                            packed_lhs
+                                                .wrapping_add((depth_start - depth_outer) * MR),
+                                            lhs,
+                                            lhs_cs,
+                                            lhs_rs,
+                                            packed_lhs_stride,
+                                            row_outer,
+                                            depth_start,
+                                            triangle,
+                                            conj,
+                                        )
+                                    }
+                                },
+                            );
+                        }
+
+                        for block in block_list {
+                            let OutputBlock {
+                                index,
+                                n_chunk,
+                                n_col_mini_chunks,
+                                dst,
+                                dst_cs,
+                                dst_rs,
+                                rhs,
+                                rhs_cs,
+                                rhs_rs,
+                                do_pack_rhs,
+                                packed_rhs,
+                                to_scratch,
+                                alpha,
+                                beta,
+                                conj_dst,
+                                epilogue,
+                                mask,
+                                dst_blocks,
+                                row_reductions,
+                                reductions_view,
+                                n_reduced_rows,
+                            } = *block;
+
+                            let alpha_status = if alpha.is_zero() {
+                                0
+                            } else if alpha.is_one() {
+                                1
+                            } else {
+                                2
+                            };
+                            let (packed_rhs_rs, packed_rhs_cs) = if rhs_is_lhs_transpose {
+                                (MR as isize, 1)
+                            } else if do_pack_rhs {
+                                (NR as isize, 1)
+                            } else {
+                                (rhs_rs, rhs_cs)
+                            };
+
+                            let mut j = 0;
+                            while j < n_col_mini_chunks {
+                                let mut i = 0;
+                                while i < n_row_mini_chunks {
+                                    let (col_inner, n_chunk_inner) = col_tile(n_chunk, j);
+
+                                    let row_inner = MR * i;
+                                    let m_chunk_inner = MR.min(m_chunk - row_inner);
+
+                                    if !tile_has_work(
+                                        block,
+                                        row_outer + row_inner,
+                                        m_chunk_inner,
+                                        col_outer + col_inner,
+                                        n_chunk_inner,
+                                    ) {
+                                        i += 1;
+                                        continue;
+                                    }
+
+                                    let inner_idx = &mut i;
+                                    if job_id < job_start || job_id >= job_end {
+                                        job_id += 1;
+                                        *inner_idx += 1;
+                                        continue;
+                                    }
+                                    job_id += 1;
+
+                                    let dst = dst.wrapping_offset(
+                                        (row_outer + row_inner) as isize * dst_rs
+                                            + (col_outer + col_inner) as isize * dst_cs,
+                                    );
+
+                                    if is_masked_tile(
+                                        mask,
+                                        dst_blocks,
+                                        row_outer + row_inner,
+                                        col_outer + col_inner,
+                                        m_chunk_inner,
+                                        n_chunk_inner,
+                                    ) {
+                                        fill_masked_tile(
+                                            mask,
+                                            dst_blocks,
+                                            dst.0,
+                                            dst_cs,
+                                            dst_rs,
+                                            row_outer + row_inner,
+                                            col_outer + col_inner,
+                                            m_chunk_inner,
+                                            n_chunk_inner,
+                                        );
+                                        i += 1;
+                                        continue;
+                                    }
+
+                                    let func = dispatcher[(m_chunk_inner + (N - 1)) / N - 1]
+                                        [n_chunk_inner - 1];
+
+                                    // the entries that the mask drops without filling them are
+                                    // left untouched
+                                    let untouched_mask = mask.filter(|mask| {
+                                        mask.fill.is_none()
+                                            && !mask.keeps_all(
+                                                row_outer + row_inner,
+                                                col_outer + col_inner,
+                                                m_chunk_inner,
+                                                n_chunk_inner,
+                                            )
+                                    });
+
+                                    // the scaling applies to every partial product, the rest
+                                    // only once the whole product is accumulated
+                                    let call =
+                                        |depth_start: usize,
+                                         depth_end: usize,
+                                         first: bool,
+                                         last: bool| {
+                                            let depth_inner = depth_start - depth_outer;
+                                            let tile_epilogue = epilogue
+                                                .map(|e| {
+                                                    if last && is_last_depth_chunk {
+                                                        e.without_reductions()
+                                                    } else {
+                                                        e.scale_only()
+                                                    }
+                                                })
+                                                .filter(|e| !e.is_identity())
+                                                .map(|e| {
+                                                    e.offset(
+                                                        row_outer + row_inner,
+                                                        col_outer + col_inner,
+                                                    )
+                                                });
+
+                                            let next_lhs = if do_pack_lhs {
+                                                packed_lhs
+                                                    .wrapping_add((i + 1) * packed_lhs_stride)
+                                                    .0
+                                            } else {
+                                                lhs.wrapping_offset(
+                                                    (row_outer + row_inner + m_chunk_inner)
+                                                        as isize
+                                                        * lhs_rs
+                                                        + depth_outer as isize * lhs_cs,
+                                                )
+                                                .0
+                                            };
+                                            let packed_lhs = if do_pack_lhs {
+                                                packed_lhs
+                                                    .wrapping_add(
+                                                        i * packed_lhs_stride + depth_inner * MR,
+                                                    )
+                                                    .0
+                                            } else {
+                                                lhs.wrapping_offset(
+                                                    (row_outer + row_inner) as isize * lhs_rs
+                                                        + depth_start as isize * lhs_cs,
+                                                )
+                                                .0
+                                            };
+                                            let packed_rhs = if rhs_is_lhs_transpose {
+                                                let col = col_outer + col_inner;
+                                                shared_panels
+                                                    .wrapping_add(
+                                                        (col / MR) * packed_lhs_stride
+                                                            + depth_inner * MR
+                                                            + col % MR,
+                                                    )
+                                                    .0
+                                            } else if do_pack_rhs {
+                                                packed_rhs
+                                                    .wrapping_add(
+                                                        j * packed_rhs_stride + depth_inner * NR,
+                                                    )
+                                                    .0
+                                            } else {
+                                                rhs.wrapping_offset(
+                                                    depth_start as isize * rhs_rs
+                                                        + (col_outer + col_inner) as isize * rhs_cs,
+                                                )
+                                                .0
+                                            };
+                                            let alpha = if first { alpha } else { T::one() };
+
+                                            let tile_epilogue =
+                                                match (tile_epilogue, untouched_mask) {
+                                                    (None, None) => {
+                                                        func(
+                                                            m_chunk_inner,
+                                                            n_chunk_inner,
+                                                            depth_end - depth_start,
+                                                            dst.0,
+                                                            packed_lhs,
+                                                            packed_rhs,
+                                                            dst_cs,
+                                                            dst_rs,
+                                                            packed_lhs_cs,
+                                                            packed_rhs_rs,
+                                                            packed_rhs_cs,
+                                                            alpha,
+                                                            beta,
+                                                            if first { alpha_status } else { 1 },
+                                                            first && conj_dst,
+                                                            conj_lhs,
+                                                            conj_rhs,
+                                                            next_lhs,
+                                                        );
+                                                        return;
+                                                    }
+                                                    (tile_epilogue, _) => tile_epilogue
+                                                        .unwrap_or_else(Epilogue::identity),
+                                                };
+
+                                            // the product goes through a temporary tile, and is
+                                            // combined with `dst` by the epilogue
+                                            let mut tmp = [[T::zero(); MR]; NR];
+                                            func(
+                                                m_chunk_inner,
+                                                n_chunk_inner,
+                                                depth_end - depth_start,
+                                                tmp.as_mut_ptr() as *mut T,
+                                                packed_lhs,
+                                                packed_rhs,
+                                                MR as isize,
+                                                1,
+                                                packed_lhs_cs,
+                                                packed_rhs_rs,
+                                                packed_rhs_cs,
+                                                T::zero(),
+                                                beta,
+                                                0,
+                                                false,
+                                                conj_lhs,
+                                                conj_rhs,
+                                                next_lhs,
+                                            );
+                                            for (jj, &products) in
+                                                tmp.iter().enumerate().take(n_chunk_inner)
+                                            {
+                                                let dst = dst.0.offset(jj as isize * dst_cs);
+                                                let mut values = [T::zero(); MR];
+                                                if !alpha.is_zero() {
+                                                    for (ii, value) in values
+                                                        .iter_mut()
+                                                        .enumerate()
+                                                        .take(m_chunk_inner)
+                                                    {
+                                                        let dst = *dst.offset(ii as isize * dst_rs);
+                                                        *value = alpha
+                                                            * if first && conj_dst {
+                                                                dst.conj()
+                                                            } else {
+                                                                dst
+                                                            };
+                                                    }
+                                                }
+                                                let values = apply_epilogue(
+                                                    &tile_epilogue,
+                                                    products,
+                                                    values,
+                                                    0,
+                                                    jj,
+                                                    m_chunk_inner,
+                                                );
+                                                for (ii, &value) in
+                                                    values.iter().enumerate().take(m_chunk_inner)
+                                                {
+                                                    if untouched_mask.is_some_and(|mask| {
+                                                        !mask.keeps(
+                                                            row_outer + row_inner + ii,
+                                                            col_outer + col_inner + jj,
+                                                        )
+                                                    }) {
+                                                        continue;
+                                                    }
+                                                    *dst.offset(ii as isize * dst_rs) = value;
+                                                }
+                                            }
+                                        };
+
+                                    match lhs_blocks {
+                                        None => {
+                                            call(depth_outer, depth_outer + k_chunk, true, true)
+                                        }
+                                        Some(_) => {
+                                            // each range is only stored once the next one is
+                                            // known, so that the last one gets the epilogue
+                                            let mut first = true;
+                                            let mut pending = None;
+                                            for_each_lhs_depth_range(
+                                                lhs_blocks,
+                                                row_outer + row_inner,
+                                                m_chunk_inner,
+                                                depth_outer,
+                                                k_chunk,
+                                                |depth_start, depth_end| {
+                                                    if let Some((start, end)) = pending {
+                                                        call(start, end, first, false);
+                                                        first = false;
+                                                    }
+                                                    pending = Some((depth_start, depth_end));
+                                                },
+                                            );
+                                            match pending {
+                                                Some((start, end)) => call(start, end, first, true),
+                                                // only alpha×dst and the epilogue
+                                                None => call(depth_outer, depth_outer, true, true),
+                                            }
+                                        }
+                                    }
+
+                                    // the masked entries of the partially masked tiles were
+                                    // computed, and are overwritten
+                                    if !to_scratch
+                                        && is_last_depth_chunk
+                                        && (mask.is_some() || dst_blocks.is_some())
+                                    {
+                                        fill_masked_tile(
+                                            mask,
+                                            dst_blocks,
+                                            dst.0,
+                                            dst_cs,
+                                            dst_rs,
+                                            row_outer + row_inner,
+                                            col_outer + col_inner,
+                                            m_chunk_inner,
+                                            n_chunk_inner,
+                                        );
+                                    }
+
+                                    if to_scratch && is_last_depth_chunk {
+                                        hook.store(
+                                            index,
+                                            m_chunk_inner,
+                                            n_chunk_inner,
+                                            row_outer + row_inner,
+                                            col_outer + col_inner,
+                                            dst.0,
+                                            dst_cs,
+                                            dst_rs,
+                                        );
+                                    }
+
+                                    if !row_reductions.0.is_null() && is_last_depth_chunk {
+                                        let row_reductions =
+                                            row_reductions.wrapping_add(tid * n_reduced_rows);
+                                        for jj in 0..n_chunk_inner {
+                                            let col = col_outer + col_inner + jj;
+                                            for ii in 0..m_chunk_inner {
+                                                let row = row_outer + row_inner + ii;
+                                                if mask.is_some_and(|mask| !mask.keeps(row, col))
+                                                    || dst_blocks.is_some_and(|blocks| {
+                                                        !blocks.is_active(
+                                                            row / blocks.block_rows,
+                                                            col / blocks.block_cols,
+                                                        )
+                                                    })
+                                                {
+                                                    continue;
+                                                }
+                                                let value = *dst.0.offset(
+                                                    ii as isize * dst_rs + jj as isize * dst_cs,
+                                                );
+                                                let (row, col) = reductions_view.map(row, col);
+                                                merge_reduction(
+                                                    &mut *row_reductions.0.add(row),
+                                                    &RowReduction::single(value, col),
+                                                );
+                                            }
+                                        }
+                                    }
+                                    i += 1;
+                                }
+                                j += 1;
+                            }
+                        }
+
+                        row_outer += m_chunk;
+                    }
+                });
+            };
+
+            match parallelism {
+                Parallelism::None => func(0),
+                Parallelism::Rayon(_) => {
+                    if n_threads == 1 {
+                        func(0);
+                    } else {
+                        par_for_each(n_threads, func);
+                    }
+                }
+            }
+
+            for block in blocks.iter_mut() {
+                block.conj_dst = false;
+                block.alpha.set_one();
+            }
+
+            depth_outer += k_chunk;
+        }
+        col_outer += nc.min(n_max - col_outer);
+    }
+
+    for (output, state) in outputs.iter().zip(&outputs_state) {
+        if let Some(reductions) = output.epilogue.reductions {
+            let n_reduced_rows = state.n_reduced_rows;
+            for i in 0..n_reduced_rows {
+                let mut row = *state.row_reductions.0.add(i);
+                for slot in 1..n_slots {
+                    merge_reduction(
+                        &mut row,
+                        &*state.row_reductions.0.add(slot * n_reduced_rows + i),
+                    );
+                }
+                reductions.write(i, &row);
+            }
+        }
+    }
+}
+
+/// One of the terms of the sum computed by [`gemm_sum_generic`]: `lhs` is `m×k` and `rhs` is
+/// `k×n`
+#[derive(Copy, Clone, Debug)]
+pub struct GemmTerm<T> {
+    pub k: usize,
+    pub lhs: *const T,
+    pub lhs_cs: isize,
+    pub lhs_rs: isize,
+    pub rhs: *const T,
+    pub rhs_cs: isize,
+    pub rhs_rs: isize,
+}
+
+unsafe impl<T: Sync> Send for GemmTerm<T> {}
+unsafe impl<T: Sync> Sync for GemmTerm<T> {}
+
+// calls `f(term, depth in the term, depth in the range, len)` for each part of the terms that
+// overlaps `depth_start..depth_end`, where the depths of the terms are laid end to end
+#[inline(always)]
+fn for_each_term_segment<T>(
+    terms: &[GemmTerm<T>],
+    depth_start: usize,
+    depth_end: usize,
+    mut f: impl FnMut(&GemmTerm<T>, usize, usize, usize),
+) {
+    let mut term_start = 0;
+    for term in terms {
+        let term_end = term_start + term.k;
+        let start = depth_start.max(term_start);
+        let end = depth_end.min(term_end);
+        if start < end {
+            f(term, start - term_start, start - depth_start, end - start);
+        }
+        if term_end >= depth_end {
+            break;
+        }
+        term_start = term_end;
+    }
+}
+
+// packs the terms of `gemm_sum_generic` one after the other along the depth
+struct SumTerms<'a, S, T, const N: usize> {
+    simd: S,
+    terms: &'a [GemmTerm<T>],
+}
+
+impl<S: Simd, T: Copy + Sync, const N: usize> GemmHook<T> for SumTerms<'_, S, T, N> {
+    #[inline(always)]
+    unsafe fn pack_lhs<const MR: usize>(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut T,
+        row: usize,
+        depth: usize,
+        dst_stride: usize,
+    ) {
+        for_each_term_segment(
+            self.terms,
+            depth,
+            depth + k,
+            |term, term_depth, chunk_depth, len| {
+                pack_lhs::<T, N, MR, _>(
+                    self.simd,
+                    m,
+                    len,
+                    Ptr(dst.add(chunk_depth * MR)),
+                    Ptr(term.lhs as *mut T).wrapping_offset(
+                        row as isize * term.lhs_rs + term_depth as isize * term.lhs_cs,
+                    ),
+                    term.lhs_cs,
+                    term.lhs_rs,
+                    dst_stride,
+                );
+            },
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn pack_rhs<const NR: usize>(
+        &self,
+        _: usize,
+        n: usize,
+        k: usize,
+        dst: *mut T,
+        depth: usize,
+        col: usize,
+        dst_stride: usize,
+    ) {
+        for_each_term_segment(
+            self.terms,
+            depth,
+            depth + k,
+            |term, term_depth, chunk_depth, len| {
+                pack_rhs::<T, 1, NR, _>(
+                    self.simd,
+                    n,
+                    len,
+                    Ptr(dst.add(chunk_depth * NR)),
+                    Ptr(term.rhs as *mut T).wrapping_offset(
+                        term_depth as isize * term.rhs_rs + col as isize * term.rhs_cs,
+                    ),
+                    term.rhs_cs,
+                    term.rhs_rs,
+                    dst_stride,
+                );
+            },
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn store(
+        &self,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: *const T,
+        _: isize,
+        _: isize,
+    ) {
+    }
+}
+
+/// dst := alpha×dst + beta×Σ lhs_i×rhs_i, where the `(lhs_i, rhs_i)` are the `terms`.
+///
+/// The terms are concatenated along the depth dimension while they are packed, so that each
+/// output tile accumulates all of them in registers before it is stored.
+#[inline(always)]
+pub unsafe fn gemm_sum_generic<
+    S: Simd,
+    T: Copy
+        + Zero
+        + One
+        + Conj
+        + Send
+        + Sync
+        + core::fmt::Debug
+        + core::ops::Add<Output = T>
+        + core::ops::Mul<Output = T>
+        + core::cmp::PartialEq,
+    const N: usize,
+    const MR: usize,
+    const NR: usize,
+    const MR_DIV_N: usize,
+>(
+    simd: S,
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    terms: &[GemmTerm<T>],
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    dispatcher: &[[MicroKernelFn<T>; NR]; MR_DIV_N],
+    parallelism: Parallelism,
+    epilogue: *const Epilogue<T>,
+    apply_epilogue: impl Copy
+        + Send
+        + Sync
+        + Fn(&Epilogue<T>, [T; MR], [T; MR], usize, usize, usize) -> [T; MR],
+) {
+    // the terms are not contiguous along the depth, so both operands are packed by the hook
+    gemm_multi_generic::<S, T, N, MR, NR, MR_DIV_N>(
+        simd,
+        m,
+        terms.iter().map(|term| term.k).sum(),
+        core::ptr::null(),
+        0,
+        0,
+        None,
+        None,
+        false,
+        &SumTerms::<S, T, N> { simd, terms },
+        &[GemmOutput {
+            n,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            rhs: core::ptr::null(),
+            rhs_cs: 0,
+            rhs_rs: 0,
+            alpha,
+            beta,
+            epilogue: if epilogue.is_null() {
+                Epilogue::identity()
+            } else {
+                *epilogue
+            },
+            mask: None,
+            dst_blocks: None,
+        }],
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        dispatcher,
+        parallelism,
+        apply_epilogue,
+        |_, _| {},
+    );
+}
+
+// one of the real matrices of the 3M method, with the imaginary parts conjugated as needed
+#[derive(Copy, Clone)]
+enum ThreeMPart {
+    Re,
+    Im,
+    Sum,
+}
+
+impl ThreeMPart {
+    #[inline(always)]
+    fn get<R: Copy + num_traits::Num>(self, x: num_complex::Complex<R>, im_sign: R) -> R {
+        match self {
+            ThreeMPart::Re => x.re,
+            ThreeMPart::Im => im_sign * x.im,
+            ThreeMPart::Sum => x.re + im_sign * x.im,
+        }
+    }
+}
+
+// packs the `part` of the `m×k` complex matrix `src` in zero-padded panels of `DST_WIDTH` rows
+#[inline(always)]
+unsafe fn pack_3m_part<R: Copy + num_traits::Num, const DST_WIDTH: usize>(
+    m: usize,
+    k: usize,
+    dst: *mut R,
+    src: *const num_complex::Complex<R>,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+    part: ThreeMPart,
+    im_sign: R,
+) {
+    let mut i = 0;
+    while i < m {
+        let width = DST_WIDTH.min(m - i);
+        let dst = dst.add((i / DST_WIDTH) * dst_stride);
+        let src = src.wrapping_offset(i as isize * src_rs);
+        for depth in 0..k {
+            let src = src.wrapping_offset(depth as isize * src_cs);
+            let dst = dst.add(depth * DST_WIDTH);
+            for ii in 0..width {
+                *dst.add(ii) = part.get(*src.offset(ii as isize * src_rs), im_sign);
+            }
+            for ii in width..DST_WIDTH {
+                *dst.add(ii) = R::zero();
+            }
+        }
+        i += width;
+    }
+}
+
+// packs the `part` of the complex operands of `gemm_3m_generic`, and accumulates `coeff` times
+// each tile of the real product into the complex `dst`
+struct ThreeM<R> {
+    part: ThreeMPart,
+    lhs: Ptr<num_complex::Complex<R>>,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    lhs_im_sign: R,
+    rhs: Ptr<num_complex::Complex<R>>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    rhs_im_sign: R,
+    dst: Ptr<num_complex::Complex<R>>,
+    dst_cs: isize,
+    dst_rs: isize,
+    alpha: num_complex::Complex<R>,
+    conj_dst: bool,
+    coeff: num_complex::Complex<R>,
+}
+
+impl<R: Copy + Send + Sync + num_traits::Num + core::ops::Neg<Output = R>> GemmHook<R>
+    for ThreeM<R>
+{
+    #[inline(always)]
+    unsafe fn pack_lhs<const MR: usize>(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut R,
+        row: usize,
+        depth: usize,
+        dst_stride: usize,
+    ) {
+        pack_3m_part::<R, MR>(
+            m,
+            k,
+            dst,
+            self.lhs
+                .wrapping_offset(row as isize * self.lhs_rs + depth as isize * self.lhs_cs)
+                .0,
+            self.lhs_cs,
+            self.lhs_rs,
+            dst_stride,
+            self.part,
+            self.lhs_im_sign,
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn pack_rhs<const NR: usize>(
+        &self,
+        _: usize,
+        n: usize,
+        k: usize,
+        dst: *mut R,
+        depth: usize,
+        col: usize,
+        dst_stride: usize,
+    ) {
+        pack_3m_part::<R, NR>(
+            n,
+            k,
+            dst,
+            self.rhs
+                .wrapping_offset(depth as isize * self.rhs_rs + col as isize * self.rhs_cs)
+                .0,
+            self.rhs_rs,
+            self.rhs_cs,
+            dst_stride,
+            self.part,
+            self.rhs_im_sign,
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn store(
+        &self,
+        _: usize,
+        m: usize,
+        n: usize,
+        row: usize,
+        col: usize,
+        src: *const R,
+        src_cs: isize,
+        src_rs: isize,
+    ) {
+        let (alpha, coeff) = (self.alpha, self.coeff);
+        for j in 0..n {
+            for i in 0..m {
+                let dst = self
+                    .dst
+                    .wrapping_offset(
+                        (row + i) as isize * self.dst_rs + (col + j) as isize * self.dst_cs,
+                    )
+                    .0;
+                let src = *src.offset(i as isize * src_rs + j as isize * src_cs);
+                let product = num_complex::Complex::new(coeff.re * src, coeff.im * src);
+                *dst = if alpha.is_zero() {
+                    product
+                } else {
+                    let d = if self.conj_dst { (*dst).conj() } else { *dst };
+                    alpha * d + product
+                };
+            }
+        }
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs for complex matrices, computed with three real products by
+/// the 3M method: with lhs = ar + i×ai and rhs = br + i×bi,
+/// re(lhs×rhs) = ar×br - ai×bi and im(lhs×rhs) = (ar + ai)×(br + bi) - ar×br - ai×bi.
+///
+/// The real parts, the imaginary parts and their sums are formed while the operands are packed
+/// for the real microkernels, and each tile of the three products is accumulated into `dst` with
+/// its coefficient once it is computed.
+#[inline(always)]
+pub unsafe fn gemm_3m_generic<
+    S: Simd,
+    R: Copy
+        + Zero
+        + One
+        + Conj
+        + Send
+        + Sync
+        + core::fmt::Debug
+        + num_traits::Num
+        + core::ops::Neg<Output = R>,
+    const N: usize,
+    const MR: usize,
+    const NR: usize,
+    const MR_DIV_N: usize,
+>(
+    simd: S,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut num_complex::Complex<R>,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const num_complex::Complex<R>,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const num_complex::Complex<R>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: num_complex::Complex<R>,
+    beta: num_complex::Complex<R>,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    dispatcher: &[[MicroKernelFn<R>; NR]; MR_DIV_N],
+    parallelism: Parallelism,
+) {
+    type C<R> = num_complex::Complex<R>;
+
+    // conjugating an operand flips the sign of its imaginary part
+    let lhs_im_sign = if conj_lhs { -R::one() } else { R::one() };
+    let rhs_im_sign = if conj_rhs { -R::one() } else { R::one() };
+
+    // beta×(p_re - p_im + i×(p_sum - p_re - p_im))
+    let one = R::one();
+    let parts = [
+        (ThreeMPart::Re, beta * C::new(one, -one)),
+        (ThreeMPart::Im, beta * C::new(-one, -one)),
+        (ThreeMPart::Sum, beta * C::new(R::zero(), one)),
+    ];
+    for (idx, (part, coeff)) in parts.into_iter().enumerate() {
+        // the first product scales `dst`, and the others accumulate into it
+        let (alpha, conj_dst) = match idx {
+            0 if read_dst => (alpha, conj_dst),
+            0 => (C::zero(), false),
+            _ => (C::one(), false),
+        };
+        gemm_multi_generic::<S, R, N, MR, NR, MR_DIV_N>(
+            simd,
+            m,
+            k,
+            core::ptr::null(),
+            0,
+            0,
+            None,
+            None,
+            false,
+            &ThreeM {
+                part,
+                lhs: Ptr(lhs as *mut C<R>),
+                lhs_cs,
+                lhs_rs,
+                lhs_im_sign,
+                rhs: Ptr(rhs as *mut C<R>),
+                rhs_cs,
+                rhs_rs,
+                rhs_im_sign,
+                dst: Ptr(dst),
+                dst_cs,
+                dst_rs,
+                alpha,
+                conj_dst,
+                coeff,
+            },
+            &[GemmOutput {
+                n,
+                dst: core::ptr::null_mut(),
+                dst_cs: 0,
+                dst_rs: 0,
+                read_dst: false,
+                rhs: core::ptr::null(),
+                rhs_cs: 0,
+                rhs_rs: 0,
+                alpha: R::zero(),
+                beta: R::one(),
+                epilogue: Epilogue::identity(),
+                mask: None,
+                dst_blocks: None,
+            }],
+            false,
+            false,
+            false,
+            dispatcher,
+            parallelism,
+            add_products,
+            |_, _| {},
+        );
+    }
+}
+
+// adds the squares of the `k` values of each of the first `n` lanes of the `WIDTH`-wide panels
+// of `packed` to `norms[..n]`
+#[inline(always)]
+unsafe fn add_packed_sq_norms<T: num_traits::Float, const WIDTH: usize>(
+    n: usize,
+    k: usize,
+    packed: *const T,
+    packed_stride: usize,
+    norms: *mut T,
+) {
+    let mut i = 0;
+    while i < n {
+        let width = WIDTH.min(n - i);
+        let packed = packed.add((i / WIDTH) * packed_stride);
+        let mut acc = [T::zero(); WIDTH];
+        for depth in 0..k {
+            let values = (packed.add(depth * WIDTH) as *const [T; WIDTH]).read_unaligned();
+            for (acc, x) in acc.iter_mut().zip(values) {
+                *acc = *acc + x * x;
+            }
+        }
+        for (ii, &acc) in acc.iter().enumerate().take(width) {
+            let norm = norms.add(i + ii);
+            *norm = *norm + acc;
+        }
+        i += WIDTH;
+    }
+}
+
+// packs the operands of `gemm_pairwise_generic`, and accumulates the squared norms of the rows of
+// `lhs` and of the columns of `rhs` from the packed panels. for the cosine similarities, each
+// norm is replaced by its inverse once it is complete, and the tiles are stored by the hook
+struct Pairwise<'a, S, T, const N: usize> {
+    simd: S,
+    k: usize,
+    lhs: Ptr<T>,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: Ptr<T>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    row_norms: Ptr<T>,
+    col_norms: Ptr<T>,
+    // the depth up to which the norms of the row block starting at each row are accumulated, or
+    // `usize::MAX` while a thread accumulates them. a row block is packed again for each column
+    // block, and by each thread that has tiles in it, but its norms are only accumulated once
+    row_depth: &'a [AtomicUsize],
+    cosine: bool,
+    dst: Ptr<T>,
+    dst_cs: isize,
+    dst_rs: isize,
+}
+
+impl<S: Simd, T: num_traits::Float + Send + Sync, const N: usize> Pairwise<'_, S, T, N> {
+    // called on each of the `n` norms once the last depth range is accumulated
+    #[inline(always)]
+    unsafe fn finish(&self, norms: *mut T, n: usize, depth_end: usize) {
+        if self.cosine && depth_end == self.k {
+            for i in 0..n {
+                let norm = norms.add(i);
+                *norm = if *norm > T::zero() {
+                    norm.read().sqrt().recip()
+                } else {
+                    T::zero()
+                };
+            }
+        }
+    }
+}
+
+impl<S: Simd, T: num_traits::Float + Send + Sync, const N: usize> GemmHook<T>
+    for Pairwise<'_, S, T, N>
+{
+    #[inline(always)]
+    unsafe fn pack_lhs<const MR: usize>(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut T,
+        row: usize,
+        depth: usize,
+        dst_stride: usize,
+    ) {
+        pack_lhs::<T, N, MR, _>(
+            self.simd,
+            m,
+            k,
+            Ptr(dst),
+            self.lhs
+                .wrapping_offset(row as isize * self.lhs_rs + depth as isize * self.lhs_cs),
+            self.lhs_cs,
+            self.lhs_rs,
+            dst_stride,
+        );
+
+        let row_depth = &self.row_depth[row];
+        loop {
+            match row_depth.compare_exchange_weak(
+                depth,
+                usize::MAX,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    let norms = self.row_norms.wrapping_add(row).0;
+                    add_packed_sq_norms::<T, MR>(m, k, dst, dst_stride, norms);
+                    self.finish(norms, m, depth + k);
+                    row_depth.store(depth + k, Ordering::Release);
+                    return;
+                }
+                // the tiles of this thread need the norms that another thread is accumulating
+                Err(current) if current == usize::MAX || current == depth => {
+                    std::thread::yield_now()
+                }
+                // already accumulated, for a previous column block or by another thread
+                Err(_) => return,
+            }
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn pack_rhs<const NR: usize>(
+        &self,
+        _: usize,
+        n: usize,
+        k: usize,
+        dst: *mut T,
+        depth: usize,
+        col: usize,
+        dst_stride: usize,
+    ) {
+        pack_rhs::<T, 1, NR, _>(
+            self.simd,
+            n,
+            k,
+            Ptr(dst),
+            self.rhs
+                .wrapping_offset(depth as isize * self.rhs_rs + col as isize * self.rhs_cs),
+            self.rhs_cs,
+            self.rhs_rs,
+            dst_stride,
+        );
+
+        // each column block is packed once for each depth range, before its tiles are computed
+        let norms = self.col_norms.wrapping_add(col).0;
+        add_packed_sq_norms::<T, NR>(n, k, dst, dst_stride, norms);
+        self.finish(norms, n, depth + k);
+    }
+
+    #[inline(always)]
+    unsafe fn store(
+        &self,
+        _: usize,
+        m: usize,
+        n: usize,
+        row: usize,
+        col: usize,
+        src: *const T,
+        src_cs: isize,
+        src_rs: isize,
+    ) {
+        // only the cosine similarities go through the scratch tiles, since the scaling of the
+        // epilogue applies to partial products, before the norms are complete
+        for j in 0..n {
+            let col_norm = *self.col_norms.0.add(col + j);
+            for i in 0..m {
+                let row_norm = *self.row_norms.0.add(row + i);
+                *self
+                    .dst
+                    .wrapping_offset(
+                        (row + i) as isize * self.dst_rs + (col + j) as isize * self.dst_cs,
+                    )
+                    .0 =
+                    *src.offset(i as isize * src_rs + j as isize * src_cs) * row_norm * col_norm;
+            }
+        }
+    }
+}
+
+/// Pairwise squared euclidean distances `dst[i, j] := |x_i|² + |y_j|² - 2 x_i·y_j`, clamped to
+/// zero if `clamp_to_zero`, or cosine similarities `dst[i, j] := x_i·y_j / (|x_i| × |y_j|)` if
+/// `cosine`, where `x_i` is the `i`-th row of `lhs` and `y_j` is the `j`-th column of `rhs`.
+///
+/// The squared norms are accumulated from the packed panels of the operands, and the distances
+/// are formed by the epilogue of each tile. The cosine similarities are zero if either vector
+/// is zero
+#[inline(always)]
+pub unsafe fn gemm_pairwise_generic<
+    S: Simd,
+    T: Copy + Zero + One + Conj + Send + Sync + core::fmt::Debug + num_traits::Float + EpilogueScalar,
+    const N: usize,
+    const MR: usize,
+    const NR: usize,
+    const MR_DIV_N: usize,
+>(
+    simd: S,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    cosine: bool,
+    clamp_to_zero: bool,
+    dispatcher: &[[MicroKernelFn<T>; NR]; MR_DIV_N],
+    parallelism: Parallelism,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+    if k == 0 {
+        for j in 0..n {
+            for i in 0..m {
+                *dst.offset(i as isize * dst_rs + j as isize * dst_cs) = T::zero();
+            }
+        }
+        return;
+    }
+
+    let mut norms = vec![T::zero(); m + n];
+    let row_depth: Vec<AtomicUsize> = (0..m).map(|_| AtomicUsize::new(0)).collect();
+    let (row_norms, col_norms) = (Ptr(norms.as_mut_ptr()), Ptr(norms.as_mut_ptr().add(m)));
+
+    let (output_dst, beta, epilogue) = if cosine {
+        (core::ptr::null_mut(), T::one(), Epilogue::identity())
+    } else {
+        (
+            dst,
+            T::from(-2.0).unwrap(),
+            Epilogue {
+                bias: Bias::PerRowAndCol(row_norms.0, 1, col_norms.0, 1),
+                activation: if clamp_to_zero {
+                    Activation::Relu
+                } else {
+                    Activation::Identity
+                },
+                ..Epilogue::identity()
+            },
+        )
+    };
+
+    gemm_multi_generic::<S, T, N, MR, NR, MR_DIV_N>(
+        simd,
+        m,
+        k,
+        core::ptr::null(),
+        0,
+        0,
+        None,
+        None,
+        false,
+        &Pairwise::<S, T, N> {
+            simd,
+            k,
+            lhs: Ptr(lhs as *mut T),
+            lhs_cs,
+            lhs_rs,
+            rhs: Ptr(rhs as *mut T),
+            rhs_cs,
+            rhs_rs,
+            row_norms,
+            col_norms,
+            row_depth: &row_depth,
+            cosine,
+            dst: Ptr(dst),
+            dst_cs,
+            dst_rs,
+        },
+        &[GemmOutput {
+            n,
+            dst: output_dst,
+            dst_cs,
+            dst_rs,
+            read_dst: false,
+            rhs: core::ptr::null(),
+            rhs_cs: 0,
+            rhs_rs: 0,
+            alpha: T::zero(),
+            beta,
+            epilogue,
+            mask: None,
+            dst_blocks: None,
+        }],
+        false,
+        false,
+        false,
+        dispatcher,
+        parallelism,
+        |epilogue, products, values, i, j, len| epilogue.store(products, values, i, j, len),
+        |_, _| {},
+    );
+}
+
+/// [`GemmHook`] of the complex products in planar storage, where each matrix is split in a real
+/// and an imaginary matrix with the same strides.
+///
+/// The operands are interleaved while they are packed for the complex microkernels, and each
+/// tile of the product is split into `dst_re` and `dst_im` as
+/// dst := alpha×op(dst) + tile, where op conjugates if `conj_dst`. `dst` is not read if `alpha`
+/// is zero
+pub struct PlanarHook<S, R> {
+    pub simd: S,
+    pub dst_re: Ptr<R>,
+    pub dst_im: Ptr<R>,
+    pub dst_cs: isize,
+    pub dst_rs: isize,
+    pub alpha: num_complex::Complex<R>,
+    pub conj_dst: bool,
+    pub lhs_re: Ptr<R>,
+    pub lhs_im: Ptr<R>,
+    pub lhs_cs: isize,
+    pub lhs_rs: isize,
+    pub rhs_re: Ptr<R>,
+    pub rhs_im: Ptr<R>,
+    pub rhs_cs: isize,
+    pub rhs_rs: isize,
+}
+
+impl<S: Simd, R: Copy + Send + Sync + num_traits::Num + core::ops::Neg<Output = R>>
+    GemmHook<num_complex::Complex<R>> for PlanarHook<S, R>
+{
+    #[inline(always)]
+    unsafe fn pack_lhs<const MR: usize>(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut num_complex::Complex<R>,
+        row: usize,
+        depth: usize,
+        dst_stride: usize,
+    ) {
+        let offset = row as isize * self.lhs_rs + depth as isize * self.lhs_cs;
+        pack_lhs_planar::<R, MR, _>(
+            self.simd,
+            m,
+            k,
+            Ptr(dst),
+            self.lhs_re.wrapping_offset(offset),
+            self.lhs_im.wrapping_offset(offset),
+            self.lhs_cs,
+            self.lhs_rs,
+            dst_stride,
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn pack_rhs<const NR: usize>(
+        &self,
+        _: usize,
+        n: usize,
+        k: usize,
+        dst: *mut num_complex::Complex<R>,
+        depth: usize,
+        col: usize,
+        dst_stride: usize,
+    ) {
+        let offset = depth as isize * self.rhs_rs + col as isize * self.rhs_cs;
+        pack_rhs_planar::<R, NR, _>(
+            self.simd,
+            n,
+            k,
+            Ptr(dst),
+            self.rhs_re.wrapping_offset(offset),
+            self.rhs_im.wrapping_offset(offset),
+            self.rhs_cs,
+            self.rhs_rs,
+            dst_stride,
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn store(
+        &self,
+        _: usize,
+        m: usize,
+        n: usize,
+        row: usize,
+        col: usize,
+        src: *const num_complex::Complex<R>,
+        src_cs: isize,
+        src_rs: isize,
+    ) {
+        let alpha = self.alpha;
+        for j in 0..n {
+            for i in 0..m {
+                let offset = (row + i) as isize * self.dst_rs + (col + j) as isize * self.dst_cs;
+                let dst_re = self.dst_re.wrapping_offset(offset).0;
+                let dst_im = self.dst_im.wrapping_offset(offset).0;
+                let tile = *src.offset(i as isize * src_rs + j as isize * src_cs);
+                let value = if alpha.is_zero() {
+                    tile
+                } else {
+                    let dst = num_complex::Complex::new(*dst_re, *dst_im);
+                    alpha * if self.conj_dst { dst.conj() } else { dst } + tile
+                };
+                *dst_re = value.re;
+                *dst_im = value.im;
+            }
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! __inject_mod {
+    ($module: ident, $ty: ident, $N: expr, $simd: ident) => {
+        mod $module {
+            use super::*;
+            use crate::microkernel::$module::$ty::*;
+            const N: usize = $N;
+
+            #[inline(never)]
+            pub unsafe fn gemm_basic(
+                m: usize,
+                n: usize,
+                k: usize,
+                dst: *mut T,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                lhs: *const T,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                rhs: *const T,
+                rhs_cs: isize,
+                rhs_rs: isize,
+                alpha: T,
+                beta: T,
+                conj_dst: bool,
+                conj_lhs: bool,
+                conj_rhs: bool,
+                parallelism: $crate::Parallelism,
+                epilogue: *const $crate::epilogue::Epilogue<T>,
+            ) {
+                $crate::gemm::gemm_basic_generic::<_, T, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    $crate::simd::$simd,
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    alpha,
+                    beta,
+                    conj_dst,
+                    conj_lhs,
+                    conj_rhs,
+                    |a, b, c| a * b + c,
+                    &UKR,
+                    parallelism,
+                    epilogue,
+                    |epilogue, products, values, i, j, len| {
+                        epilogue.store(products, values, i, j, len)
+                    },
+                    |reduction, other| reduction.merge(other),
+                );
+            }
+
+            #[inline(never)]
+            pub unsafe fn gemm_multi(
+                m: usize,
+                k: usize,
+                lhs: *const T,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                lhs_blocks: Option<$crate::sparse::BlockMask>,
+                outputs: &[$crate::gemm::GemmOutput<T>],
+                parallelism: $crate::Parallelism,
+            ) {
+                $crate::gemm::gemm_multi_generic::<_, T, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    $crate::simd::$simd,
+                    m,
+                    k,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    lhs_blocks,
+                    None,
+                    false,
+                    &$crate::gemm::NoHook,
+                    outputs,
+                    false,
+                    false,
+                    false,
+                    &UKR,
+                    parallelism,
+                    |epilogue, products, values, i, j, len| {
+                        epilogue.store(products, values, i, j, len)
+                    },
+                    |reduction, other| reduction.merge(other),
+                );
+            }
+
+            #[inline(never)]
+            pub unsafe fn gemm_sum(
+                m: usize,
+                n: usize,
+                dst: *mut T,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                terms: &[$crate::gemm::GemmTerm<T>],
+                alpha: T,
+                beta: T,
+                parallelism: $crate::Parallelism,
+                epilogue: *const $crate::epilogue::Epilogue<T>,
+            ) {
+                $crate::gemm::gemm_sum_generic::<_, T, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    $crate::simd::$simd,
+                    m,
+                    n,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    terms,
+                    alpha,
+                    beta,
+                    false,
+                    false,
+                    false,
+                    &UKR,
+                    parallelism,
+                    epilogue,
+                    |epilogue, products, values, i, j, len| {
+                        epilogue.store(products, values, i, j, len)
+                    },
+                );
+            }
+
+            #[inline(never)]
+            pub unsafe fn gemm_3m(
+                m: usize,
+                n: usize,
+                k: usize,
+                dst: *mut num_complex::Complex<T>,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                lhs: *const num_complex::Complex<T>,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                rhs: *const num_complex::Complex<T>,
+                rhs_cs: isize,
+                rhs_rs: isize,
+                alpha: num_complex::Complex<T>,
+                beta: num_complex::Complex<T>,
+                conj_dst: bool,
+                conj_lhs: bool,
+                conj_rhs: bool,
+                parallelism: $crate::Parallelism,
+            ) {
+                $crate::gemm::gemm_3m_generic::<_, T, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    $crate::simd::$simd,
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    alpha,
+                    beta,
+                    conj_dst,
+                    conj_lhs,
+                    conj_rhs,
+                    &UKR,
+                    parallelism,
+                );
+            }
+
+            #[inline(never)]
+            pub unsafe fn gemm_pairwise(
+                m: usize,
+                n: usize,
+                k: usize,
+                dst: *mut T,
+                dst_cs: isize,
+                dst_rs: isize,
+                lhs: *const T,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                rhs: *const T,
+                rhs_cs: isize,
+                rhs_rs: isize,
+                cosine: bool,
+                clamp_to_zero: bool,
+                parallelism: $crate::Parallelism,
+            ) {
+                $crate::gemm::gemm_pairwise_generic::<_, T, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    $crate::simd::$simd,
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    cosine,
+                    clamp_to_zero,
+                    &UKR,
+                    parallelism,
+                );
+            }
+
+            #[inline(never)]
+            pub unsafe fn gemm_syrk(
+                n: usize,
+                k: usize,
+                dst: *mut T,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                lhs: *const T,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                alpha: T,
+                beta: T,
+                triangle: $crate::epilogue::Triangle,
+                parallelism: $crate::Parallelism,
+            ) {
+                $crate::gemm::gemm_multi_generic::<_, T, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    $crate::simd::$simd,
+                    n,
+                    k,
                     lhs,
                     lhs_cs,
                     lhs_rs,
-                    rhs,
-                    rhs_cs,
-                    rhs_rs,
-                    alpha,
-                    beta,
-                    conj_dst,
-                    conj_lhs,
-                    conj_rhs,
-                    |a, b, c| a * b + c,
+                    None,
+                    None,
+                    true,
+                    &$crate::gemm::NoHook,
+                    &[$crate::gemm::GemmOutput {
+                        n,
+                        dst,
+                        dst_cs,
+                        dst_rs,
+                        read_dst,
+                        rhs: lhs,
+                        rhs_cs: lhs_rs,
+                        rhs_rs: lhs_cs,
+                        alpha,
+                        beta,
+                        epilogue: $crate::epilogue::Epilogue::identity(),
+                        mask: Some($crate::epilogue::TriangularMask {
+                            triangle,
+                            diag_offset: 0,
+                            fill: None,
+                        }),
+                        dst_blocks: None,
+                    }],
+                    false,
+                    false,
+                    false,
+                    &UKR,
+                    parallelism,
+                    |epilogue, products, values, i, j, len| {
+                        epilogue.store(products, values, i, j, len)
+                    },
+                    |reduction, other| reduction.merge(other),
+                );
+            }
+
+            #[inline(never)]
+            pub unsafe fn gemm_symm(
+                m: usize,
+                n: usize,
+                dst: *mut T,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                lhs: *const T,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                rhs: *const T,
+                rhs_cs: isize,
+                rhs_rs: isize,
+                alpha: T,
+                beta: T,
+                triangle: $crate::epilogue::Triangle,
+                parallelism: $crate::Parallelism,
+            ) {
+                $crate::gemm::gemm_multi_generic::<_, T, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    $crate::simd::$simd,
+                    m,
+                    m,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    None,
+                    Some($crate::gemm::SymmetricLhs {
+                        triangle,
+                        conj: false,
+                    }),
+                    false,
+                    &$crate::gemm::NoHook,
+                    &[$crate::gemm::GemmOutput {
+                        n,
+                        dst,
+                        dst_cs,
+                        dst_rs,
+                        read_dst,
+                        rhs,
+                        rhs_cs,
+                        rhs_rs,
+                        alpha,
+                        beta,
+                        epilogue: $crate::epilogue::Epilogue::identity(),
+                        mask: None,
+                        dst_blocks: None,
+                    }],
+                    false,
+                    false,
+                    false,
                     &UKR,
                     parallelism,
+                    |epilogue, products, values, i, j, len| {
+                        epilogue.store(products, values, i, j, len)
+                    },
+                    |reduction, other| reduction.merge(other),
                 );
             }
         }
     };
 }
 
+#[macro_export]
+macro_rules! __inject_mod_compensated {
+    ($module: ident, $ty: ident, $N: expr, $simd: ident) => {
+        paste::paste! {
+            mod [<$module _compensated>] {
+                use super::*;
+                use crate::microkernel::$module::$ty::*;
+                const N: usize = $N;
+
+                #[inline(never)]
+                pub unsafe fn gemm_compensated(
+                    m: usize,
+                    n: usize,
+                    k: usize,
+                    dst: *mut T,
+                    dst_cs: isize,
+                    dst_rs: isize,
+                    read_dst: bool,
+                    lhs: *const T,
+                    lhs_cs: isize,
+                    lhs_rs: isize,
+                    rhs: *const T,
+                    rhs_cs: isize,
+                    rhs_rs: isize,
+                    alpha: T,
+                    beta: T,
+                    parallelism: $crate::Parallelism,
+                ) {
+                    $crate::compensated::gemm_compensated_generic::<
+                        _,
+                        T,
+                        N,
+                        { COMPENSATED_MR_DIV_N * N },
+                        COMPENSATED_NR,
+                    >(
+                        $crate::simd::$simd,
+                        m,
+                        n,
+                        k,
+                        dst,
+                        dst_cs,
+                        dst_rs,
+                        read_dst,
+                        lhs,
+                        lhs_cs,
+                        lhs_rs,
+                        rhs,
+                        rhs_cs,
+                        rhs_rs,
+                        alpha,
+                        beta,
+                        COMPENSATED_UKR,
+                        parallelism,
+                    );
+                }
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! __inject_mod_cplx {
     ($module: ident, $ty: ident, $N: expr, $simd: ident) => {
@@ -724,17 +3555,321 @@ macro_rules! __inject_mod_cplx {
                         |a, b, c| a * b + c,
                         &CPLX_UKR,
                         parallelism,
+                        core::ptr::null(),
+                        $crate::gemm::add_products,
+                        |_, _| {},
+                        );
+                }
+
+                #[inline(never)]
+                pub unsafe fn gemm_syrk_cplx(
+                    n: usize,
+                    k: usize,
+                    dst: *mut num_complex::Complex<T>,
+                    dst_cs: isize,
+                    dst_rs: isize,
+                    read_dst: bool,
+                    lhs: *const num_complex::Complex<T>,
+                    lhs_cs: isize,
+                    lhs_rs: isize,
+                    alpha: num_complex::Complex<T>,
+                    beta: num_complex::Complex<T>,
+                    triangle: $crate::epilogue::Triangle,
+                    conj_lhs: bool,
+                    conj_rhs: bool,
+                    parallelism: $crate::Parallelism,
+                    ) {
+                    $crate::gemm::gemm_multi_generic::<_, _, N, { CPLX_MR_DIV_N * N }, CPLX_NR, CPLX_MR_DIV_N>(
+                        $crate::simd::$simd,
+                        n,
+                        k,
+                        lhs,
+                        lhs_cs,
+                        lhs_rs,
+                        None,
+                        None,
+                        true,
+                        &$crate::gemm::NoHook,
+                        &[$crate::gemm::GemmOutput {
+                            n,
+                            dst,
+                            dst_cs,
+                            dst_rs,
+                            read_dst,
+                            rhs: lhs,
+                            rhs_cs: lhs_rs,
+                            rhs_rs: lhs_cs,
+                            alpha,
+                            beta,
+                            epilogue: $crate::epilogue::Epilogue::identity(),
+                            mask: Some($crate::epilogue::TriangularMask {
+                                triangle,
+                                diag_offset: 0,
+                                fill: None,
+                            }),
+                            dst_blocks: None,
+                        }],
+                        false,
+                        conj_lhs,
+                        conj_rhs,
+                        &CPLX_UKR,
+                        parallelism,
+                        $crate::gemm::add_products,
+                        |_, _| {},
+                        );
+                }
+
+                #[inline(never)]
+                pub unsafe fn gemm_planar_cplx(
+                    m: usize,
+                    n: usize,
+                    k: usize,
+                    dst_re: *mut T,
+                    dst_im: *mut T,
+                    dst_cs: isize,
+                    dst_rs: isize,
+                    read_dst: bool,
+                    lhs_re: *const T,
+                    lhs_im: *const T,
+                    lhs_cs: isize,
+                    lhs_rs: isize,
+                    rhs_re: *const T,
+                    rhs_im: *const T,
+                    rhs_cs: isize,
+                    rhs_rs: isize,
+                    alpha: num_complex::Complex<T>,
+                    beta: num_complex::Complex<T>,
+                    conj_dst: bool,
+                    conj_lhs: bool,
+                    conj_rhs: bool,
+                    parallelism: $crate::Parallelism,
+                    ) {
+                    $crate::gemm::gemm_multi_generic::<_, _, N, { CPLX_MR_DIV_N * N }, CPLX_NR, CPLX_MR_DIV_N>(
+                        $crate::simd::$simd,
+                        m,
+                        k,
+                        core::ptr::null(),
+                        0,
+                        0,
+                        None,
+                        None,
+                        false,
+                        &$crate::gemm::PlanarHook {
+                            simd: $crate::simd::$simd,
+                            dst_re: $crate::Ptr(dst_re),
+                            dst_im: $crate::Ptr(dst_im),
+                            dst_cs,
+                            dst_rs,
+                            alpha: if read_dst { alpha } else { num_complex::Complex::new(0.0, 0.0) },
+                            conj_dst,
+                            lhs_re: $crate::Ptr(lhs_re as *mut T),
+                            lhs_im: $crate::Ptr(lhs_im as *mut T),
+                            lhs_cs,
+                            lhs_rs,
+                            rhs_re: $crate::Ptr(rhs_re as *mut T),
+                            rhs_im: $crate::Ptr(rhs_im as *mut T),
+                            rhs_cs,
+                            rhs_rs,
+                        },
+                        &[$crate::gemm::GemmOutput {
+                            n,
+                            dst: core::ptr::null_mut(),
+                            dst_cs: 0,
+                            dst_rs: 0,
+                            read_dst: false,
+                            rhs: core::ptr::null(),
+                            rhs_cs: 0,
+                            rhs_rs: 0,
+                            alpha: num_complex::Complex::new(0.0, 0.0),
+                            beta,
+                            epilogue: $crate::epilogue::Epilogue::identity(),
+                            mask: None,
+                            dst_blocks: None,
+                        }],
+                        false,
+                        conj_lhs,
+                        conj_rhs,
+                        &CPLX_UKR,
+                        parallelism,
+                        $crate::gemm::add_products,
+                        |_, _| {},
+                        );
+                }
+
+                #[inline(never)]
+                pub unsafe fn gemm_symm_cplx(
+                    m: usize,
+                    n: usize,
+                    dst: *mut num_complex::Complex<T>,
+                    dst_cs: isize,
+                    dst_rs: isize,
+                    read_dst: bool,
+                    lhs: *const num_complex::Complex<T>,
+                    lhs_cs: isize,
+                    lhs_rs: isize,
+                    rhs: *const num_complex::Complex<T>,
+                    rhs_cs: isize,
+                    rhs_rs: isize,
+                    alpha: num_complex::Complex<T>,
+                    beta: num_complex::Complex<T>,
+                    lhs_symmetric: $crate::gemm::SymmetricLhs,
+                    conj_lhs: bool,
+                    parallelism: $crate::Parallelism,
+                    ) {
+                    $crate::gemm::gemm_multi_generic::<_, _, N, { CPLX_MR_DIV_N * N }, CPLX_NR, CPLX_MR_DIV_N>(
+                        $crate::simd::$simd,
+                        m,
+                        m,
+                        lhs,
+                        lhs_cs,
+                        lhs_rs,
+                        None,
+                        Some(lhs_symmetric),
+                        false,
+                        &$crate::gemm::NoHook,
+                        &[$crate::gemm::GemmOutput {
+                            n,
+                            dst,
+                            dst_cs,
+                            dst_rs,
+                            read_dst,
+                            rhs,
+                            rhs_cs,
+                            rhs_rs,
+                            alpha,
+                            beta,
+                            epilogue: $crate::epilogue::Epilogue::identity(),
+                            mask: None,
+                            dst_blocks: None,
+                        }],
+                        false,
+                        conj_lhs,
+                        false,
+                        &CPLX_UKR,
+                        parallelism,
+                        $crate::gemm::add_products,
+                        |_, _| {},
                         );
                 }
             }
         }
-    };
-}
+    };
+}
+
+/// Picks `$func`, with optional generic arguments, from the fastest `__inject_mod` instantiation
+/// supported at runtime. The module names get `$suffix` appended when one is given
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select_fn {
+    ($suffix: tt, $func: ident $($generics: tt)*) => {
+        paste::paste! {{
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            {
+                #[cfg(feature = "nightly")]
+                if $crate::feature_detected!("avx512f") {
+                    return [<avx512f $suffix>]::$func $($generics)*;
+                }
+                if $crate::feature_detected!("fma") {
+                    [<fma $suffix>]::$func $($generics)*
+                } else if $crate::feature_detected!("avx") {
+                    [<avx $suffix>]::$func $($generics)*
+                } else if $crate::feature_detected!("sse") && $crate::feature_detected!("sse2") {
+                    [<sse $suffix>]::$func $($generics)*
+                } else {
+                    [<scalar $suffix>]::$func $($generics)*
+                }
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            {
+                if $crate::feature_detected!("neon") {
+                    [<neon $suffix>]::$func $($generics)*
+                } else {
+                    [<scalar $suffix>]::$func $($generics)*
+                }
+            }
+
+            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+            {
+                [<simd128 $suffix>]::$func $($generics)*
+            }
+
+            #[cfg(all(target_arch = "wasm32", not(target_feature = "simd128")))]
+            {
+                [<scalar $suffix>]::$func $($generics)*
+            }
+
+            #[cfg(not(any(
+                target_arch = "x86",
+                target_arch = "x86_64",
+                target_arch = "aarch64",
+                target_arch = "wasm32"
+            )))]
+            {
+                [<scalar $suffix>]::$func $($generics)*
+            }
+        }}
+    };
+    ($func: ident $($generics: tt)*) => {
+        $crate::__select_fn!("", $func $($generics)*)
+    };
+}
+
+/// Picks the `$func` of the fastest `__inject_mod_cplx` instantiation supported at runtime
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select_cplx_fn {
+    ($func: ident) => {{
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            #[cfg(feature = "nightly")]
+            if $crate::feature_detected!("avx512f") {
+                return avx512f_cplx::$func;
+            }
+            if $crate::feature_detected!("fma") {
+                return fma_cplx::$func;
+            }
+        }
+
+        scalar_cplx::$func
+    }};
+}
+
+#[macro_export]
+macro_rules! gemm_def {
+    ($ty: tt, $multiplier: expr) => {
+        type GemmTy = unsafe fn(
+            usize,
+            usize,
+            usize,
+            *mut T,
+            isize,
+            isize,
+            bool,
+            *const T,
+            isize,
+            isize,
+            *const T,
+            isize,
+            isize,
+            T,
+            T,
+            bool,
+            bool,
+            bool,
+            $crate::Parallelism,
+            *const $crate::epilogue::Epilogue<T>,
+        );
+
+        fn init_gemm_fn() -> GemmTy {
+            $crate::__select_fn!(gemm_basic)
+        }
 
-#[macro_export]
-macro_rules! gemm_def {
-    ($ty: tt, $multiplier: expr) => {
-        type GemmTy = unsafe fn(
+        lazy_static::lazy_static! {
+            pub static ref GEMM: GemmTy = init_gemm_fn();
+        }
+
+        type GemmCompensatedTy = unsafe fn(
             usize,
             usize,
             usize,
@@ -750,62 +3885,163 @@ macro_rules! gemm_def {
             isize,
             T,
             T,
+            $crate::Parallelism,
+        );
+
+        fn init_gemm_compensated_fn() -> GemmCompensatedTy {
+            $crate::__select_fn!(_compensated, gemm_compensated)
+        }
+
+        lazy_static::lazy_static! {
+            pub static ref GEMM_COMPENSATED: GemmCompensatedTy = init_gemm_compensated_fn();
+        }
+
+        type GemmMultiTy = unsafe fn(
+            usize,
+            usize,
+            *const T,
+            isize,
+            isize,
+            Option<$crate::sparse::BlockMask>,
+            &[$crate::gemm::GemmOutput<T>],
+            $crate::Parallelism,
+        );
+
+        fn init_gemm_multi_fn() -> GemmMultiTy {
+            $crate::__select_fn!(gemm_multi)
+        }
+
+        lazy_static::lazy_static! {
+            pub static ref GEMM_MULTI: GemmMultiTy = init_gemm_multi_fn();
+        }
+
+        type GemmSumTy = unsafe fn(
+            usize,
+            usize,
+            *mut T,
+            isize,
+            isize,
+            bool,
+            &[$crate::gemm::GemmTerm<T>],
+            T,
+            T,
+            $crate::Parallelism,
+            *const $crate::epilogue::Epilogue<T>,
+        );
+
+        fn init_gemm_sum_fn() -> GemmSumTy {
+            $crate::__select_fn!(gemm_sum)
+        }
+
+        lazy_static::lazy_static! {
+            pub static ref GEMM_SUM: GemmSumTy = init_gemm_sum_fn();
+        }
+
+        type Gemm3mTy = unsafe fn(
+            usize,
+            usize,
+            usize,
+            *mut num_complex::Complex<T>,
+            isize,
+            isize,
+            bool,
+            *const num_complex::Complex<T>,
+            isize,
+            isize,
+            *const num_complex::Complex<T>,
+            isize,
+            isize,
+            num_complex::Complex<T>,
+            num_complex::Complex<T>,
             bool,
             bool,
             bool,
             $crate::Parallelism,
         );
 
-        fn init_gemm_fn() -> GemmTy {
-            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-            {
-                #[cfg(feature = "nightly")]
-                if $crate::feature_detected!("avx512f") {
-                    return avx512f::gemm_basic;
-                }
-                if $crate::feature_detected!("fma") {
-                    fma::gemm_basic
-                } else if $crate::feature_detected!("avx") {
-                    avx::gemm_basic
-                } else if $crate::feature_detected!("sse") && $crate::feature_detected!("sse2") {
-                    sse::gemm_basic
-                } else {
-                    scalar::gemm_basic
-                }
-            }
+        fn init_gemm_3m_fn() -> Gemm3mTy {
+            $crate::__select_fn!(gemm_3m)
+        }
 
-            #[cfg(target_arch = "aarch64")]
-            {
-                if $crate::feature_detected!("neon") {
-                    neon::gemm_basic
-                } else {
-                    scalar::gemm_basic
-                }
-            }
+        lazy_static::lazy_static! {
+            pub static ref GEMM_3M: Gemm3mTy = init_gemm_3m_fn();
+        }
 
-            #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
-            {
-                simd128::gemm_basic
-            }
+        type GemmPairwiseTy = unsafe fn(
+            usize,
+            usize,
+            usize,
+            *mut T,
+            isize,
+            isize,
+            *const T,
+            isize,
+            isize,
+            *const T,
+            isize,
+            isize,
+            bool,
+            bool,
+            $crate::Parallelism,
+        );
 
-            #[cfg(all(target_arch = "wasm32", not(target_feature = "simd128")))]
-            {
-                scalar::gemm_basic
-            }
+        fn init_gemm_pairwise_fn() -> GemmPairwiseTy {
+            $crate::__select_fn!(gemm_pairwise)
+        }
 
-            #[cfg(not(any(
-                target_arch = "x86",
-                target_arch = "x86_64",
-                target_arch = "aarch64",
-                target_arch = "wasm32"
-            )))]
-            {
-                scalar::gemm_basic
-            }
+        lazy_static::lazy_static! {
+            pub static ref GEMM_PAIRWISE: GemmPairwiseTy = init_gemm_pairwise_fn();
+        }
+
+        type GemmSyrkTy = unsafe fn(
+            usize,
+            usize,
+            *mut T,
+            isize,
+            isize,
+            bool,
+            *const T,
+            isize,
+            isize,
+            T,
+            T,
+            $crate::epilogue::Triangle,
+            $crate::Parallelism,
+        );
+
+        fn init_gemm_syrk_fn() -> GemmSyrkTy {
+            $crate::__select_fn!(gemm_syrk)
         }
 
         lazy_static::lazy_static! {
-            pub static ref GEMM: GemmTy = init_gemm_fn();
+            pub static ref GEMM_SYRK: GemmSyrkTy = init_gemm_syrk_fn();
+        }
+
+        type GemmSymmTy = unsafe fn(
+            usize,
+            usize,
+            *mut T,
+            isize,
+            isize,
+            bool,
+            *const T,
+            isize,
+            isize,
+            *const T,
+            isize,
+            isize,
+            T,
+            T,
+            $crate::epilogue::Triangle,
+            $crate::Parallelism,
+        );
+
+        fn init_gemm_symm_fn() -> GemmSymmTy {
+            $crate::__select_fn!(gemm_symm)
+        }
+
+        lazy_static::lazy_static! {
+            pub static ref GEMM_SYMM: GemmSymmTy = init_gemm_symm_fn();
         }
 
         $crate::__inject_mod!(scalar, $ty, 1, Scalar);
@@ -824,6 +4060,23 @@ macro_rules! gemm_def {
 
         #[cfg(target_arch = "wasm32")]
         $crate::__inject_mod!(simd128, $ty, 2 * $multiplier, Simd128);
+
+        $crate::__inject_mod_compensated!(scalar, $ty, 1, Scalar);
+
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        $crate::__inject_mod_compensated!(sse, $ty, 2 * $multiplier, Sse);
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        $crate::__inject_mod_compensated!(avx, $ty, 4 * $multiplier, Avx);
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        $crate::__inject_mod_compensated!(fma, $ty, 4 * $multiplier, Fma);
+        #[cfg(all(feature = "nightly", any(target_arch = "x86", target_arch = "x86_64")))]
+        $crate::__inject_mod_compensated!(avx512f, $ty, 8 * $multiplier, Avx512f);
+
+        #[cfg(target_arch = "aarch64")]
+        $crate::__inject_mod_compensated!(neon, $ty, 2 * $multiplier, Neon);
+
+        #[cfg(target_arch = "wasm32")]
+        $crate::__inject_mod_compensated!(simd128, $ty, 2 * $multiplier, Simd128);
     };
 }
 
@@ -853,24 +4106,100 @@ macro_rules! gemm_cplx_def {
         );
 
         fn init_gemm_cplx_fn() -> GemmCplxTy {
-            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-            {
-                #[cfg(feature = "nightly")]
-                if $crate::feature_detected!("avx512f") {
-                    return avx512f_cplx::gemm_basic_cplx;
-                }
-                if $crate::feature_detected!("fma") {
-                    return fma_cplx::gemm_basic_cplx;
-                }
-            }
-
-            scalar_cplx::gemm_basic_cplx
+            $crate::__select_cplx_fn!(gemm_basic_cplx)
         }
 
         lazy_static::lazy_static! {
             pub static ref GEMM_CPLX: GemmCplxTy = init_gemm_cplx_fn();
         }
 
+        type GemmSyrkCplxTy = unsafe fn(
+            usize,
+            usize,
+            *mut num_complex::Complex<T>,
+            isize,
+            isize,
+            bool,
+            *const num_complex::Complex<T>,
+            isize,
+            isize,
+            num_complex::Complex<T>,
+            num_complex::Complex<T>,
+            $crate::epilogue::Triangle,
+            bool,
+            bool,
+            $crate::Parallelism,
+        );
+
+        fn init_gemm_syrk_cplx_fn() -> GemmSyrkCplxTy {
+            $crate::__select_cplx_fn!(gemm_syrk_cplx)
+        }
+
+        lazy_static::lazy_static! {
+            pub static ref GEMM_SYRK_CPLX: GemmSyrkCplxTy = init_gemm_syrk_cplx_fn();
+        }
+
+        type GemmSymmCplxTy = unsafe fn(
+            usize,
+            usize,
+            *mut num_complex::Complex<T>,
+            isize,
+            isize,
+            bool,
+            *const num_complex::Complex<T>,
+            isize,
+            isize,
+            *const num_complex::Complex<T>,
+            isize,
+            isize,
+            num_complex::Complex<T>,
+            num_complex::Complex<T>,
+            $crate::gemm::SymmetricLhs,
+            bool,
+            $crate::Parallelism,
+        );
+
+        fn init_gemm_symm_cplx_fn() -> GemmSymmCplxTy {
+            $crate::__select_cplx_fn!(gemm_symm_cplx)
+        }
+
+        lazy_static::lazy_static! {
+            pub static ref GEMM_SYMM_CPLX: GemmSymmCplxTy = init_gemm_symm_cplx_fn();
+        }
+
+        type GemmPlanarCplxTy = unsafe fn(
+            usize,
+            usize,
+            usize,
+            *mut T,
+            *mut T,
+            isize,
+            isize,
+            bool,
+            *const T,
+            *const T,
+            isize,
+            isize,
+            *const T,
+            *const T,
+            isize,
+            isize,
+            num_complex::Complex<T>,
+            num_complex::Complex<T>,
+            bool,
+            bool,
+            bool,
+            $crate::Parallelism,
+        );
+
+        fn init_gemm_planar_cplx_fn() -> GemmPlanarCplxTy {
+            $crate::__select_cplx_fn!(gemm_planar_cplx)
+        }
+
+        lazy_static::lazy_static! {
+            pub static ref GEMM_PLANAR_CPLX: GemmPlanarCplxTy = init_gemm_planar_cplx_fn();
+        }
+
         $crate::__inject_mod_cplx!(scalar, $ty, 1, Scalar);
 
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
@@ -0,0 +1,252 @@
+use crate::{
+    pack_operands::{pack_lhs, pack_rhs},
+    simd::Scalar,
+    tiled::{gemm_tiled, tile_params, Tile},
+    Parallelism, Ptr,
+};
+
+/// Scalar type usable with the generic matrix multiplication path
+///
+/// # Safety
+///
+/// The all-zero bit pattern must be a valid value of `Self`. It is used to pad the packed
+/// operands, though padding values are never passed to the arithmetic operations.
+pub unsafe trait GenericScalar: Copy + Send + Sync + 'static {
+    fn zero() -> Self;
+    fn one() -> Self;
+    /// Returns `self × a + b`
+    fn mul_add(self, a: Self, b: Self) -> Self;
+    fn conj(self) -> Self;
+}
+
+macro_rules! impl_generic_scalar {
+    ($($ty: ty),*) => {
+        $(
+            unsafe impl GenericScalar for $ty {
+                #[inline(always)]
+                fn zero() -> Self {
+                    0 as $ty
+                }
+                #[inline(always)]
+                fn one() -> Self {
+                    1 as $ty
+                }
+                #[inline(always)]
+                fn mul_add(self, a: Self, b: Self) -> Self {
+                    self * a + b
+                }
+                #[inline(always)]
+                fn conj(self) -> Self {
+                    self
+                }
+            }
+        )*
+    };
+}
+
+impl_generic_scalar!(f32, f64, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+unsafe impl<T: GenericScalar + core::ops::Neg<Output = T>> GenericScalar
+    for num_complex::Complex<T>
+{
+    #[inline(always)]
+    fn zero() -> Self {
+        Self::new(T::zero(), T::zero())
+    }
+    #[inline(always)]
+    fn one() -> Self {
+        Self::new(T::one(), T::zero())
+    }
+    #[inline(always)]
+    fn mul_add(self, a: Self, b: Self) -> Self {
+        Self::new(
+            self.re.mul_add(a.re, (-self.im).mul_add(a.im, b.re)),
+            self.re.mul_add(a.im, self.im.mul_add(a.re, b.im)),
+        )
+    }
+    #[inline(always)]
+    fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+}
+
+pub const MR: usize = 4;
+pub const NR: usize = 4;
+
+#[inline(always)]
+unsafe fn accumulate<T: GenericScalar, const CONJ_LHS: bool, const CONJ_RHS: bool>(
+    m: usize,
+    n: usize,
+    k: usize,
+    acc: &mut [[T; MR]; NR],
+    mut packed_lhs: *const T,
+    mut packed_rhs: *const T,
+) {
+    for _ in 0..k {
+        for (j, acc) in acc.iter_mut().enumerate().take(n) {
+            let rhs = *packed_rhs.add(j);
+            let rhs = if CONJ_RHS { rhs.conj() } else { rhs };
+            for (i, acc) in acc.iter_mut().enumerate().take(m) {
+                let lhs = *packed_lhs.add(i);
+                let lhs = if CONJ_LHS { lhs.conj() } else { lhs };
+                *acc = lhs.mul_add(rhs, *acc);
+            }
+        }
+        packed_lhs = packed_lhs.add(MR);
+        packed_rhs = packed_rhs.add(NR);
+    }
+}
+
+// alpha_status: 0 if dst is overwritten, 1 if the product is added to dst, 2 if dst is also
+// scaled by alpha
+#[inline(never)]
+unsafe fn microkernel<T: GenericScalar, const CONJ_LHS: bool, const CONJ_RHS: bool>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    packed_lhs: *const T,
+    packed_rhs: *const T,
+    alpha: T,
+    beta: T,
+    alpha_status: u8,
+    conj_dst: bool,
+) {
+    let mut acc = [[T::zero(); MR]; NR];
+    if m == MR && n == NR {
+        accumulate::<T, CONJ_LHS, CONJ_RHS>(MR, NR, k, &mut acc, packed_lhs, packed_rhs);
+    } else {
+        accumulate::<T, CONJ_LHS, CONJ_RHS>(m, n, k, &mut acc, packed_lhs, packed_rhs);
+    }
+
+    for (j, acc) in acc.iter().enumerate().take(n) {
+        for (i, &acc) in acc.iter().enumerate().take(m) {
+            let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+            *dst = match alpha_status {
+                0 => beta.mul_add(acc, T::zero()),
+                1 => beta.mul_add(acc, *dst),
+                _ => {
+                    let old = if conj_dst { (*dst).conj() } else { *dst };
+                    beta.mul_add(acc, alpha.mul_add(old, T::zero()))
+                }
+            };
+        }
+    }
+}
+
+type GenericMicroKernelFn<T> =
+    unsafe fn(usize, usize, usize, *mut T, isize, isize, *const T, *const T, T, T, u8, bool);
+
+/// dst := alpha×conj?(dst) + beta×conj?(lhs)×conj?(rhs), for any [`GenericScalar`]
+///
+/// Uses the same cache blocking, packing and threading as the vectorized kernels, with a scalar
+/// microkernel.
+pub unsafe fn gemm_generic<T: GenericScalar>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    if k == 0 {
+        for j in 0..n {
+            for i in 0..m {
+                let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+                *dst = if read_dst {
+                    let old = if conj_dst { (*dst).conj() } else { *dst };
+                    alpha.mul_add(old, T::zero())
+                } else {
+                    T::zero()
+                };
+            }
+        }
+        return;
+    }
+
+    let ukr: GenericMicroKernelFn<T> = match (conj_lhs, conj_rhs) {
+        (false, false) => microkernel::<T, false, false>,
+        (false, true) => microkernel::<T, false, true>,
+        (true, false) => microkernel::<T, true, false>,
+        (true, true) => microkernel::<T, true, true>,
+    };
+
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut T);
+    let rhs = Ptr(rhs as *mut T);
+
+    gemm_tiled::<T, MR, NR>(
+        m,
+        n,
+        k,
+        tile_params::<T, MR, NR>(m, n, k, parallelism),
+        parallelism,
+        |packed_lhs, stride, row, m_chunk, depth, k_chunk| {
+            pack_lhs::<T, 1, MR, _>(
+                Scalar,
+                m_chunk,
+                k_chunk,
+                packed_lhs,
+                lhs.wrapping_offset(row as isize * lhs_rs + depth as isize * lhs_cs),
+                lhs_cs,
+                lhs_rs,
+                stride,
+            )
+        },
+        Some(|packed_rhs, stride, col, n_chunk, depth, k_chunk| {
+            pack_rhs::<T, 1, NR, _>(
+                Scalar,
+                n_chunk,
+                k_chunk,
+                packed_rhs,
+                rhs.wrapping_offset(depth as isize * rhs_rs + col as isize * rhs_cs),
+                rhs_cs,
+                rhs_rs,
+                stride,
+            )
+        }),
+        |tile: Tile, packed_lhs, packed_rhs| {
+            let alpha_status = if tile.depth != 0 {
+                1
+            } else if read_dst {
+                2
+            } else {
+                0
+            };
+            ukr(
+                tile.m,
+                tile.n,
+                tile.k,
+                dst.wrapping_offset(tile.row as isize * dst_rs + tile.col as isize * dst_cs)
+                    .0,
+                dst_cs,
+                dst_rs,
+                packed_lhs,
+                packed_rhs,
+                alpha,
+                beta,
+                alpha_status,
+                conj_dst,
+            )
+        },
+    );
+}
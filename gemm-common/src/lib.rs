@@ -1,14 +1,26 @@
 #![cfg_attr(feature = "nightly", feature(stdsimd), feature(avx512_target_feature))]
 
+pub mod band;
+pub mod bitmatrix;
 pub mod cache;
 
+pub mod compensated;
+pub mod epilogue;
+
 pub mod gemm;
 pub mod gemv;
+pub mod generic;
 pub mod gevv;
 
+pub(crate) mod math;
 pub mod microkernel;
+pub mod modular;
 pub mod pack_operands;
+pub mod semiring;
 pub mod simd;
+pub mod sparse;
+pub mod sparse24;
+pub(crate) mod tiled;
 
 #[derive(Copy, Clone, Debug)]
 pub enum Parallelism {
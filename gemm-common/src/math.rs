@@ -0,0 +1,73 @@
+// elementary functions written with plain arithmetic and selects instead of calls to libm, so
+// that loops over them can be vectorized
+
+macro_rules! impl_exp {
+    ($name: ident, $ty: ident, $mantissa_bits: expr, $exp_bias: expr, $min: expr, $max: expr, $poly: path, $ln2_hi: expr, $ln2_lo: expr) => {
+        /// exp(x), with the argument clamped to the range where the result is a normal number.
+        /// NaN is mapped to the lower bound
+        #[inline(always)]
+        pub fn $name(x: $ty) -> $ty {
+            let x = if x > $min { x } else { $min };
+            let x = if x < $max { x } else { $max };
+
+            // exp(x) = 2^n × exp(r), |r| <= ln(2)/2
+            let round_magic = (1.5 * (1u64 << $mantissa_bits) as f64) as $ty;
+            let n_shifted = x * core::$ty::consts::LOG2_E + round_magic;
+            let n = n_shifted - round_magic;
+            let n_bits = n_shifted.to_bits().wrapping_sub(round_magic.to_bits());
+            let r = x - n * $ln2_hi - n * $ln2_lo;
+            let pow2n = <$ty>::from_bits(n_bits.wrapping_add($exp_bias) << $mantissa_bits);
+            $poly(r) * pow2n
+        }
+    };
+}
+
+// cephes expf, |r| <= ln(2)/2
+#[inline(always)]
+fn exp_poly_f32(r: f32) -> f32 {
+    let p = 1.987_569_1e-4;
+    let p = p * r + 1.398_199_9e-3;
+    let p = p * r + 8.333_452e-3;
+    let p = p * r + 4.166_579_6e-2;
+    let p = p * r + 1.666_666_5e-1;
+    let p = p * r + 0.5;
+    p * r * r + r + 1.0
+}
+
+// cephes exp, |r| <= ln(2)/2
+#[inline(always)]
+fn exp_poly_f64(r: f64) -> f64 {
+    let rr = r * r;
+    let p = 1.261_771_930_748_105_9e-4;
+    let p = p * rr + 3.029_944_077_074_419_6e-2;
+    let p = p * rr + 9.999_999_999_999_999e-1;
+    let px = r * p;
+    let q = 3.001_985_051_386_644_6e-6;
+    let q = q * rr + 2.524_483_403_496_841e-3;
+    let q = q * rr + 2.272_655_482_081_550_3e-1;
+    let q = q * rr + 2.0;
+    1.0 + 2.0 * (px / (q - px))
+}
+
+impl_exp!(
+    exp_f32,
+    f32,
+    23,
+    127,
+    -87.0,
+    88.0,
+    exp_poly_f32,
+    0.693_359_4,
+    -2.121_944_4e-4
+);
+impl_exp!(
+    exp_f64,
+    f64,
+    52,
+    1023,
+    -708.0,
+    709.0,
+    exp_poly_f64,
+    6.931_457_519_531_25e-1,
+    1.428_606_820_309_417_3e-6
+);
@@ -19,6 +19,9 @@ pub type MicroKernelFn<T> = unsafe fn(
     *const T,
 );
 
+// k, accumulator tile (hi followed by lo, both column major), packed lhs, packed rhs
+pub type CompensatedMicroKernelFn<T> = unsafe fn(usize, *mut T, *const T, *const T);
+
 // microkernel_fn_array!{
 // [ a, b, c, ],
 // [ d, e, f, ],
@@ -1102,3 +1105,99 @@ macro_rules! microkernel_cplx {
         }
     };
 }
+
+// error-free product for targets without a fused multiply-add, using Dekker's splitting.
+// expects `splat`, `add`, `sub` and `mul` in scope.
+#[macro_export]
+macro_rules! two_prod_dekker {
+    () => {
+        #[inline(always)]
+        unsafe fn two_prod(a: Pack, b: Pack) -> (Pack, Pack) {
+            #[inline(always)]
+            unsafe fn split(a: Pack) -> (Pack, Pack) {
+                let factor = splat(((1u64 << T::MANTISSA_DIGITS.div_ceil(2)) + 1) as T);
+                let c = mul(factor, a);
+                let a_hi = sub(c, sub(c, a));
+                (a_hi, sub(a, a_hi))
+            }
+
+            let p = mul(a, b);
+            let (a_hi, a_lo) = split(a);
+            let (b_hi, b_lo) = split(b);
+            let e = add(
+                add(
+                    add(sub(mul(a_hi, b_hi), p), mul(a_hi, b_lo)),
+                    mul(a_lo, b_hi),
+                ),
+                mul(a_lo, b_lo),
+            );
+            (p, e)
+        }
+    };
+}
+
+// the accumulator tile is always full: the driver packs both operands, so the padding rows and
+// columns are zero and contribute nothing.
+#[macro_export]
+macro_rules! microkernel_compensated {
+    ($([$target: tt])?, $name: ident, $mr_div_n: tt, $nr: tt) => {
+        pub const COMPENSATED_MR_DIV_N: usize = $mr_div_n;
+        pub const COMPENSATED_NR: usize = $nr;
+        pub const COMPENSATED_UKR: $crate::microkernel::CompensatedMicroKernelFn<T> = $name;
+
+        #[inline]
+        $(#[target_feature(enable = $target)])?
+        pub unsafe fn $name(
+            k: usize,
+            acc: *mut T,
+            mut packed_lhs: *const T,
+            mut packed_rhs: *const T,
+        ) {
+            #[inline(always)]
+            unsafe fn two_sum(a: Pack, b: Pack) -> (Pack, Pack) {
+                let s = add(a, b);
+                let bb = sub(s, a);
+                (s, add(sub(a, sub(s, bb)), sub(b, bb)))
+            }
+
+            let acc_hi = acc as *mut Pack;
+            let acc_lo = acc.add($mr_div_n * N * $nr) as *mut Pack;
+
+            let mut hi = [[splat(0.0); $mr_div_n]; $nr];
+            let mut lo = [[splat(0.0); $mr_div_n]; $nr];
+            seq_macro::seq!(N_ITER in 0..$nr {{
+                seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                    hi[N_ITER][M_ITER] = acc_hi.add(M_ITER + $mr_div_n * N_ITER).read_unaligned();
+                    lo[N_ITER][M_ITER] = acc_lo.add(M_ITER + $mr_div_n * N_ITER).read_unaligned();
+                }});
+            }});
+
+            for _ in 0..k {
+                let mut lhs = [splat(0.0); $mr_div_n];
+                seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                    lhs[M_ITER] = (packed_lhs.add(M_ITER * N) as *const Pack).read_unaligned();
+                }});
+
+                seq_macro::seq!(N_ITER in 0..$nr {{
+                    let rhs = splat(*packed_rhs.add(N_ITER));
+                    seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                        let (p, p_err) = two_prod(lhs[M_ITER], rhs);
+                        let (s, s_err) = two_sum(hi[N_ITER][M_ITER], p);
+                        hi[N_ITER][M_ITER] = s;
+                        lo[N_ITER][M_ITER] = add(lo[N_ITER][M_ITER], add(s_err, p_err));
+                    }});
+                }});
+
+                packed_lhs = packed_lhs.add($mr_div_n * N);
+                packed_rhs = packed_rhs.add($nr);
+            }
+
+            seq_macro::seq!(N_ITER in 0..$nr {{
+                seq_macro::seq!(M_ITER in 0..$mr_div_n {{
+                    acc_hi.add(M_ITER + $mr_div_n * N_ITER).write_unaligned(hi[N_ITER][M_ITER]);
+                    acc_lo.add(M_ITER + $mr_div_n * N_ITER).write_unaligned(lo[N_ITER][M_ITER]);
+                }});
+            }});
+        }
+    };
+}
@@ -0,0 +1,280 @@
+use crate::{
+    pack_operands::{pack_lhs, pack_rhs},
+    simd::Simd,
+    sparse::dispatch,
+    tiled::{gemm_tiled, tile_params, Tile},
+    Parallelism, Ptr,
+};
+
+/// Unsigned integer type holding residues modulo a runtime modulus
+pub trait ModularScalar: Copy + Send + Sync + 'static + PartialOrd + From<u8> {
+    /// exclusive upper bound on the modulus
+    const MODULUS_BOUND: Self;
+    /// accumulator type, wide enough to hold a sum of several products of residues
+    type Wide: Copy + Send + Sync + 'static;
+
+    fn wide_zero() -> Self::Wide;
+    fn widen(self) -> Self::Wide;
+    /// Returns `self × a + acc`
+    fn mul_add_wide(self, a: Self, acc: Self::Wide) -> Self::Wide;
+    fn reduce(acc: Self::Wide, modulus: Self) -> Self;
+    /// number of products of residues that can be added to a residue in `Wide` without overflow
+    fn max_terms(modulus: Self) -> usize;
+}
+
+macro_rules! impl_modular_scalar {
+    ($ty: ty, $wide: ty, $bound: expr) => {
+        impl ModularScalar for $ty {
+            const MODULUS_BOUND: Self = $bound;
+            type Wide = $wide;
+
+            #[inline(always)]
+            fn wide_zero() -> $wide {
+                0
+            }
+            #[inline(always)]
+            fn widen(self) -> $wide {
+                self as $wide
+            }
+            #[inline(always)]
+            fn mul_add_wide(self, a: Self, acc: $wide) -> $wide {
+                self as $wide * a as $wide + acc
+            }
+            #[inline(always)]
+            fn reduce(acc: $wide, modulus: Self) -> Self {
+                (acc % modulus as $wide) as $ty
+            }
+            fn max_terms(modulus: Self) -> usize {
+                let max = (modulus - 1) as $wide;
+                if max == 0 {
+                    usize::MAX
+                } else {
+                    ((<$wide>::MAX - max) / (max * max)).min(usize::MAX as $wide) as usize
+                }
+            }
+        }
+    };
+}
+
+impl_modular_scalar!(u32, u64, 1 << 31);
+impl_modular_scalar!(u64, u128, 1 << 62);
+
+pub const MR: usize = 4;
+pub const NR: usize = 4;
+
+// computes the full tile, so the packed operands are packed with `N` equal to the panel width,
+// which zero-pads partial panels
+// alpha_status: 0 if dst is overwritten, 1 if the product is added to dst, 2 if dst is also
+// scaled by alpha
+#[inline(always)]
+unsafe fn microkernel<T: ModularScalar>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    packed_lhs: *const T,
+    packed_rhs: *const T,
+    alpha: T,
+    beta: T,
+    alpha_status: u8,
+    modulus: T,
+    max_terms: usize,
+) {
+    let mut acc = [[T::wide_zero(); MR]; NR];
+    let mut depth = 0;
+    while depth < k {
+        // the accumulators hold residues at this point, so `max_terms` products can be added
+        // before they need to be reduced again
+        let depth_end = k.min(depth.saturating_add(max_terms));
+        while depth < depth_end {
+            let lhs = &*(packed_lhs.add(depth * MR) as *const [T; MR]);
+            let rhs = &*(packed_rhs.add(depth * NR) as *const [T; NR]);
+            for (acc, &rhs) in acc.iter_mut().zip(rhs) {
+                for (acc, &lhs) in acc.iter_mut().zip(lhs) {
+                    *acc = lhs.mul_add_wide(rhs, *acc);
+                }
+            }
+            depth += 1;
+        }
+        for acc in acc.iter_mut() {
+            for acc in acc.iter_mut() {
+                *acc = T::reduce(*acc, modulus).widen();
+            }
+        }
+    }
+
+    for (j, acc) in acc.iter().enumerate().take(n) {
+        for (i, &acc) in acc.iter().enumerate().take(m) {
+            let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+            let acc = T::reduce(acc, modulus);
+            // both products are below modulus², so their sum can't overflow
+            *dst = match alpha_status {
+                0 => T::reduce(beta.mul_add_wide(acc, T::wide_zero()), modulus),
+                1 => T::reduce(beta.mul_add_wide(acc, (*dst).widen()), modulus),
+                _ => T::reduce(
+                    beta.mul_add_wide(acc, alpha.mul_add_wide(*dst, T::wide_zero())),
+                    modulus,
+                ),
+            };
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn gemm_modular_generic<S: Simd, T: ModularScalar>(
+    simd: S,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    modulus: T,
+    parallelism: Parallelism,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    if k == 0 {
+        for j in 0..n {
+            for i in 0..m {
+                let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+                *dst = if read_dst {
+                    T::reduce(alpha.mul_add_wide(*dst, T::wide_zero()), modulus)
+                } else {
+                    T::from(0)
+                };
+            }
+        }
+        return;
+    }
+
+    let max_terms = T::max_terms(modulus);
+
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut T);
+    let rhs = Ptr(rhs as *mut T);
+
+    gemm_tiled::<T, MR, NR>(
+        m,
+        n,
+        k,
+        tile_params::<T, MR, NR>(m, n, k, parallelism),
+        parallelism,
+        |packed_lhs, stride, row, m_chunk, depth, k_chunk| {
+            pack_lhs::<T, MR, MR, _>(
+                simd,
+                m_chunk,
+                k_chunk,
+                packed_lhs,
+                lhs.wrapping_offset(row as isize * lhs_rs + depth as isize * lhs_cs),
+                lhs_cs,
+                lhs_rs,
+                stride,
+            )
+        },
+        Some(|packed_rhs, stride, col, n_chunk, depth, k_chunk| {
+            pack_rhs::<T, NR, NR, _>(
+                simd,
+                n_chunk,
+                k_chunk,
+                packed_rhs,
+                rhs.wrapping_offset(depth as isize * rhs_rs + col as isize * rhs_cs),
+                rhs_cs,
+                rhs_rs,
+                stride,
+            )
+        }),
+        |tile: Tile, packed_lhs, packed_rhs| {
+            let dst = dst.wrapping_offset(tile.row as isize * dst_rs + tile.col as isize * dst_cs);
+            let alpha_status = if tile.depth != 0 {
+                1
+            } else if read_dst {
+                2
+            } else {
+                0
+            };
+            S::vectorize(
+                #[inline(always)]
+                || {
+                    microkernel::<T>(
+                        tile.m,
+                        tile.n,
+                        tile.k,
+                        dst.0,
+                        dst_cs,
+                        dst_rs,
+                        packed_lhs,
+                        packed_rhs,
+                        alpha,
+                        beta,
+                        alpha_status,
+                        modulus,
+                        max_terms,
+                    )
+                },
+            );
+        },
+    );
+}
+
+/// dst := alpha×dst + beta×lhs×rhs mod `modulus`, computed exactly
+///
+/// `modulus` must be at least 2 and below [`ModularScalar::MODULUS_BOUND`] (2^31 for `u32`,
+/// 2^62 for `u64`), and it doesn't need to be prime. All the inputs, including `alpha`, `beta`,
+/// and `dst` if `read_dst` is true, must be residues, i.e. less than `modulus`.
+/// The products are accumulated in [`ModularScalar::Wide`], and only reduced every
+/// [`ModularScalar::max_terms`] steps along `k`.
+pub unsafe fn gemm_modular<T: ModularScalar>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    modulus: T,
+    parallelism: Parallelism,
+) {
+    assert!(modulus >= T::from(2) && modulus < T::MODULUS_BOUND);
+
+    dispatch!(gemm_modular_generic::<_, T>(
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        modulus,
+        parallelism,
+    ))
+}
@@ -1,4 +1,4 @@
-use crate::simd::Simd;
+use crate::{epilogue::Triangle, gemm::Conj, simd::Simd};
 
 #[inline(always)]
 pub fn quick_zero<T: Copy>(slice: &mut [T]) {
@@ -283,6 +283,194 @@ pub unsafe fn pack_lhs<T: Copy, const N: usize, const MR: usize, S: Simd>(
     );
 }
 
+// packs the planar matrix `src_re + i×src_im` in panels of `DST_WIDTH` interleaved rows, zero
+// padded like [`pack_generic`]
+#[inline(always)]
+unsafe fn pack_planar_generic<R: Copy, const DST_WIDTH: usize>(
+    m: usize,
+    k: usize,
+    dst: *mut num_complex::Complex<R>,
+    src_re: *const R,
+    src_im: *const R,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+) {
+    let mut i = 0;
+    while i < m {
+        let width = DST_WIDTH.min(m - i);
+        let mut dst = dst.add((i / DST_WIDTH) * dst_stride);
+        let offset = i as isize * src_rs;
+        for p in 0..k {
+            let offset = offset + p as isize * src_cs;
+            for j in 0..width {
+                let offset = offset + j as isize * src_rs;
+                *dst.add(j) = num_complex::Complex {
+                    re: *src_re.offset(offset),
+                    im: *src_im.offset(offset),
+                };
+            }
+            quick_zero(core::slice::from_raw_parts_mut(
+                dst.add(width),
+                DST_WIDTH - width,
+            ));
+            dst = dst.add(DST_WIDTH);
+        }
+        i += DST_WIDTH;
+    }
+}
+
+/// Packs the planar `m×k` matrix `src_re + i×src_im` like [`pack_lhs`], with the interleaved
+/// layout of the complex microkernels
+#[inline(never)]
+pub unsafe fn pack_lhs_planar<R: Copy, const MR: usize, S: Simd>(
+    _: S,
+    m: usize,
+    k: usize,
+    dst: crate::Ptr<num_complex::Complex<R>>,
+    src_re: crate::Ptr<R>,
+    src_im: crate::Ptr<R>,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+) {
+    let dst = dst.0;
+    let src_re = src_re.0;
+    let src_im = src_im.0;
+    S::vectorize(
+        #[inline(always)]
+        || pack_planar_generic::<R, MR>(m, k, dst, src_re, src_im, src_cs, src_rs, dst_stride),
+    );
+}
+
+/// Packs the planar `k×n` matrix `src_re + i×src_im` like [`pack_rhs`], with the interleaved
+/// layout of the complex microkernels
+#[inline(never)]
+pub unsafe fn pack_rhs_planar<R: Copy, const NR: usize, S: Simd>(
+    _: S,
+    n: usize,
+    k: usize,
+    dst: crate::Ptr<num_complex::Complex<R>>,
+    src_re: crate::Ptr<R>,
+    src_im: crate::Ptr<R>,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+) {
+    let dst = dst.0;
+    let src_re = src_re.0;
+    let src_im = src_im.0;
+    S::vectorize(
+        #[inline(always)]
+        || pack_planar_generic::<R, NR>(n, k, dst, src_re, src_im, src_rs, src_cs, dst_stride),
+    );
+}
+
+/// Packs the `m×k` block starting at `(row, col)` of the symmetric matrix `src`, of which only
+/// the `triangle` is read. The other entries are read from their mirror, and conjugated if the
+/// matrix is Hermitian, in which case the imaginary part of the diagonal is ignored
+#[inline(never)]
+pub unsafe fn pack_lhs_symmetric<T: Conj, const N: usize, const MR: usize, S: Simd>(
+    _: S,
+    m: usize,
+    k: usize,
+    dst: crate::Ptr<T>,
+    src: crate::Ptr<T>,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+    row: usize,
+    col: usize,
+    triangle: Triangle,
+    conj: bool,
+) {
+    if k == 0 {
+        return;
+    }
+
+    let dst = dst.0;
+    let src = src.0;
+    let stored = |i: usize, j: usize| match triangle {
+        Triangle::Lower => j <= i,
+        Triangle::Upper => j >= i,
+    };
+    let at = |i: usize, j: usize| src.wrapping_offset(i as isize * src_rs + j as isize * src_cs);
+
+    S::vectorize(
+        #[inline(always)]
+        || {
+            let mut i = 0;
+            while i < m {
+                let width = MR.min(m - i);
+                let dst = dst.add((i / MR) * dst_stride);
+                let (row_start, row_end) = (row + i, row + i + width - 1);
+                let (col_start, col_end) = (col, col + k - 1);
+
+                // the panels that lie on one side of the diagonal are packed like the full
+                // matrix, possibly transposed, and only the ones that cross it entry by entry
+                if stored(row_start, col_end) && stored(row_end, col_start) {
+                    pack_generic_inner_loop::<_, N, MR>(
+                        dst,
+                        at(row_start, col_start),
+                        src_rs,
+                        src_cs,
+                        width,
+                        k,
+                    );
+                } else if !stored(row_start, col_end) && !stored(row_end, col_start) {
+                    pack_generic_inner_loop::<_, N, MR>(
+                        dst,
+                        at(col_start, row_start),
+                        src_cs,
+                        src_rs,
+                        width,
+                        k,
+                    );
+                    if conj {
+                        for depth in 0..k {
+                            for ii in 0..width {
+                                let dst = dst.add(depth * MR + ii);
+                                *dst = (*dst).conj();
+                            }
+                        }
+                    }
+                } else {
+                    for depth in 0..k {
+                        let j = col + depth;
+                        for ii in 0..width {
+                            let i = row_start + ii;
+                            *dst.add(depth * MR + ii) = if stored(i, j) {
+                                *at(i, j)
+                            } else if conj {
+                                (*at(j, i)).conj()
+                            } else {
+                                *at(j, i)
+                            };
+                        }
+                        quick_zero(core::slice::from_raw_parts_mut(
+                            dst.add(depth * MR + width),
+                            MR - width,
+                        ));
+                    }
+                }
+
+                // the diagonal of a Hermitian matrix is real, whatever is stored there
+                if conj {
+                    for ii in 0..width {
+                        let i = row_start + ii;
+                        if (col_start..=col_end).contains(&i) {
+                            let dst = dst.add((i - col) * MR + ii);
+                            *dst = (*dst).real();
+                        }
+                    }
+                }
+
+                i += width;
+            }
+        },
+    );
+}
+
 #[inline(never)]
 pub unsafe fn pack_rhs<T: Copy, const N: usize, const NR: usize, S: Simd>(
     _: S,
@@ -0,0 +1,291 @@
+use crate::{
+    math::{exp_f32, exp_f64},
+    pack_operands::{pack_lhs, pack_rhs},
+    simd::Simd,
+    sparse::dispatch,
+    tiled::{gemm_tiled, tile_params, Tile},
+    Parallelism, Ptr,
+};
+
+/// Operations of a semiring over `T`
+///
+/// `add` must be associative and commutative, and `mul` must distribute over `add`, so that the
+/// terms of each dot product can be combined in any order.
+pub trait Semiring<T>: Copy + Send + Sync + 'static {
+    /// identity of `add`
+    fn zero() -> T;
+    /// identity of `mul`
+    fn one() -> T;
+    fn add(a: T, b: T) -> T;
+    fn mul(a: T, b: T) -> T;
+}
+
+/// Tropical semiring `(min, +)`, with identities `+inf` and `0`
+#[derive(Copy, Clone, Debug)]
+pub struct MinPlus;
+/// Tropical semiring `(max, +)`, with identities `-inf` and `0`
+#[derive(Copy, Clone, Debug)]
+pub struct MaxPlus;
+/// Log semiring `(log(exp(a) + exp(b)), +)`, with identities `-inf` and `0`
+#[derive(Copy, Clone, Debug)]
+pub struct LogSumExp;
+
+// log is written with plain arithmetic and selects, like exp, so that the microkernel can be
+// vectorized
+macro_rules! impl_semirings {
+    ($ty: ident, $exp: ident, $log_terms: expr) => {
+        impl Semiring<$ty> for MinPlus {
+            #[inline(always)]
+            fn zero() -> $ty {
+                <$ty>::INFINITY
+            }
+            #[inline(always)]
+            fn one() -> $ty {
+                0.0
+            }
+            #[inline(always)]
+            fn add(a: $ty, b: $ty) -> $ty {
+                if a < b {
+                    a
+                } else {
+                    b
+                }
+            }
+            #[inline(always)]
+            fn mul(a: $ty, b: $ty) -> $ty {
+                a + b
+            }
+        }
+
+        impl Semiring<$ty> for MaxPlus {
+            #[inline(always)]
+            fn zero() -> $ty {
+                <$ty>::NEG_INFINITY
+            }
+            #[inline(always)]
+            fn one() -> $ty {
+                0.0
+            }
+            #[inline(always)]
+            fn add(a: $ty, b: $ty) -> $ty {
+                if a > b {
+                    a
+                } else {
+                    b
+                }
+            }
+            #[inline(always)]
+            fn mul(a: $ty, b: $ty) -> $ty {
+                a + b
+            }
+        }
+
+        impl Semiring<$ty> for LogSumExp {
+            #[inline(always)]
+            fn zero() -> $ty {
+                <$ty>::NEG_INFINITY
+            }
+            #[inline(always)]
+            fn one() -> $ty {
+                0.0
+            }
+            #[inline(always)]
+            fn add(a: $ty, b: $ty) -> $ty {
+                // max(a, b) + log(1 + exp(-|a - b|))
+                // the difference is NaN if both are infinite with the same sign, in which case
+                // exp sends it to the same place as any other negligible term
+                let max = if a > b { a } else { b };
+                let y = $exp(-(a - b).abs());
+
+                // log1p(y) = log(u) - ((u - 1) - y) / u, u = 1 + y in [1, 2]
+                let u = 1.0 + y;
+                let correction = ((u - 1.0) - y) / u;
+                let big = u > core::$ty::consts::SQRT_2;
+                let v = if big { u * 0.5 } else { u };
+                let e: $ty = if big { core::$ty::consts::LN_2 } else { 0.0 };
+                // log(v) = 2 atanh(f), f = (v - 1) / (v + 1)
+                let f = (v - 1.0) / (v + 1.0);
+                let s = f * f;
+                let mut series: $ty = 0.0;
+                let mut i = $log_terms;
+                while i > 0 {
+                    i -= 1;
+                    series = series * s + 1.0 / (2 * i + 1) as $ty;
+                }
+
+                max + ((e + 2.0 * f * series) - correction)
+            }
+            #[inline(always)]
+            fn mul(a: $ty, b: $ty) -> $ty {
+                a + b
+            }
+        }
+    };
+}
+
+impl_semirings!(f32, exp_f32, 6);
+impl_semirings!(f64, exp_f64, 12);
+
+pub const MR: usize = 8;
+pub const NR: usize = 4;
+
+// computes the full tile, since the padding of the packed operands is never stored. the
+// operands are packed with `N` equal to the panel width, which zero-pads partial panels
+#[inline(always)]
+unsafe fn microkernel<T: Copy, R: Semiring<T>>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    packed_lhs: *const T,
+    packed_rhs: *const T,
+    read_dst: bool,
+) {
+    let mut acc = [[R::zero(); MR]; NR];
+    for depth in 0..k {
+        let lhs = &*(packed_lhs.add(depth * MR) as *const [T; MR]);
+        let rhs = &*(packed_rhs.add(depth * NR) as *const [T; NR]);
+        for (acc, &rhs) in acc.iter_mut().zip(rhs) {
+            for (acc, &lhs) in acc.iter_mut().zip(lhs) {
+                *acc = R::add(*acc, R::mul(lhs, rhs));
+            }
+        }
+    }
+
+    for (j, acc) in acc.iter().enumerate().take(n) {
+        for (i, &acc) in acc.iter().enumerate().take(m) {
+            let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+            *dst = if read_dst { R::add(*dst, acc) } else { acc };
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn gemm_semiring_generic<S: Simd, T: Copy + Send + Sync + 'static, R: Semiring<T>>(
+    simd: S,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    parallelism: Parallelism,
+) {
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    if k == 0 {
+        if !read_dst {
+            for j in 0..n {
+                for i in 0..m {
+                    *dst.offset(i as isize * dst_rs + j as isize * dst_cs) = R::zero();
+                }
+            }
+        }
+        return;
+    }
+
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut T);
+    let rhs = Ptr(rhs as *mut T);
+
+    gemm_tiled::<T, MR, NR>(
+        m,
+        n,
+        k,
+        tile_params::<T, MR, NR>(m, n, k, parallelism),
+        parallelism,
+        |packed_lhs, stride, row, m_chunk, depth, k_chunk| {
+            pack_lhs::<T, MR, MR, _>(
+                simd,
+                m_chunk,
+                k_chunk,
+                packed_lhs,
+                lhs.wrapping_offset(row as isize * lhs_rs + depth as isize * lhs_cs),
+                lhs_cs,
+                lhs_rs,
+                stride,
+            )
+        },
+        Some(|packed_rhs, stride, col, n_chunk, depth, k_chunk| {
+            pack_rhs::<T, NR, NR, _>(
+                simd,
+                n_chunk,
+                k_chunk,
+                packed_rhs,
+                rhs.wrapping_offset(depth as isize * rhs_rs + col as isize * rhs_cs),
+                rhs_cs,
+                rhs_rs,
+                stride,
+            )
+        }),
+        |tile: Tile, packed_lhs, packed_rhs| {
+            let dst = dst.wrapping_offset(tile.row as isize * dst_rs + tile.col as isize * dst_cs);
+            S::vectorize(
+                #[inline(always)]
+                || {
+                    microkernel::<T, R>(
+                        tile.m,
+                        tile.n,
+                        tile.k,
+                        dst.0,
+                        dst_cs,
+                        dst_rs,
+                        packed_lhs,
+                        packed_rhs,
+                        read_dst || tile.depth != 0,
+                    )
+                },
+            );
+        },
+    );
+}
+
+/// dst := dst ⊕ (lhs ⊗ rhs) if `read_dst`, otherwise dst := lhs ⊗ rhs, where the matrix product
+/// is taken over the semiring `R`: `(lhs ⊗ rhs)[i, j] = ⊕_p lhs[i, p] ⊗ rhs[p, j]`
+///
+/// The all-zero bit pattern must be a valid value of `T`, since it is used to pad the packed
+/// operands.
+pub unsafe fn gemm_semiring<T: Copy + Send + Sync + 'static, R: Semiring<T>>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    parallelism: Parallelism,
+) {
+    dispatch!(gemm_semiring_generic::<_, T, R>(
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        parallelism,
+    ))
+}
@@ -27,6 +27,15 @@ mod x86 {
     #[derive(Copy, Clone)]
     pub struct Avx512f;
 
+    // for the bit matrix kernels
+    #[derive(Copy, Clone)]
+    pub struct Popcnt;
+    #[derive(Copy, Clone)]
+    pub struct Avx2Popcnt;
+    #[cfg(feature = "nightly")]
+    #[derive(Copy, Clone)]
+    pub struct Avx512Vpopcntdq;
+
     impl Simd for Sse {
         #[inline]
         #[target_feature(enable = "sse,sse2")]
@@ -59,6 +68,31 @@ mod x86 {
             f()
         }
     }
+
+    impl Simd for Popcnt {
+        #[inline]
+        #[target_feature(enable = "popcnt")]
+        unsafe fn vectorize(f: impl FnOnce()) {
+            f()
+        }
+    }
+
+    impl Simd for Avx2Popcnt {
+        #[inline]
+        #[target_feature(enable = "avx2,popcnt")]
+        unsafe fn vectorize(f: impl FnOnce()) {
+            f()
+        }
+    }
+
+    #[cfg(feature = "nightly")]
+    impl Simd for Avx512Vpopcntdq {
+        #[inline]
+        #[target_feature(enable = "avx512f,avx512vpopcntdq,popcnt")]
+        unsafe fn vectorize(f: impl FnOnce()) {
+            f()
+        }
+    }
 }
 
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
@@ -0,0 +1,532 @@
+//! Sparse matrices: block masks for the block-sparse products, and products of matrices in
+//! compressed sparse row or column format with dense matrices.
+
+use crate::{
+    cache::div_ceil,
+    gemm::{c32, c64, par_for_each},
+    simd::Simd,
+    tiled::{job_range, n_threads},
+    Parallelism, Ptr,
+};
+use core::ops::{Add, Mul};
+use num_traits::{One, Zero};
+
+/// Sparsity pattern of a matrix split into blocks of `block_rows×block_cols`, where only the
+/// active blocks may contain nonzero values.
+///
+/// Bit `j` of the `i`-th row of blocks is bit `j % 64` of `bits[i * row_stride + j / 64]`. The
+/// blocks on the last row and column may be partially outside of the matrix
+#[derive(Copy, Clone, Debug)]
+pub struct BlockMask {
+    pub block_rows: usize,
+    pub block_cols: usize,
+    pub bits: *const u64,
+    pub row_stride: usize,
+}
+
+unsafe impl Send for BlockMask {}
+unsafe impl Sync for BlockMask {}
+
+impl BlockMask {
+    /// Bits of the mask of an `nrows×ncols` matrix whose active blocks are the `(row, col)` block
+    /// indices of `blocks`, and the matching row stride
+    pub fn bits_from_blocks(
+        nrows: usize,
+        ncols: usize,
+        block_rows: usize,
+        block_cols: usize,
+        blocks: &[(usize, usize)],
+    ) -> (Vec<u64>, usize) {
+        let row_stride = div_ceil(div_ceil(ncols, block_cols), 64);
+        let mut bits = vec![0u64; div_ceil(nrows, block_rows) * row_stride];
+        for &(i, j) in blocks {
+            bits[i * row_stride + j / 64] |= 1 << (j % 64);
+        }
+        (bits, row_stride)
+    }
+
+    #[inline(always)]
+    pub unsafe fn is_active(&self, block_row: usize, block_col: usize) -> bool {
+        (*self.bits.add(block_row * self.row_stride + block_col / 64) >> (block_col % 64)) & 1 == 1
+    }
+
+    /// Whether any block of the matrix that intersects the `nrows×ncols` submatrix starting at
+    /// `(row, col)` is active
+    #[inline]
+    pub unsafe fn any_active(&self, row: usize, col: usize, nrows: usize, ncols: usize) -> bool {
+        let cols = col / self.block_cols..div_ceil(col + ncols, self.block_cols);
+        (row / self.block_rows..div_ceil(row + nrows, self.block_rows))
+            .any(|i| cols.clone().any(|j| self.is_active(i, j)))
+    }
+
+    /// Calls `f(col_start, col_end)` for each maximal range of the columns `col..col + ncols`
+    /// whose blocks are active on any of the rows `row..row + nrows`
+    #[inline]
+    pub unsafe fn for_each_active_col_range(
+        &self,
+        row: usize,
+        col: usize,
+        nrows: usize,
+        ncols: usize,
+        mut f: impl FnMut(usize, usize),
+    ) {
+        let col_end = col + ncols;
+        let mut range_start = None;
+        for j in col / self.block_cols..div_ceil(col_end, self.block_cols) {
+            let active = (row / self.block_rows..div_ceil(row + nrows, self.block_rows))
+                .any(|i| self.is_active(i, j));
+            let block_start = (j * self.block_cols).max(col);
+            match (active, range_start) {
+                (true, None) => range_start = Some(block_start),
+                (false, Some(start)) => {
+                    f(start, block_start);
+                    range_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = range_start {
+            f(start, col_end);
+        }
+    }
+}
+
+/// Scalar type of the sparse × dense products
+pub trait SparseScalar:
+    Copy + Send + Sync + 'static + Zero + One + Add<Output = Self> + Mul<Output = Self>
+{
+}
+
+impl SparseScalar for f32 {}
+impl SparseScalar for f64 {}
+impl SparseScalar for c32 {}
+impl SparseScalar for c64 {}
+
+/// Sparse matrix in compressed sparse row format.
+///
+/// The nonzero entries of row `i` are `values[row_ptr[i]..row_ptr[i + 1]]`, in the columns
+/// `col_indices[row_ptr[i]..row_ptr[i + 1]]`. `row_ptr` has `nrows + 1` elements.
+#[derive(Debug)]
+pub struct CsrMatrix<'a, T> {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub row_ptr: &'a [usize],
+    pub col_indices: &'a [usize],
+    pub values: &'a [T],
+}
+
+/// Sparse matrix in compressed sparse column format.
+///
+/// The nonzero entries of column `j` are `values[col_ptr[j]..col_ptr[j + 1]]`, in the rows
+/// `row_indices[col_ptr[j]..col_ptr[j + 1]]`. `col_ptr` has `ncols + 1` elements.
+#[derive(Debug)]
+pub struct CscMatrix<'a, T> {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub col_ptr: &'a [usize],
+    pub row_indices: &'a [usize],
+    pub values: &'a [T],
+}
+
+impl<T> Copy for CsrMatrix<'_, T> {}
+impl<T> Clone for CsrMatrix<'_, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for CscMatrix<'_, T> {}
+impl<T> Clone for CscMatrix<'_, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+// checks that `ptr` is a valid compressed pointer array for `n_outer` rows or columns, and that
+// the indices of the nonzero entries are less than `n_inner`
+fn check_compressed<T>(
+    n_outer: usize,
+    n_inner: usize,
+    ptr: &[usize],
+    indices: &[usize],
+    values: &[T],
+) {
+    assert!(ptr.len() == n_outer + 1);
+    assert!(ptr.windows(2).all(|w| w[0] <= w[1]));
+    assert!(ptr[n_outer] <= indices.len());
+    assert!(ptr[n_outer] <= values.len());
+    assert!(indices[ptr[0]..ptr[n_outer]].iter().all(|&i| i < n_inner));
+}
+
+// dense columns handled at once, accumulated in a buffer that stays in L1
+pub(crate) const NC: usize = 128;
+
+/// Calls `$func::<S, ..>(simd, args)`, with `simd: S` the best simd backend available at runtime.
+macro_rules! dispatch {
+    ($func: ident::<_ $(, $ty: ty)* $(,)?>($($arg: expr),* $(,)?)) => {{
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            #[cfg(feature = "nightly")]
+            if crate::feature_detected!("avx512f") {
+                return $func::<_ $(, $ty)*>(crate::simd::Avx512f, $($arg),*);
+            }
+            if crate::feature_detected!("fma") {
+                return $func::<_ $(, $ty)*>(crate::simd::Fma, $($arg),*);
+            } else if crate::feature_detected!("avx") {
+                return $func::<_ $(, $ty)*>(crate::simd::Avx, $($arg),*);
+            } else if crate::feature_detected!("sse") && crate::feature_detected!("sse2") {
+                return $func::<_ $(, $ty)*>(crate::simd::Sse, $($arg),*);
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            if crate::feature_detected!("neon") {
+                return $func::<_ $(, $ty)*>(crate::simd::Neon, $($arg),*);
+            }
+        }
+
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        {
+            return $func::<_ $(, $ty)*>(crate::simd::Simd128, $($arg),*);
+        }
+
+        #[allow(unreachable_code)]
+        {
+            $func::<_ $(, $ty)*>(crate::simd::Scalar, $($arg),*)
+        }
+    }};
+}
+pub(crate) use dispatch;
+
+// acc[j] += a × rhs[j × rhs_cs], for `j < acc.len()`
+#[inline(always)]
+pub(crate) unsafe fn axpy<T: SparseScalar>(acc: &mut [T], a: T, rhs: *const T, rhs_cs: isize) {
+    if rhs_cs == 1 {
+        let rhs = core::slice::from_raw_parts(rhs, acc.len());
+        for (acc, &b) in acc.iter_mut().zip(rhs) {
+            *acc = *acc + a * b;
+        }
+    } else {
+        for (j, acc) in acc.iter_mut().enumerate() {
+            *acc = *acc + a * *rhs.offset(j as isize * rhs_cs);
+        }
+    }
+}
+
+// dst[j × dst_cs] := alpha × dst[j × dst_cs] + beta × acc[j] if `read_dst`, otherwise
+// dst[j × dst_cs] := beta × acc[j], for `j < acc.len()`
+#[inline(always)]
+pub(crate) unsafe fn store<T: SparseScalar>(
+    dst: *mut T,
+    dst_cs: isize,
+    acc: &[T],
+    read_dst: bool,
+    alpha: T,
+    beta: T,
+) {
+    if dst_cs == 1 {
+        let dst = core::slice::from_raw_parts_mut(dst, acc.len());
+        if read_dst {
+            for (dst, &acc) in dst.iter_mut().zip(acc) {
+                *dst = alpha * *dst + beta * acc;
+            }
+        } else {
+            for (dst, &acc) in dst.iter_mut().zip(acc) {
+                *dst = beta * acc;
+            }
+        }
+    } else {
+        for (j, &acc) in acc.iter().enumerate() {
+            let dst = dst.offset(j as isize * dst_cs);
+            *dst = if read_dst {
+                alpha * *dst + beta * acc
+            } else {
+                beta * acc
+            };
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn csr_gemm_generic<S: Simd, T: SparseScalar>(
+    _simd: S,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: CsrMatrix<'_, T>,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    let m = lhs.nrows;
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let row_ptr = lhs.row_ptr;
+    // the cost of a row is proportional to its number of nonzero entries, plus one for the store
+    // of the destination row. the rows are split in contiguous ranges of about the same cost
+    let cost = |i: usize| row_ptr[i] - row_ptr[0] + i;
+    let total_cost = cost(m);
+    let n_threads = n_threads(parallelism, total_cost * n).min(m);
+
+    // first row whose cumulative cost is at least `target`
+    let split = |target: usize| {
+        let (mut lo, mut hi) = (0, m);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if cost(mid) < target {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    };
+
+    let dst = Ptr(dst);
+    let rhs = Ptr(rhs as *mut T);
+
+    let func = |tid: usize| {
+        let row_start = split(total_cost * tid / n_threads);
+        let row_end = split(total_cost * (tid + 1) / n_threads);
+        let dst = dst.wrapping_add(0).0;
+        let rhs = rhs.wrapping_add(0).0 as *const T;
+
+        S::vectorize(
+            #[inline(always)]
+            || {
+                let mut acc = [T::zero(); NC];
+                for i in row_start..row_end {
+                    let entries = row_ptr[i]..row_ptr[i + 1];
+                    let dst = dst.offset(i as isize * dst_rs);
+
+                    let mut col = 0;
+                    while col < n {
+                        let nc = NC.min(n - col);
+                        let acc = &mut acc[..nc];
+                        acc.fill(T::zero());
+
+                        for idx in entries.clone() {
+                            let p = *lhs.col_indices.get_unchecked(idx);
+                            let a = *lhs.values.get_unchecked(idx);
+                            axpy(
+                                acc,
+                                a,
+                                rhs.offset(p as isize * rhs_rs + col as isize * rhs_cs),
+                                rhs_cs,
+                            );
+                        }
+
+                        store(
+                            dst.offset(col as isize * dst_cs),
+                            dst_cs,
+                            acc,
+                            read_dst,
+                            alpha,
+                            beta,
+                        );
+                        col += nc;
+                    }
+                }
+            },
+        );
+    };
+
+    if n_threads <= 1 {
+        func(0);
+    } else {
+        par_for_each(n_threads, func);
+    }
+}
+
+#[inline(always)]
+unsafe fn csc_gemm_generic<S: Simd, T: SparseScalar>(
+    _simd: S,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: CscMatrix<'_, T>,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    let m = lhs.nrows;
+    let k = lhs.ncols;
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    let col_ptr = lhs.col_ptr;
+    let nnz = col_ptr[k] - col_ptr[0];
+
+    // the nonzero entries of a column of `lhs` are scattered over the rows of `dst`, so the
+    // threads own disjoint ranges of dense columns instead, and go through all the entries
+    let n_threads = n_threads(parallelism, (nnz + m) * n).min(div_ceil(n, 16));
+    let nc = NC.min(div_ceil(div_ceil(n, n_threads), 16) * 16);
+    let n_jobs = div_ceil(n, nc);
+    let n_threads = n_threads.min(n_jobs);
+
+    let dst = Ptr(dst);
+    let rhs = Ptr(rhs as *mut T);
+
+    let func = |tid: usize| {
+        let (job_start, job_end) = job_range(tid, n_threads, n_jobs);
+        let dst = dst.wrapping_add(0).0;
+        let rhs = rhs.wrapping_add(0).0 as *const T;
+
+        S::vectorize(
+            #[inline(always)]
+            || {
+                let mut rhs_row = [T::zero(); NC];
+                for job_id in job_start..job_end {
+                    let col = job_id * nc;
+                    let nc = nc.min(n - col);
+                    let rhs_row = &mut rhs_row[..nc];
+
+                    // the scaled destination is the starting point of the accumulation
+                    for i in 0..m {
+                        let dst = dst.offset(i as isize * dst_rs + col as isize * dst_cs);
+                        for j in 0..nc {
+                            let dst = dst.offset(j as isize * dst_cs);
+                            *dst = if read_dst { alpha * *dst } else { T::zero() };
+                        }
+                    }
+
+                    for p in 0..k {
+                        let entries = col_ptr[p]..col_ptr[p + 1];
+                        if entries.is_empty() {
+                            continue;
+                        }
+
+                        let rhs = rhs.offset(p as isize * rhs_rs + col as isize * rhs_cs);
+                        for (j, b) in rhs_row.iter_mut().enumerate() {
+                            *b = *rhs.offset(j as isize * rhs_cs);
+                        }
+
+                        for idx in entries {
+                            let i = *lhs.row_indices.get_unchecked(idx);
+                            let a = beta * *lhs.values.get_unchecked(idx);
+                            let dst = dst.offset(i as isize * dst_rs + col as isize * dst_cs);
+                            if dst_cs == 1 {
+                                let dst = core::slice::from_raw_parts_mut(dst, nc);
+                                for (dst, &b) in dst.iter_mut().zip(&*rhs_row) {
+                                    *dst = *dst + a * b;
+                                }
+                            } else {
+                                for (j, &b) in rhs_row.iter().enumerate() {
+                                    let dst = dst.offset(j as isize * dst_cs);
+                                    *dst = *dst + a * b;
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+        );
+    };
+
+    if n_threads <= 1 {
+        func(0);
+    } else {
+        par_for_each(n_threads, func);
+    }
+}
+
+/// Sparse × dense matrix product: dst := alpha×dst + beta×lhs×rhs, where `lhs` is an `m×k`
+/// matrix in compressed sparse row format, `rhs` is `k×n` and `dst` is `m×n`, with
+/// `m = lhs.nrows` and `k = lhs.ncols`.
+///
+/// If `read_dst` is false, `alpha` is ignored and `dst` is not read. The rows of `dst` are split
+/// across threads in ranges with about the same number of nonzero entries.
+pub unsafe fn csr_gemm<T: SparseScalar>(
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: CsrMatrix<'_, T>,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    check_compressed(
+        lhs.nrows,
+        lhs.ncols,
+        lhs.row_ptr,
+        lhs.col_indices,
+        lhs.values,
+    );
+    dispatch!(csr_gemm_generic::<_, T>(
+        n,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        parallelism,
+    ))
+}
+
+/// Sparse × dense matrix product: dst := alpha×dst + beta×lhs×rhs, where `lhs` is an `m×k`
+/// matrix in compressed sparse column format, `rhs` is `k×n` and `dst` is `m×n`, with
+/// `m = lhs.nrows` and `k = lhs.ncols`.
+///
+/// If `read_dst` is false, `alpha` is ignored and `dst` is not read. The columns of `dst` are
+/// split across threads.
+pub unsafe fn csc_gemm<T: SparseScalar>(
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: CscMatrix<'_, T>,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    check_compressed(
+        lhs.ncols,
+        lhs.nrows,
+        lhs.col_ptr,
+        lhs.row_indices,
+        lhs.values,
+    );
+    dispatch!(csc_gemm_generic::<_, T>(
+        n,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        parallelism,
+    ))
+}
@@ -0,0 +1,264 @@
+//! Products of dense matrices with weights with 2:4 structured sparsity, where each group of four
+//! consecutive rows of a column holds at most two nonzero values.
+//!
+//! Only the two selected values of each group are stored, along with their 2-bit positions in
+//! the group, so the microkernel does half the multiplications of the dense product and gathers
+//! the matching columns of the packed lhs.
+
+use crate::{
+    cache::{div_ceil, KernelParams},
+    pack_operands::pack_lhs,
+    simd::Simd,
+    sparse::{dispatch, SparseScalar},
+    tiled::{gemm_tiled, tile_params, Tile},
+    Parallelism, Ptr,
+};
+
+pub const MR: usize = 16;
+pub const NR: usize = 4;
+
+/// `nrows×ncols` matrix with 2:4 structured sparsity along its columns.
+///
+/// Group `g` of column `j` covers the rows `4 * g..4 * g + 4`. Its two values are
+/// `values[j * values_cs + 2 * g]` and `values[j * values_cs + 2 * g + 1]`, and their rows in the
+/// group are the bits `0..2` and `2..4` of the nibble `g % 2` of `indices[j * indices_cs + g / 2]`.
+/// The rows of the last group past `nrows` are never selected.
+#[derive(Clone, Debug)]
+pub struct Sparse24Matrix<T> {
+    pub nrows: usize,
+    pub ncols: usize,
+    pub values: Vec<T>,
+    pub values_cs: usize,
+    pub indices: Vec<u8>,
+    pub indices_cs: usize,
+}
+
+impl<T: SparseScalar> Sparse24Matrix<T> {
+    /// Compresses the `nrows×ncols` matrix `src`, which must have at most two nonzero values in
+    /// each group of four consecutive rows of a column
+    pub unsafe fn from_dense(
+        nrows: usize,
+        ncols: usize,
+        src: *const T,
+        src_cs: isize,
+        src_rs: isize,
+    ) -> Self {
+        let n_groups = div_ceil(nrows, 4);
+        let values_cs = 2 * n_groups;
+        let indices_cs = div_ceil(n_groups, 2);
+        let mut values = vec![T::zero(); ncols * values_cs];
+        let mut indices = vec![0u8; ncols * indices_cs];
+
+        for j in 0..ncols {
+            for g in 0..n_groups {
+                let group_rows = 4.min(nrows - 4 * g);
+                let mut selected = [(0, T::zero()); 2];
+                let mut n_selected = 0;
+                for r in 0..group_rows {
+                    let x = *src.offset((4 * g + r) as isize * src_rs + j as isize * src_cs);
+                    if !x.is_zero() {
+                        assert!(n_selected < 2);
+                        selected[n_selected] = (r, x);
+                        n_selected += 1;
+                    }
+                }
+                // the unused slots hold zeros at the first row of the group, which always exists
+                let nibble = selected[0].0 | (selected[1].0 << 2);
+                values[j * values_cs + 2 * g] = selected[0].1;
+                values[j * values_cs + 2 * g + 1] = selected[1].1;
+                indices[j * indices_cs + g / 2] |= (nibble as u8) << (4 * (g % 2));
+            }
+        }
+
+        Self {
+            nrows,
+            ncols,
+            values,
+            values_cs,
+            indices,
+            indices_cs,
+        }
+    }
+}
+
+// `values` and `indices` point to the first group of the depth chunk, which is a multiple of 8
+// rows so that it starts on a byte of `indices`
+#[inline(always)]
+unsafe fn microkernel<T: SparseScalar>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    packed_lhs: *const T,
+    values: *const T,
+    values_cs: usize,
+    indices: *const u8,
+    indices_cs: usize,
+    read_dst: bool,
+    alpha: T,
+    beta: T,
+) {
+    let mut acc = [[T::zero(); MR]; NR];
+    for g in 0..div_ceil(k, 4) {
+        let lhs = packed_lhs.add(4 * g * MR);
+        for (j, acc) in acc.iter_mut().enumerate().take(n) {
+            let nibble = *indices.add(j * indices_cs + g / 2) >> (4 * (g % 2));
+            let v0 = *values.add(j * values_cs + 2 * g);
+            let v1 = *values.add(j * values_cs + 2 * g + 1);
+            let lhs0 = &*(lhs.add((nibble & 3) as usize * MR) as *const [T; MR]);
+            let lhs1 = &*(lhs.add(((nibble >> 2) & 3) as usize * MR) as *const [T; MR]);
+            for ((acc, &lhs0), &lhs1) in acc.iter_mut().zip(lhs0).zip(lhs1) {
+                *acc = *acc + lhs0 * v0 + lhs1 * v1;
+            }
+        }
+    }
+
+    for (j, acc) in acc.iter().enumerate().take(n) {
+        for (i, &acc) in acc.iter().enumerate().take(m) {
+            let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+            *dst = if read_dst {
+                alpha * *dst + beta * acc
+            } else {
+                beta * acc
+            };
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn gemm_sparse24_generic<S: Simd, T: SparseScalar>(
+    simd: S,
+    m: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: &Sparse24Matrix<T>,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    let n = rhs.ncols;
+    let k = rhs.nrows;
+    if m == 0 || n == 0 {
+        return;
+    }
+
+    if k == 0 {
+        for j in 0..n {
+            for i in 0..m {
+                let dst = dst.offset(i as isize * dst_rs + j as isize * dst_cs);
+                *dst = if read_dst { alpha * *dst } else { T::zero() };
+            }
+        }
+        return;
+    }
+
+    let params = tile_params::<T, MR, NR>(m, n, k, parallelism);
+    // depth chunks start on a byte of `indices`
+    let params = KernelParams {
+        kc: (params.kc / 8 * 8).max(8),
+        ..params
+    };
+
+    let dst = Ptr(dst);
+    let lhs = Ptr(lhs as *mut T);
+    let values = Ptr(rhs.values.as_ptr() as *mut T);
+    let indices = Ptr(rhs.indices.as_ptr() as *mut u8);
+    let values_cs = rhs.values_cs;
+    let indices_cs = rhs.indices_cs;
+
+    gemm_tiled::<T, MR, NR>(
+        m,
+        n,
+        k,
+        params,
+        parallelism,
+        |packed_lhs, stride, row, m_chunk, depth, k_chunk| {
+            pack_lhs::<T, MR, MR, _>(
+                simd,
+                m_chunk,
+                k_chunk,
+                packed_lhs,
+                lhs.wrapping_offset(row as isize * lhs_rs + depth as isize * lhs_cs),
+                lhs_cs,
+                lhs_rs,
+                stride,
+            )
+        },
+        None::<fn(Ptr<T>, usize, usize, usize, usize, usize)>,
+        |tile: Tile, packed_lhs, _| {
+            let dst = dst.wrapping_offset(tile.row as isize * dst_rs + tile.col as isize * dst_cs);
+            let values = values.wrapping_add(tile.col * values_cs + tile.depth / 2);
+            let indices = indices.wrapping_add(tile.col * indices_cs + tile.depth / 8);
+            let first = tile.depth == 0;
+
+            S::vectorize(
+                #[inline(always)]
+                || {
+                    microkernel::<T>(
+                        tile.m,
+                        tile.n,
+                        tile.k,
+                        dst.0,
+                        dst_cs,
+                        dst_rs,
+                        packed_lhs,
+                        values.0,
+                        values_cs,
+                        indices.0,
+                        indices_cs,
+                        read_dst || !first,
+                        if first { alpha } else { T::one() },
+                        beta,
+                    )
+                },
+            );
+        },
+    );
+}
+
+/// Dense × 2:4 structured-sparse matrix product: dst := alpha×dst + beta×lhs×rhs, where `lhs` is
+/// `m×k`, `dst` is `m×n`, with `k = rhs.nrows` and `n = rhs.ncols`.
+///
+/// If `read_dst` is false, `alpha` is ignored and `dst` is not read.
+pub unsafe fn gemm_sparse24<T: SparseScalar>(
+    m: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: &Sparse24Matrix<T>,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    let n_groups = div_ceil(rhs.nrows, 4);
+    assert!(rhs.values_cs >= 2 * n_groups);
+    assert!(rhs.indices_cs >= div_ceil(n_groups, 2));
+    assert!(rhs.values.len() >= rhs.ncols * rhs.values_cs);
+    assert!(rhs.indices.len() >= rhs.ncols * rhs.indices_cs);
+
+    dispatch!(gemm_sparse24_generic::<_, T>(
+        m,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        alpha,
+        beta,
+        parallelism,
+    ))
+}
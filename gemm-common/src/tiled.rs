@@ -0,0 +1,258 @@
+//! Cache blocked loop shared by the products with their own packing and microkernel.
+//!
+//! The output is split in `nc` wide column blocks, the depth in `kc` deep chunks and the rows in
+//! `mc` tall row blocks, like in the vectorized kernels. The rhs block is packed once per column
+//! block and depth chunk, then the `MR×NR` tiles are split across threads, each of them packing
+//! the row blocks of the lhs it needs into its [`L2_SLAB`].
+
+use crate::{
+    cache::{div_ceil, kernel_params, KernelParams},
+    gemm::{get_threading_threshold, par_for_each, CACHELINE_ALIGN, L2_SLAB},
+    Parallelism, Ptr,
+};
+use dyn_stack::{DynStack, GlobalMemBuffer, StackReq};
+
+/// Number of threads worth spawning for `work` multiply-adds
+pub(crate) fn n_threads(parallelism: Parallelism, work: usize) -> usize {
+    match parallelism {
+        Parallelism::None => 1,
+        Parallelism::Rayon(max_threads) => {
+            let threading_threshold = get_threading_threshold();
+            let max_threads = if max_threads == 0 {
+                rayon::current_num_threads()
+            } else {
+                max_threads
+            };
+            if work > threading_threshold {
+                std::cmp::max(
+                    1,
+                    std::cmp::min(
+                        max_threads,
+                        (work - threading_threshold + 1) / threading_threshold,
+                    ),
+                )
+            } else {
+                1
+            }
+        }
+    }
+}
+
+/// Jobs `job_start..job_end` out of `n_jobs` taken by thread `tid`
+#[inline(always)]
+pub(crate) fn job_range(tid: usize, n_threads: usize, n_jobs: usize) -> (usize, usize) {
+    let min_jobs_per_thread = n_jobs / n_threads;
+    let rem = n_jobs - n_threads * min_jobs_per_thread;
+
+    // thread `tid` takes min_jobs_per_thread or min_jobs_per_thread + 1
+    if tid < rem {
+        let start = tid * (min_jobs_per_thread + 1);
+        (start, start + min_jobs_per_thread + 1)
+    } else {
+        let start = tid * min_jobs_per_thread + rem;
+        (start, start + min_jobs_per_thread)
+    }
+}
+
+/// Block sizes of [`gemm_tiled`] for `MR×NR` tiles of `T`
+pub(crate) fn tile_params<T, const MR: usize, const NR: usize>(
+    m: usize,
+    n: usize,
+    k: usize,
+    parallelism: Parallelism,
+) -> KernelParams {
+    let KernelParams { kc, mc, nc } = kernel_params(m, n, k, MR, NR, core::mem::size_of::<T>());
+    let nc = if nc > 0 {
+        nc
+    } else {
+        match parallelism {
+            Parallelism::None => 128 * NR,
+            Parallelism::Rayon(_) => div_ceil(n, NR) * NR,
+        }
+    };
+    KernelParams { kc, mc, nc }
+}
+
+/// Tile of the output passed to the microkernel of [`gemm_tiled`]
+///
+/// The tile covers the rows `row..row + m` and the columns `col..col + n`, with `m <= MR` and
+/// `n <= NR`, and accumulates the depth chunk `depth..depth + k`. `depth == 0` on the first chunk.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Tile {
+    pub row: usize,
+    pub col: usize,
+    pub m: usize,
+    pub n: usize,
+    pub depth: usize,
+    pub k: usize,
+}
+
+/// Runs the blocked loop over the `m×n` output and the depth `k`.
+///
+/// `pack_lhs(packed, stride, row, m_chunk, depth, k_chunk)` packs the rows
+/// `row..row + m_chunk` and the depth chunk `depth..depth + k_chunk` of the lhs in panels of `MR`
+/// rows, `stride` elements apart. `pack_rhs` does the same with the columns, in panels of `NR`
+/// columns; if it is `None`, the rhs isn't packed and the microkernel gets a null packed rhs.
+/// `ukr(tile, packed_lhs, packed_rhs)` then accumulates each tile from its panels, every depth
+/// chunk of a tile being visited in order by a single thread.
+///
+/// # Safety
+///
+/// `params.mc` must be a multiple of `MR` and `params.nc` a multiple of `NR`, and the closures
+/// must only write to the packed panels they are given and to the tiles of the output.
+#[inline(always)]
+pub(crate) unsafe fn gemm_tiled<T, const MR: usize, const NR: usize>(
+    m: usize,
+    n: usize,
+    k: usize,
+    params: KernelParams,
+    parallelism: Parallelism,
+    pack_lhs: impl Fn(Ptr<T>, usize, usize, usize, usize, usize) + Sync,
+    pack_rhs: Option<impl Fn(Ptr<T>, usize, usize, usize, usize, usize) + Sync>,
+    ukr: impl Fn(Tile, *const T, *const T) + Sync,
+) {
+    let KernelParams { kc, mc, nc } = params;
+
+    let simd_align = CACHELINE_ALIGN.max(core::mem::align_of::<T>());
+
+    let packed_rhs_stride = if pack_rhs.is_some() { kc * NR } else { 0 };
+    let packed_lhs_stride = kc * MR;
+    let packed_rhs_len = packed_rhs_stride * (nc / NR);
+
+    let mut mem = GlobalMemBuffer::new(StackReq::new_aligned::<T>(packed_rhs_len, simd_align));
+    let stack = DynStack::new(&mut mem);
+    let mut packed_rhs_storage = stack.make_aligned_uninit::<T>(packed_rhs_len, simd_align).0;
+    let packed_rhs = Ptr(if pack_rhs.is_some() {
+        packed_rhs_storage.as_mut_ptr() as *mut T
+    } else {
+        core::ptr::null_mut()
+    });
+
+    let pack_lhs = &pack_lhs;
+    let ukr = &ukr;
+
+    let mut col_outer = 0;
+    while col_outer != n {
+        let n_chunk = nc.min(n - col_outer);
+        let n_col_mini_chunks = div_ceil(n_chunk, NR);
+
+        let mut depth_outer = 0;
+        while depth_outer != k {
+            let k_chunk = kc.min(k - depth_outer);
+
+            let n_threads = n_threads(parallelism, m * n_chunk * k_chunk);
+
+            if let Some(pack_rhs) = &pack_rhs {
+                let pack_rhs_cols = |col_inner: usize, ncols: usize| {
+                    pack_rhs(
+                        packed_rhs.wrapping_add((col_inner / NR) * packed_rhs_stride),
+                        packed_rhs_stride,
+                        col_outer + col_inner,
+                        ncols,
+                        depth_outer,
+                        k_chunk,
+                    );
+                };
+
+                if n_threads <= 1 {
+                    pack_rhs_cols(0, n_chunk);
+                } else {
+                    par_for_each(n_threads, |tid| {
+                        let (start, end) = job_range(tid, n_threads, n_col_mini_chunks);
+                        let col_inner = NR * start;
+                        let ncols = (NR * end).min(n_chunk) - col_inner;
+                        if ncols > 0 {
+                            pack_rhs_cols(col_inner, ncols);
+                        }
+                    });
+                }
+            }
+
+            let mut n_jobs = 0;
+            let mut row_outer = 0;
+            while row_outer != m {
+                let m_chunk = mc.min(m - row_outer);
+                n_jobs += n_col_mini_chunks * div_ceil(m_chunk, MR);
+                row_outer += m_chunk;
+            }
+
+            let func = move |tid| {
+                L2_SLAB.with(|mem| {
+                    let mut mem = mem.borrow_mut();
+                    let stack = DynStack::new(&mut mem);
+
+                    let (mut packed_lhs_storage, _) =
+                        stack.make_aligned_uninit::<T>(packed_lhs_stride * (mc / MR), simd_align);
+
+                    let packed_lhs = Ptr(packed_lhs_storage.as_mut_ptr() as *mut T);
+
+                    let (job_start, job_end) = job_range(tid, n_threads, n_jobs);
+
+                    let mut row_outer = 0;
+                    let mut job_id = 0;
+                    while row_outer != m {
+                        let m_chunk = mc.min(m - row_outer);
+                        let n_row_mini_chunks = div_ceil(m_chunk, MR);
+                        let n_mini_jobs = n_col_mini_chunks * n_row_mini_chunks;
+
+                        if job_id >= job_end {
+                            return;
+                        }
+                        if job_id + n_mini_jobs < job_start {
+                            row_outer += m_chunk;
+                            job_id += n_mini_jobs;
+                            continue;
+                        }
+
+                        pack_lhs(
+                            packed_lhs,
+                            packed_lhs_stride,
+                            row_outer,
+                            m_chunk,
+                            depth_outer,
+                            k_chunk,
+                        );
+
+                        for j in 0..n_col_mini_chunks {
+                            for i in 0..n_row_mini_chunks {
+                                if job_id < job_start || job_id >= job_end {
+                                    job_id += 1;
+                                    continue;
+                                }
+                                job_id += 1;
+
+                                let col_inner = NR * j;
+                                let row_inner = MR * i;
+                                let tile = Tile {
+                                    row: row_outer + row_inner,
+                                    col: col_outer + col_inner,
+                                    m: MR.min(m_chunk - row_inner),
+                                    n: NR.min(n_chunk - col_inner),
+                                    depth: depth_outer,
+                                    k: k_chunk,
+                                };
+
+                                ukr(
+                                    tile,
+                                    packed_lhs.wrapping_add(i * packed_lhs_stride).0,
+                                    packed_rhs.wrapping_add(j * packed_rhs_stride).0,
+                                );
+                            }
+                        }
+
+                        row_outer += m_chunk;
+                    }
+                });
+            };
+
+            if n_threads <= 1 {
+                func(0);
+            } else {
+                par_for_each(n_threads, func);
+            }
+
+            depth_outer += k_chunk;
+        }
+        col_outer += n_chunk;
+    }
+}
@@ -0,0 +1,651 @@
+use core::any::TypeId;
+use gemm_common::{
+    epilogue::Epilogue,
+    gemm::{gemm_multi_generic, GemmHook, GemmOutput},
+    microkernel::MicroKernelFn,
+    pack_operands::quick_zero,
+    simd::Simd,
+    Parallelism, Ptr,
+};
+use num_traits::AsPrimitive;
+
+use crate::rounding::{Narrow, Rounding, TileRounding};
+type T = u8;
+
+/// 8-bit floating point encoding of an operand
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fp8Format {
+    /// 4 exponent bits, 3 mantissa bits, bias 7. no infinities, `S.1111.111` is NaN, and the
+    /// largest finite value is 448
+    E4M3,
+    /// 5 exponent bits, 2 mantissa bits, bias 15. follows the IEEE 754 conventions for
+    /// infinities and NaN, and the largest finite value is 57344
+    E5M2,
+}
+
+/// Scale applied to the decoded values of an operand
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fp8Scale {
+    /// a single scale for the whole operand
+    PerTensor(f32),
+    /// one scale per row of `lhs`, or per column of `rhs` (i.e. per row of the transposed `rhs`,
+    /// as weight matrices are usually stored). the pointer must be valid for `m` (resp. `n`)
+    /// reads
+    PerRow(*const f32),
+}
+
+const fn decode_bits(code: u8, exp_bits: u32, man_bits: u32, ieee_special: bool) -> u32 {
+    let code = code as u32;
+    let sign = (code >> 7) << 31;
+    let exp = (code >> man_bits) & ((1 << exp_bits) - 1);
+    let man = code & ((1 << man_bits) - 1);
+    let bias = (1 << (exp_bits - 1)) - 1;
+
+    if exp == (1 << exp_bits) - 1 && (ieee_special || man == (1 << man_bits) - 1) {
+        if ieee_special && man == 0 {
+            sign | 0x7f80_0000
+        } else {
+            sign | 0x7fc0_0000
+        }
+    } else if exp == 0 {
+        if man == 0 {
+            sign
+        } else {
+            // subnormal: man × 2^(1 - bias - man_bits), renormalized around its leading bit
+            let p = 31 - man.leading_zeros();
+            let f32_exp = p + 128 - bias - man_bits;
+            sign | (f32_exp << 23) | ((man - (1 << p)) << (23 - p))
+        }
+    } else {
+        sign | ((exp + 127 - bias) << 23) | (man << (23 - man_bits))
+    }
+}
+
+const fn make_lut(exp_bits: u32, man_bits: u32, ieee_special: bool) -> [f32; 256] {
+    let mut lut = [0.0f32; 256];
+    let mut i = 0;
+    while i < 256 {
+        lut[i] = f32::from_bits(decode_bits(i as u8, exp_bits, man_bits, ieee_special));
+        i += 1;
+    }
+    lut
+}
+
+static E4M3_LUT: [f32; 256] = make_lut(4, 3, false);
+static E5M2_LUT: [f32; 256] = make_lut(5, 2, true);
+
+impl Fp8Format {
+    /// Returns the table mapping each code to its `f32` value
+    #[inline]
+    pub fn lut(self) -> &'static [f32; 256] {
+        match self {
+            Fp8Format::E4M3 => &E4M3_LUT,
+            Fp8Format::E5M2 => &E5M2_LUT,
+        }
+    }
+
+    #[inline]
+    pub fn to_f32(self, code: u8) -> f32 {
+        self.lut()[code as usize]
+    }
+}
+
+#[inline(always)]
+unsafe fn decode_scalar(
+    lut: &[f32; 256],
+    src: *const T,
+    dst: *mut f32,
+    scale: Option<*const f32>,
+    len: usize,
+) {
+    match scale {
+        Some(scale) => {
+            for j in 0..len {
+                *dst.add(j) = lut[*src.add(j) as usize] * *scale.add(j);
+            }
+        }
+        None => {
+            for j in 0..len {
+                *dst.add(j) = lut[*src.add(j) as usize];
+            }
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn decode_avx2(
+    lut: &[f32; 256],
+    src: *const T,
+    dst: *mut f32,
+    scale: Option<*const f32>,
+    len: usize,
+) {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::*;
+
+    let mut j = 0;
+    while j + 8 <= len {
+        let codes = _mm256_cvtepu8_epi32(_mm_loadl_epi64(src.add(j) as *const __m128i));
+        let mut val = _mm256_i32gather_ps::<4>(lut.as_ptr(), codes);
+        if let Some(scale) = scale {
+            val = _mm256_mul_ps(val, _mm256_loadu_ps(scale.add(j)));
+        }
+        _mm256_storeu_ps(dst.add(j), val);
+        j += 8;
+    }
+    decode_scalar(
+        lut,
+        src.add(j),
+        dst.add(j),
+        scale.map(|scale| scale.add(j)),
+        len - j,
+    );
+}
+
+// decodes `len` contiguous codes, multiplying each one by the matching scale if any
+type DecodeFn = unsafe fn(&[f32; 256], *const T, *mut f32, Option<*const f32>, usize);
+
+fn decode_fn() -> DecodeFn {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if gemm_common::feature_detected!("avx2") {
+        return decode_avx2;
+    }
+    decode_scalar
+}
+
+#[inline(always)]
+unsafe fn pack_generic_inner_loop<const DST_WIDTH: usize>(
+    mut dst: *mut f32,
+    mut src: *const T,
+    src_rs: isize,
+    src_cs: isize,
+    src_width: usize,
+    k: usize,
+    lut: &[f32; 256],
+    scale: Option<*const f32>,
+    decode: DecodeFn,
+) {
+    if src_width == DST_WIDTH && src_rs == 1 {
+        for _ in 0..k {
+            decode(lut, src, dst, scale, DST_WIDTH);
+            src = src.wrapping_offset(src_cs);
+            dst = dst.add(DST_WIDTH);
+        }
+    } else {
+        for _ in 0..k {
+            for j in 0..src_width {
+                let val = lut[*src.offset(j as isize * src_rs) as usize];
+                *dst.add(j) = match scale {
+                    Some(scale) => val * *scale.add(j),
+                    None => val,
+                };
+            }
+            quick_zero(core::slice::from_raw_parts_mut(
+                dst.add(src_width),
+                DST_WIDTH - src_width,
+            ));
+            src = src.wrapping_offset(src_cs);
+            dst = dst.add(DST_WIDTH);
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn pack_generic<const DST_WIDTH: usize>(
+    m: usize,
+    k: usize,
+    mut dst: *mut f32,
+    mut src: *const T,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+    lut: &[f32; 256],
+    mut scale: Option<*const f32>,
+    decode: DecodeFn,
+) {
+    let m_width = m / DST_WIDTH * DST_WIDTH;
+
+    let mut i = 0;
+    while i < m_width {
+        pack_generic_inner_loop::<DST_WIDTH>(
+            dst, src, src_rs, src_cs, DST_WIDTH, k, lut, scale, decode,
+        );
+        src = src.wrapping_offset(src_rs * DST_WIDTH as isize);
+        dst = dst.add(dst_stride);
+        scale = scale.map(|scale| scale.add(DST_WIDTH));
+
+        i += DST_WIDTH;
+    }
+    if i < m {
+        pack_generic_inner_loop::<DST_WIDTH>(
+            dst,
+            src,
+            src_rs,
+            src_cs,
+            m - i,
+            k,
+            lut,
+            scale,
+            decode,
+        );
+    }
+}
+
+// fp8 operands are decoded to `f32` while packing, and multiplied with the `f32` microkernels.
+// per-tensor scales are folded into `beta`, per-row scales are applied to the packed values.
+// an `f32` destination is accumulated into in place. `f16` and `bf16` destinations are only
+// written once each of their tiles is fully accumulated, and narrowed with the requested
+// rounding.
+struct Fp8<D> {
+    dst: Ptr<D>,
+    dst_cs: isize,
+    dst_rs: isize,
+    alpha: f32,
+    rounding: Rounding,
+    decode: DecodeFn,
+    lhs: Ptr<T>,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    lhs_lut: &'static [f32; 256],
+    lhs_scale: Option<Ptr<f32>>,
+    rhs: Ptr<T>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    rhs_lut: &'static [f32; 256],
+    rhs_scale: Option<Ptr<f32>>,
+}
+
+impl<D: Narrow + AsPrimitive<f32>> GemmHook<f32> for Fp8<D> {
+    #[inline(always)]
+    unsafe fn pack_lhs<const MR: usize>(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut f32,
+        row: usize,
+        depth: usize,
+        dst_stride: usize,
+    ) {
+        pack_generic::<MR>(
+            m,
+            k,
+            dst,
+            self.lhs
+                .wrapping_offset(row as isize * self.lhs_rs + depth as isize * self.lhs_cs)
+                .0,
+            self.lhs_cs,
+            self.lhs_rs,
+            dst_stride,
+            self.lhs_lut,
+            self.lhs_scale
+                .map(|scale| scale.wrapping_add(row).0 as *const f32),
+            self.decode,
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn pack_rhs<const NR: usize>(
+        &self,
+        _: usize,
+        n: usize,
+        k: usize,
+        dst: *mut f32,
+        depth: usize,
+        col: usize,
+        dst_stride: usize,
+    ) {
+        pack_generic::<NR>(
+            n,
+            k,
+            dst,
+            self.rhs
+                .wrapping_offset(depth as isize * self.rhs_rs + col as isize * self.rhs_cs)
+                .0,
+            self.rhs_rs,
+            self.rhs_cs,
+            dst_stride,
+            self.rhs_lut,
+            self.rhs_scale
+                .map(|scale| scale.wrapping_add(col).0 as *const f32),
+            self.decode,
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn store(
+        &self,
+        _: usize,
+        m: usize,
+        n: usize,
+        row: usize,
+        col: usize,
+        src: *const f32,
+        src_cs: isize,
+        src_rs: isize,
+    ) {
+        let alpha = self.alpha;
+        let mut rounding = TileRounding::new(self.rounding, row, col, 0);
+        for j in 0..n {
+            for i in 0..m {
+                let dst = self
+                    .dst
+                    .wrapping_offset(
+                        (row + i) as isize * self.dst_rs + (col + j) as isize * self.dst_cs,
+                    )
+                    .0;
+                let src = *src.offset(i as isize * src_rs + j as isize * src_cs);
+                *dst = rounding.narrow(if alpha == 0.0 {
+                    src
+                } else {
+                    alpha * (*dst).as_() + src
+                });
+            }
+        }
+    }
+}
+
+#[inline(always)]
+pub unsafe fn gemm_basic_generic<
+    S: Simd,
+    D: Narrow + Send + Sync + AsPrimitive<f32>,
+    const N: usize,
+    const MR: usize,
+    const NR: usize,
+    const MR_DIV_N: usize,
+>(
+    simd: S,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut D,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    lhs_format: Fp8Format,
+    lhs_scale: Fp8Scale,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    rhs_format: Fp8Format,
+    rhs_scale: Fp8Scale,
+    alpha: f32,
+    mut beta: f32,
+    dispatcher: &[[MicroKernelFn<f32>; NR]; MR_DIV_N],
+    parallelism: Parallelism,
+    rounding: Rounding,
+) {
+    let lhs_scale = match lhs_scale {
+        Fp8Scale::PerTensor(scale) => {
+            beta *= scale;
+            None
+        }
+        Fp8Scale::PerRow(scale) => Some(Ptr(scale as *mut f32)),
+    };
+    let rhs_scale = match rhs_scale {
+        Fp8Scale::PerTensor(scale) => {
+            beta *= scale;
+            None
+        }
+        Fp8Scale::PerRow(scale) => Some(Ptr(scale as *mut f32)),
+    };
+
+    let in_place = TypeId::of::<D>() == TypeId::of::<f32>();
+
+    gemm_multi_generic::<S, f32, N, MR, NR, MR_DIV_N>(
+        simd,
+        m,
+        k,
+        core::ptr::null(),
+        0,
+        0,
+        None,
+        None,
+        false,
+        &Fp8 {
+            dst: Ptr(dst),
+            dst_cs,
+            dst_rs,
+            alpha: if read_dst { alpha } else { 0.0 },
+            rounding,
+            decode: decode_fn(),
+            lhs: Ptr(lhs as *mut T),
+            lhs_cs,
+            lhs_rs,
+            lhs_lut: lhs_format.lut(),
+            lhs_scale,
+            rhs: Ptr(rhs as *mut T),
+            rhs_cs,
+            rhs_rs,
+            rhs_lut: rhs_format.lut(),
+            rhs_scale,
+        },
+        &[GemmOutput {
+            n,
+            dst: if in_place {
+                dst as *mut f32
+            } else {
+                core::ptr::null_mut()
+            },
+            dst_cs,
+            dst_rs,
+            read_dst: in_place && read_dst,
+            rhs: core::ptr::null(),
+            rhs_cs: 0,
+            rhs_rs: 0,
+            alpha,
+            beta,
+            epilogue: Epilogue::identity(),
+            mask: None,
+            dst_blocks: None,
+        }],
+        false,
+        false,
+        false,
+        dispatcher,
+        parallelism,
+        gemm_common::gemm::add_products,
+        |_, _| {},
+    );
+}
+
+macro_rules! inject_mod {
+    ($module: ident, $N: expr, $simd: ident) => {
+        mod $module {
+            use super::*;
+            use gemm_f32::microkernel::$module::f32::*;
+            const N: usize = $N;
+
+            #[inline(never)]
+            pub unsafe fn gemm_basic_with_rounding<D: Narrow + Send + Sync + AsPrimitive<f32>>(
+                m: usize,
+                n: usize,
+                k: usize,
+                dst: *mut D,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                lhs: *const T,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                lhs_format: Fp8Format,
+                lhs_scale: Fp8Scale,
+                rhs: *const T,
+                rhs_cs: isize,
+                rhs_rs: isize,
+                rhs_format: Fp8Format,
+                rhs_scale: Fp8Scale,
+                alpha: f32,
+                beta: f32,
+                parallelism: Parallelism,
+                rounding: Rounding,
+            ) {
+                gemm_basic_generic::<_, D, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    gemm_common::simd::$simd,
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    lhs_format,
+                    lhs_scale,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    rhs_format,
+                    rhs_scale,
+                    alpha,
+                    beta,
+                    &UKR,
+                    parallelism,
+                    rounding,
+                );
+            }
+
+            pub unsafe fn gemm_basic<D: Narrow + Send + Sync + AsPrimitive<f32>>(
+                m: usize,
+                n: usize,
+                k: usize,
+                dst: *mut D,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                lhs: *const T,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                lhs_format: Fp8Format,
+                lhs_scale: Fp8Scale,
+                rhs: *const T,
+                rhs_cs: isize,
+                rhs_rs: isize,
+                rhs_format: Fp8Format,
+                rhs_scale: Fp8Scale,
+                alpha: f32,
+                beta: f32,
+                parallelism: Parallelism,
+            ) {
+                gemm_basic_with_rounding::<D>(
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    lhs_format,
+                    lhs_scale,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    rhs_format,
+                    rhs_scale,
+                    alpha,
+                    beta,
+                    parallelism,
+                    Rounding::Nearest,
+                );
+            }
+        }
+    };
+}
+
+pub mod f8 {
+    use super::*;
+
+    type GemmTy<D> = unsafe fn(
+        usize,
+        usize,
+        usize,
+        *mut D,
+        isize,
+        isize,
+        bool,
+        *const T,
+        isize,
+        isize,
+        Fp8Format,
+        Fp8Scale,
+        *const T,
+        isize,
+        isize,
+        Fp8Format,
+        Fp8Scale,
+        f32,
+        f32,
+        Parallelism,
+    );
+    type GemmWithRoundingTy<D> = unsafe fn(
+        usize,
+        usize,
+        usize,
+        *mut D,
+        isize,
+        isize,
+        bool,
+        *const T,
+        isize,
+        isize,
+        Fp8Format,
+        Fp8Scale,
+        *const T,
+        isize,
+        isize,
+        Fp8Format,
+        Fp8Scale,
+        f32,
+        f32,
+        Parallelism,
+        Rounding,
+    );
+
+    fn init_gemm_fn<D: Narrow + Send + Sync + AsPrimitive<f32>>() -> GemmTy<D> {
+        gemm_common::__select_fn!(gemm_basic::<D>)
+    }
+
+    fn init_gemm_with_rounding_fn<D: Narrow + Send + Sync + AsPrimitive<f32>>(
+    ) -> GemmWithRoundingTy<D> {
+        gemm_common::__select_fn!(gemm_basic_with_rounding::<D>)
+    }
+
+    lazy_static::lazy_static! {
+        pub static ref GEMM_F32_DST: GemmTy<f32> = init_gemm_fn::<f32>();
+        pub static ref GEMM_F16_DST: GemmTy<half::f16> = init_gemm_fn::<half::f16>();
+        pub static ref GEMM_BF16_DST: GemmTy<half::bf16> = init_gemm_fn::<half::bf16>();
+        pub static ref GEMM_F32_DST_WITH_ROUNDING: GemmWithRoundingTy<f32> =
+            init_gemm_with_rounding_fn::<f32>();
+        pub static ref GEMM_F16_DST_WITH_ROUNDING: GemmWithRoundingTy<half::f16> =
+            init_gemm_with_rounding_fn::<half::f16>();
+        pub static ref GEMM_BF16_DST_WITH_ROUNDING: GemmWithRoundingTy<half::bf16> =
+            init_gemm_with_rounding_fn::<half::bf16>();
+    }
+
+    inject_mod!(scalar, 1, Scalar);
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    inject_mod!(sse, 4, Sse);
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    inject_mod!(avx, 8, Avx);
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    inject_mod!(fma, 8, Fma);
+    #[cfg(all(feature = "nightly", any(target_arch = "x86", target_arch = "x86_64")))]
+    inject_mod!(avx512f, 16, Avx512f);
+
+    #[cfg(target_arch = "aarch64")]
+    inject_mod!(neon, 4, Neon);
+
+    #[cfg(target_arch = "wasm32")]
+    inject_mod!(simd128, 4, Simd128);
+}
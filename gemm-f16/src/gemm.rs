@@ -7,6 +7,8 @@ use gemm_common::{
     Parallelism, Ptr,
 };
 use half::slice::HalfFloatSliceExt;
+
+use crate::rounding::{Rounding, TileRounding};
 type T = half::f16;
 
 #[inline(always)]
@@ -309,6 +311,7 @@ pub unsafe fn gemm_basic_generic<
     beta: T,
     dispatcher: &[[MicroKernelFn<f32>; NR]; MR_DIV_N],
     parallelism: Parallelism,
+    rounding: Rounding,
 ) {
     if m == 0 || n == 0 {
         return;
@@ -592,6 +595,13 @@ pub unsafe fn gemm_basic_generic<
                                     packed_lhs.wrapping_add((i + 1) * packed_lhs_stride).0,
                                 );
 
+                                let mut rounding = TileRounding::new(
+                                    rounding,
+                                    row_outer + row_inner,
+                                    col_outer + col_inner,
+                                    depth_outer,
+                                );
+
                                 match alpha_status {
                                     0 => {
                                         for j in 0..n_chunk_inner {
@@ -600,7 +610,7 @@ pub unsafe fn gemm_basic_generic<
                                                     .wrapping_offset(j as isize * dst_cs)
                                                     .wrapping_offset(i as isize * dst_rs)
                                                     .0;
-                                                *dst = T::from_f32(tmp[j][i]);
+                                                *dst = rounding.narrow(tmp[j][i]);
                                             }
                                         }
                                     }
@@ -611,7 +621,7 @@ pub unsafe fn gemm_basic_generic<
                                                     .wrapping_offset(j as isize * dst_cs)
                                                     .wrapping_offset(i as isize * dst_rs)
                                                     .0;
-                                                *dst = T::from_f32((*dst).to_f32() + tmp[j][i]);
+                                                *dst = rounding.narrow((*dst).to_f32() + tmp[j][i]);
                                             }
                                         }
                                     }
@@ -622,9 +632,8 @@ pub unsafe fn gemm_basic_generic<
                                                     .wrapping_offset(j as isize * dst_cs)
                                                     .wrapping_offset(i as isize * dst_rs)
                                                     .0;
-                                                *dst = T::from_f32(
-                                                    alpha * (*dst).to_f32() + tmp[j][i],
-                                                );
+                                                *dst = rounding
+                                                    .narrow(alpha * (*dst).to_f32() + tmp[j][i]);
                                             }
                                         }
                                     }
@@ -1033,6 +1042,7 @@ pub unsafe fn gemm_basic_f16<
 
 pub mod f16 {
     use super::gemm_basic_generic;
+    use crate::rounding::Rounding;
     use gemm_common::Parallelism;
 
     type T = half::f16;
@@ -1057,8 +1067,30 @@ pub mod f16 {
         bool,
         Parallelism,
     );
+    type GemmWithRoundingTy = unsafe fn(
+        usize,
+        usize,
+        usize,
+        *mut T,
+        isize,
+        isize,
+        bool,
+        *const T,
+        isize,
+        isize,
+        *const T,
+        isize,
+        isize,
+        T,
+        T,
+        bool,
+        bool,
+        bool,
+        Parallelism,
+        Rounding,
+    );
 
-    fn init_gemm_fn() -> GemmTy {
+    fn init_gemm_fn() -> GemmWithRoundingTy {
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
         {
             #[cfg(feature = "nightly")]
@@ -1100,8 +1132,54 @@ pub mod f16 {
         }
     }
 
+    unsafe fn gemm_nearest(
+        m: usize,
+        n: usize,
+        k: usize,
+        dst: *mut T,
+        dst_cs: isize,
+        dst_rs: isize,
+        read_dst: bool,
+        lhs: *const T,
+        lhs_cs: isize,
+        lhs_rs: isize,
+        rhs: *const T,
+        rhs_cs: isize,
+        rhs_rs: isize,
+        alpha: T,
+        beta: T,
+        conj_dst: bool,
+        conj_lhs: bool,
+        conj_rhs: bool,
+        parallelism: Parallelism,
+    ) {
+        (*GEMM_WITH_ROUNDING)(
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+            Rounding::Nearest,
+        )
+    }
+
     lazy_static::lazy_static! {
-        pub static ref GEMM: GemmTy = init_gemm_fn();
+        pub static ref GEMM: GemmTy = gemm_nearest;
+        pub static ref GEMM_WITH_ROUNDING: GemmWithRoundingTy = init_gemm_fn();
     }
 
     mod scalar {
@@ -1130,6 +1208,7 @@ pub mod f16 {
             _conj_lhs: bool,
             _conj_rhs: bool,
             parallelism: gemm_common::Parallelism,
+            rounding: Rounding,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N>(
                 m,
@@ -1149,6 +1228,7 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                rounding,
             );
         }
     }
@@ -1181,7 +1261,41 @@ pub mod f16 {
             _conj_lhs: bool,
             _conj_rhs: bool,
             parallelism: gemm_common::Parallelism,
+            rounding: Rounding,
         ) {
+            // the native f16 kernels round after every step, so stochastic rounding needs the
+            // f32 accumulators
+            if let Rounding::Stochastic { .. } = rounding {
+                use gemm_f32::microkernel::neon::f32 as f32_ukr;
+                const F32_N: usize = 4;
+
+                return gemm_basic_generic::<
+                    F32_N,
+                    { f32_ukr::MR_DIV_N * F32_N },
+                    { f32_ukr::NR },
+                    { f32_ukr::MR_DIV_N },
+                >(
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    alpha,
+                    beta,
+                    &f32_ukr::UKR,
+                    parallelism,
+                    rounding,
+                );
+            }
+
             crate::gemm::gemm_basic_f16::<N, { MR_DIV_N * N }, NR, MR_DIV_N>(
                 m,
                 n,
@@ -1231,6 +1345,7 @@ pub mod f16 {
             _conj_lhs: bool,
             _conj_rhs: bool,
             parallelism: gemm_common::Parallelism,
+            rounding: Rounding,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N>(
                 m,
@@ -1250,6 +1365,7 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                rounding,
             );
         }
     }
@@ -1281,6 +1397,7 @@ pub mod f16 {
             _conj_lhs: bool,
             _conj_rhs: bool,
             parallelism: gemm_common::Parallelism,
+            rounding: Rounding,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N>(
                 m,
@@ -1300,6 +1417,7 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                rounding,
             );
         }
     }
@@ -1331,6 +1449,7 @@ pub mod f16 {
             _conj_lhs: bool,
             _conj_rhs: bool,
             parallelism: gemm_common::Parallelism,
+            rounding: Rounding,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N>(
                 m,
@@ -1350,6 +1469,7 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                rounding,
             );
         }
     }
@@ -1381,6 +1501,7 @@ pub mod f16 {
             _conj_lhs: bool,
             _conj_rhs: bool,
             parallelism: gemm_common::Parallelism,
+            rounding: Rounding,
         ) {
             gemm_basic_generic::<N, { MR_DIV_N * N }, NR, MR_DIV_N>(
                 m,
@@ -1400,6 +1521,7 @@ pub mod f16 {
                 beta,
                 &UKR,
                 parallelism,
+                rounding,
             );
         }
     }
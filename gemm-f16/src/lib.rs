@@ -1,8 +1,10 @@
 #![cfg_attr(feature = "nightly", feature(stdsimd), feature(avx512_target_feature))]
 
+pub mod fp8;
 pub mod gemm;
 pub mod microkernel;
-pub use half::f16;
+pub mod rounding;
+pub use half::{bf16, f16};
 
 #[macro_use]
 extern crate gemm_common;
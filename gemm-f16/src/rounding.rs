@@ -0,0 +1,114 @@
+use half::{bf16, f16};
+
+/// Rounding applied when the `f32` accumulators are narrowed to a low precision destination
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// round to nearest, ties to even
+    Nearest,
+    /// round to one of the two neighboring values, with a probability proportional to the
+    /// proximity to each one, so that the expected value of the result is exact.
+    /// the random numbers are drawn from a stream determined by `seed` and the position of the
+    /// output tile, so that results are reproducible for a given seed, independently of the
+    /// number of threads
+    Stochastic { seed: u64 },
+}
+
+pub trait Narrow: Copy + 'static {
+    fn from_f32_nearest(value: f32) -> Self;
+    /// `random` is uniformly distributed over `u32`
+    fn from_f32_stochastic(value: f32, random: u32) -> Self;
+}
+
+impl Narrow for f32 {
+    #[inline(always)]
+    fn from_f32_nearest(value: f32) -> Self {
+        value
+    }
+    #[inline(always)]
+    fn from_f32_stochastic(value: f32, _: u32) -> Self {
+        value
+    }
+}
+
+macro_rules! impl_narrow {
+    ($ty: ty) => {
+        impl Narrow for $ty {
+            #[inline(always)]
+            fn from_f32_nearest(value: f32) -> Self {
+                <$ty>::from_f32(value)
+            }
+
+            #[inline]
+            fn from_f32_stochastic(value: f32, random: u32) -> Self {
+                let nearest = <$ty>::from_f32(value);
+                let nearest_f32 = nearest.to_f32();
+                if !nearest.is_finite() || nearest_f32 == value {
+                    return nearest;
+                }
+
+                // the neighbor on the other side of `value`, one ulp away from `nearest`
+                let other = if nearest_f32.abs() < value.abs() {
+                    <$ty>::from_bits(nearest.to_bits() + 1)
+                } else {
+                    <$ty>::from_bits(nearest.to_bits() - 1)
+                };
+                let p = (value - nearest_f32).abs() / (other.to_f32() - nearest_f32).abs();
+                let u = (random >> 8) as f32 * (1.0 / (1u32 << 24) as f32);
+
+                if u < p {
+                    other
+                } else {
+                    nearest
+                }
+            }
+        }
+    };
+}
+
+impl_narrow!(f16);
+impl_narrow!(bf16);
+
+#[inline(always)]
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+/// Narrows the values stored to one output tile, drawing from the tile's random stream if
+/// rounding stochastically
+#[derive(Copy, Clone, Debug)]
+pub struct TileRounding(Option<u64>);
+
+impl TileRounding {
+    /// `row` and `col` are the position of the first element of the tile in `dst`, and `depth`
+    /// the first index of the `k` block whose result is being stored, for drivers that store
+    /// to `dst` more than once
+    #[inline(always)]
+    pub fn new(rounding: Rounding, row: usize, col: usize, depth: usize) -> Self {
+        match rounding {
+            Rounding::Nearest => Self(None),
+            Rounding::Stochastic { seed } => {
+                let mut state = seed;
+                for x in [row, col, depth] {
+                    state = mix(state ^ mix((x as u64).wrapping_add(GOLDEN_GAMMA)));
+                }
+                Self(Some(state))
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn narrow<D: Narrow>(&mut self, value: f32) -> D {
+        match &mut self.0 {
+            None => D::from_f32_nearest(value),
+            Some(state) => {
+                // splitmix64
+                *state = state.wrapping_add(GOLDEN_GAMMA);
+                D::from_f32_stochastic(value, (mix(*state) >> 32) as u32)
+            }
+        }
+    }
+}
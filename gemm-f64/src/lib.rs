@@ -2,6 +2,7 @@
 
 pub mod gemm;
 mod microkernel;
+pub mod widening;
 
 #[macro_use]
 extern crate gemm_common;
@@ -24,6 +24,13 @@ pub mod scalar {
             add(mul(a, b), c)
         }
 
+        #[inline(always)]
+        unsafe fn sub(lhs: Pack, rhs: Pack) -> Pack {
+            [lhs[0] - rhs[0]]
+        }
+
+        two_prod_dekker!();
+
         microkernel!(, 2, x1x1, 1, 1);
         microkernel!(, 2, x1x2, 1, 2);
         microkernel!(, 2, x1x3, 1, 3);
@@ -38,6 +45,8 @@ pub mod scalar {
             [x1x1, x1x2, x1x3, x1x4,],
             [x2x1, x2x2, x2x3, x2x4,],
         }
+
+        microkernel_compensated!(, x2x2_compensated, 2, 2);
     }
 }
 
@@ -74,6 +83,13 @@ pub mod sse {
             add(mul(a, b), c)
         }
 
+        #[inline(always)]
+        unsafe fn sub(lhs: Pack, rhs: Pack) -> Pack {
+            transmute(_mm_sub_pd(transmute(lhs), transmute(rhs)))
+        }
+
+        two_prod_dekker!();
+
         microkernel!(["sse,sse2"], 2, x1x1, 1, 1);
         microkernel!(["sse,sse2"], 2, x1x2, 1, 2);
         microkernel!(["sse,sse2"], 2, x1x3, 1, 3);
@@ -88,6 +104,8 @@ pub mod sse {
             [x1x1, x1x2, x1x3, x1x4,],
             [x2x1, x2x2, x2x3, x2x4,],
         }
+
+        microkernel_compensated!(["sse,sse2"], x2x2_compensated, 2, 2);
     }
 }
 
@@ -124,6 +142,13 @@ pub mod avx {
             add(mul(a, b), c)
         }
 
+        #[inline(always)]
+        unsafe fn sub(lhs: Pack, rhs: Pack) -> Pack {
+            transmute(_mm256_sub_pd(transmute(lhs), transmute(rhs)))
+        }
+
+        two_prod_dekker!();
+
         microkernel!(["avx"], 2, x1x1, 1, 1);
         microkernel!(["avx"], 2, x1x2, 1, 2);
         microkernel!(["avx"], 2, x1x3, 1, 3);
@@ -138,6 +163,8 @@ pub mod avx {
             [x1x1, x1x2, x1x3, x1x4,],
             [x2x1, x2x2, x2x3, x2x4,],
         }
+
+        microkernel_compensated!(["avx"], x2x2_compensated, 2, 2);
     }
 }
 
@@ -174,6 +201,20 @@ pub mod fma {
             transmute(_mm256_fmadd_pd(transmute(a), transmute(b), transmute(c)))
         }
 
+        #[inline(always)]
+        unsafe fn sub(lhs: Pack, rhs: Pack) -> Pack {
+            transmute(_mm256_sub_pd(transmute(lhs), transmute(rhs)))
+        }
+
+        #[inline(always)]
+        unsafe fn two_prod(a: Pack, b: Pack) -> (Pack, Pack) {
+            let p = mul(a, b);
+            (
+                p,
+                transmute(_mm256_fmsub_pd(transmute(a), transmute(b), transmute(p))),
+            )
+        }
+
         microkernel!(["fma"], 2, x1x1, 1, 1);
         microkernel!(["fma"], 2, x1x2, 1, 2);
         microkernel!(["fma"], 2, x1x3, 1, 3);
@@ -194,6 +235,8 @@ pub mod fma {
             [x2x1, x2x2, x2x3, x2x4,],
             [x3x1, x3x2, x3x3, x3x4,],
         }
+
+        microkernel_compensated!(["fma"], x2x2_compensated, 2, 2);
     }
 }
 
@@ -230,6 +273,20 @@ pub mod avx512f {
             transmute(_mm512_fmadd_pd(transmute(a), transmute(b), transmute(c)))
         }
 
+        #[inline(always)]
+        unsafe fn sub(lhs: Pack, rhs: Pack) -> Pack {
+            transmute(_mm512_sub_pd(transmute(lhs), transmute(rhs)))
+        }
+
+        #[inline(always)]
+        unsafe fn two_prod(a: Pack, b: Pack) -> (Pack, Pack) {
+            let p = mul(a, b);
+            (
+                p,
+                transmute(_mm512_fmsub_pd(transmute(a), transmute(b), transmute(p))),
+            )
+        }
+
         microkernel!(["avx512f"], 4, x1x1, 1, 1);
         microkernel!(["avx512f"], 4, x1x2, 1, 2);
         microkernel!(["avx512f"], 4, x1x3, 1, 3);
@@ -262,6 +319,8 @@ pub mod avx512f {
             [x2x1, x2x2, x2x3, x2x4, x2x5, x2x6, x2x7, x2x8,],
             [x3x1, x3x2, x3x3, x3x4, x3x5, x3x6, x3x7, x3x8,],
         }
+
+        microkernel_compensated!(["avx512f"], x2x4_compensated, 2, 4);
     }
 }
 
@@ -309,6 +368,13 @@ pub mod simd128 {
             add(mul(a, b), c)
         }
 
+        #[inline(always)]
+        unsafe fn sub(lhs: Pack, rhs: Pack) -> Pack {
+            transmute(f64x2_sub(transmute(lhs), transmute(rhs)))
+        }
+
+        two_prod_dekker!();
+
         microkernel!(["simd128"], 2, x1x1, 1, 1);
         microkernel!(["simd128"], 2, x1x2, 1, 2);
         microkernel!(["simd128"], 2, x1x3, 1, 3);
@@ -323,6 +389,8 @@ pub mod simd128 {
             [x1x1, x1x2, x1x3, x1x4,],
             [x2x1, x2x2, x2x3, x2x4,],
         }
+
+        microkernel_compensated!(["simd128"], x2x2_compensated, 2, 2);
     }
 }
 
@@ -358,6 +426,24 @@ pub mod neon {
             ))
         }
 
+        #[inline(always)]
+        pub unsafe fn sub(lhs: Pack, rhs: Pack) -> Pack {
+            transmute(vsubq_f64(transmute(lhs), transmute(rhs)))
+        }
+
+        #[inline(always)]
+        pub unsafe fn two_prod(a: Pack, b: Pack) -> (Pack, Pack) {
+            let p = mul(a, b);
+            (
+                p,
+                transmute(vfmaq_f64(
+                    vnegq_f64(transmute(p)),
+                    transmute(a),
+                    transmute(b),
+                )),
+            )
+        }
+
         microkernel!(["neon"], 2, x1x1, 1, 1);
         microkernel!(["neon"], 2, x1x2, 1, 2, 1, 2);
         microkernel!(["neon"], 2, x1x3, 1, 3);
@@ -390,5 +476,7 @@ pub mod neon {
             [x2x1, x2x2, x2x3, x2x4, x2x5, x2x6, x2x7, x2x8,],
             [x3x1, x3x2, x3x3, x3x4, x3x5, x3x6, x3x7, x3x8,],
         }
+
+        microkernel_compensated!(["neon"], x2x4_compensated, 2, 4);
     }
 }
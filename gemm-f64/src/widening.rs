@@ -0,0 +1,356 @@
+use core::any::TypeId;
+use gemm_common::{
+    epilogue::Epilogue,
+    gemm::{gemm_multi_generic, GemmHook, GemmOutput},
+    microkernel::MicroKernelFn,
+    pack_operands::quick_zero,
+    simd::Simd,
+    Parallelism, Ptr,
+};
+use num_traits::AsPrimitive;
+type T = f32;
+
+#[inline(always)]
+unsafe fn pack_generic_inner_loop<const DST_WIDTH: usize>(
+    mut dst: *mut f64,
+    mut src: *const T,
+    src_rs: isize,
+    src_cs: isize,
+    src_width: usize,
+    k: usize,
+) {
+    if src_width == DST_WIDTH && src_rs == 1 {
+        for _ in 0..k {
+            let val = (src as *const [T; DST_WIDTH]).read();
+            for (j, val) in val.into_iter().enumerate() {
+                *dst.add(j) = val as f64;
+            }
+            src = src.wrapping_offset(src_cs);
+            dst = dst.add(DST_WIDTH);
+        }
+    } else {
+        for _ in 0..k {
+            for j in 0..src_width {
+                *dst.add(j) = *src.offset(j as isize * src_rs) as f64;
+            }
+            quick_zero(core::slice::from_raw_parts_mut(
+                dst.add(src_width),
+                DST_WIDTH - src_width,
+            ));
+            src = src.wrapping_offset(src_cs);
+            dst = dst.add(DST_WIDTH);
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn pack_generic<const DST_WIDTH: usize>(
+    m: usize,
+    k: usize,
+    mut dst: *mut f64,
+    mut src: *const T,
+    src_cs: isize,
+    src_rs: isize,
+    dst_stride: usize,
+) {
+    let m_width = m / DST_WIDTH * DST_WIDTH;
+
+    let mut i = 0;
+    while i < m_width {
+        pack_generic_inner_loop::<DST_WIDTH>(dst, src, src_rs, src_cs, DST_WIDTH, k);
+        src = src.wrapping_offset(src_rs * DST_WIDTH as isize);
+        dst = dst.add(dst_stride);
+
+        i += DST_WIDTH;
+    }
+    if i < m {
+        pack_generic_inner_loop::<DST_WIDTH>(dst, src, src_rs, src_cs, m - i, k);
+    }
+}
+
+// `f32` operands are widened to `f64` while they are packed, and multiplied with the `f64`
+// microkernels. an `f64` destination is accumulated into in place, and an `f32` destination is
+// only written once each of its tiles is fully accumulated.
+struct Widening<D> {
+    dst: Ptr<D>,
+    dst_cs: isize,
+    dst_rs: isize,
+    alpha: f64,
+    lhs: Ptr<T>,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: Ptr<T>,
+    rhs_cs: isize,
+    rhs_rs: isize,
+}
+
+impl<D: Copy + 'static + AsPrimitive<f64>> GemmHook<f64> for Widening<D>
+where
+    f64: AsPrimitive<D>,
+{
+    #[inline(always)]
+    unsafe fn pack_lhs<const MR: usize>(
+        &self,
+        m: usize,
+        k: usize,
+        dst: *mut f64,
+        row: usize,
+        depth: usize,
+        dst_stride: usize,
+    ) {
+        pack_generic::<MR>(
+            m,
+            k,
+            dst,
+            self.lhs
+                .wrapping_offset(row as isize * self.lhs_rs + depth as isize * self.lhs_cs)
+                .0,
+            self.lhs_cs,
+            self.lhs_rs,
+            dst_stride,
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn pack_rhs<const NR: usize>(
+        &self,
+        _: usize,
+        n: usize,
+        k: usize,
+        dst: *mut f64,
+        depth: usize,
+        col: usize,
+        dst_stride: usize,
+    ) {
+        pack_generic::<NR>(
+            n,
+            k,
+            dst,
+            self.rhs
+                .wrapping_offset(depth as isize * self.rhs_rs + col as isize * self.rhs_cs)
+                .0,
+            self.rhs_rs,
+            self.rhs_cs,
+            dst_stride,
+        );
+    }
+
+    #[inline(always)]
+    unsafe fn store(
+        &self,
+        _: usize,
+        m: usize,
+        n: usize,
+        row: usize,
+        col: usize,
+        src: *const f64,
+        src_cs: isize,
+        src_rs: isize,
+    ) {
+        let alpha = self.alpha;
+        for j in 0..n {
+            for i in 0..m {
+                let dst = self
+                    .dst
+                    .wrapping_offset(
+                        (row + i) as isize * self.dst_rs + (col + j) as isize * self.dst_cs,
+                    )
+                    .0;
+                let src = *src.offset(i as isize * src_rs + j as isize * src_cs);
+                *dst = if alpha == 0.0 {
+                    src.as_()
+                } else {
+                    (alpha * (*dst).as_() + src).as_()
+                };
+            }
+        }
+    }
+}
+
+#[inline(always)]
+pub unsafe fn gemm_basic_generic<
+    S: Simd,
+    D: Copy + Send + Sync + 'static + AsPrimitive<f64>,
+    const N: usize,
+    const MR: usize,
+    const NR: usize,
+    const MR_DIV_N: usize,
+>(
+    simd: S,
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut D,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: f64,
+    beta: f64,
+    dispatcher: &[[MicroKernelFn<f64>; NR]; MR_DIV_N],
+    parallelism: Parallelism,
+) where
+    f64: AsPrimitive<D>,
+{
+    let in_place = TypeId::of::<D>() == TypeId::of::<f64>();
+
+    gemm_multi_generic::<S, f64, N, MR, NR, MR_DIV_N>(
+        simd,
+        m,
+        k,
+        core::ptr::null(),
+        0,
+        0,
+        None,
+        None,
+        false,
+        &Widening {
+            dst: Ptr(dst),
+            dst_cs,
+            dst_rs,
+            alpha: if read_dst { alpha } else { 0.0 },
+            lhs: Ptr(lhs as *mut T),
+            lhs_cs,
+            lhs_rs,
+            rhs: Ptr(rhs as *mut T),
+            rhs_cs,
+            rhs_rs,
+        },
+        &[GemmOutput {
+            n,
+            dst: if in_place {
+                dst as *mut f64
+            } else {
+                core::ptr::null_mut()
+            },
+            dst_cs,
+            dst_rs,
+            read_dst: in_place && read_dst,
+            rhs: core::ptr::null(),
+            rhs_cs: 0,
+            rhs_rs: 0,
+            alpha,
+            beta,
+            epilogue: Epilogue::identity(),
+            mask: None,
+            dst_blocks: None,
+        }],
+        false,
+        false,
+        false,
+        dispatcher,
+        parallelism,
+        gemm_common::gemm::add_products,
+        |_, _| {},
+    );
+}
+
+macro_rules! inject_mod {
+    ($module: ident, $N: expr, $simd: ident) => {
+        mod $module {
+            use super::*;
+            use crate::microkernel::$module::f64::*;
+            const N: usize = $N;
+
+            #[inline(never)]
+            pub unsafe fn gemm_basic<D: Copy + Send + Sync + 'static + AsPrimitive<f64>>(
+                m: usize,
+                n: usize,
+                k: usize,
+                dst: *mut D,
+                dst_cs: isize,
+                dst_rs: isize,
+                read_dst: bool,
+                lhs: *const T,
+                lhs_cs: isize,
+                lhs_rs: isize,
+                rhs: *const T,
+                rhs_cs: isize,
+                rhs_rs: isize,
+                alpha: f64,
+                beta: f64,
+                parallelism: Parallelism,
+            ) where
+                f64: AsPrimitive<D>,
+            {
+                gemm_basic_generic::<_, D, N, { MR_DIV_N * N }, NR, MR_DIV_N>(
+                    gemm_common::simd::$simd,
+                    m,
+                    n,
+                    k,
+                    dst,
+                    dst_cs,
+                    dst_rs,
+                    read_dst,
+                    lhs,
+                    lhs_cs,
+                    lhs_rs,
+                    rhs,
+                    rhs_cs,
+                    rhs_rs,
+                    alpha,
+                    beta,
+                    &UKR,
+                    parallelism,
+                );
+            }
+        }
+    };
+}
+
+pub mod f32 {
+    use super::*;
+
+    type GemmTy<D> = unsafe fn(
+        usize,
+        usize,
+        usize,
+        *mut D,
+        isize,
+        isize,
+        bool,
+        *const T,
+        isize,
+        isize,
+        *const T,
+        isize,
+        isize,
+        f64,
+        f64,
+        Parallelism,
+    );
+
+    fn init_gemm_fn<D: Copy + Send + Sync + 'static + AsPrimitive<f64>>() -> GemmTy<D>
+    where
+        f64: AsPrimitive<D>,
+    {
+        gemm_common::__select_fn!(gemm_basic::<D>)
+    }
+
+    lazy_static::lazy_static! {
+        pub static ref GEMM_F32_DST: GemmTy<f32> = init_gemm_fn::<f32>();
+        pub static ref GEMM_F64_DST: GemmTy<f64> = init_gemm_fn::<f64>();
+    }
+
+    inject_mod!(scalar, 1, Scalar);
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    inject_mod!(sse, 2, Sse);
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    inject_mod!(avx, 4, Avx);
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    inject_mod!(fma, 4, Fma);
+    #[cfg(all(feature = "nightly", any(target_arch = "x86", target_arch = "x86_64")))]
+    inject_mod!(avx512f, 8, Avx512f);
+
+    #[cfg(target_arch = "aarch64")]
+    inject_mod!(neon, 2, Neon);
+
+    #[cfg(target_arch = "wasm32")]
+    inject_mod!(simd128, 2, Simd128);
+}
@@ -0,0 +1,224 @@
+use crate::{FactorizationError, MatMut, NB};
+use core::any::TypeId;
+use gemm::{
+    c32, c64, dispatch_scalar, herk, syrk, trsm, Diag, Parallelism, Scalar, Side, Triangle,
+};
+
+// a := l with l×lᴴ = a, for an `nb×nb` diagonal block
+unsafe fn potrf_diag_block<U: Scalar>(nb: usize, a: MatMut<U>) -> Result<(), usize> {
+    for j in 0..nb {
+        let mut d = (*a.at(j, j)).real();
+        for p in 0..j {
+            let x = *a.at(j, p);
+            d -= (x * x.conj()).real();
+        }
+        if d <= 0.0 || d.is_nan() {
+            return Err(j);
+        }
+        let d = U::from_real(d.sqrt());
+        *a.at(j, j) = d;
+
+        for i in j + 1..nb {
+            let mut acc = *a.at(i, j);
+            for p in 0..j {
+                acc = acc - *a.at(i, p) * (*a.at(j, p)).conj();
+            }
+            *a.at(i, j) = acc / d;
+        }
+    }
+    Ok(())
+}
+
+// the upper factor is the transpose of the lower factor of the transposed matrix, so both are
+// computed from a lower triangular view
+#[inline(always)]
+fn lower_view(triangle: Triangle, a_cs: isize, a_rs: isize) -> (isize, isize) {
+    match triangle {
+        Triangle::Lower => (a_cs, a_rs),
+        Triangle::Upper => (a_rs, a_cs),
+    }
+}
+
+unsafe fn potrf_imp<T: 'static, U: Scalar>(
+    triangle: Triangle,
+    n: usize,
+    a: *mut T,
+    a_cs: isize,
+    a_rs: isize,
+    parallelism: Parallelism,
+) -> Result<(), FactorizationError> {
+    let (a_cs, a_rs) = lower_view(triangle, a_cs, a_rs);
+    let a = MatMut {
+        ptr: a as *mut U,
+        cs: a_cs,
+        rs: a_rs,
+    };
+    let is_cplx =
+        TypeId::of::<U>() == TypeId::of::<c32>() || TypeId::of::<U>() == TypeId::of::<c64>();
+
+    let mut col = 0;
+    while col < n {
+        let nb = NB.min(n - col);
+        potrf_diag_block(
+            nb,
+            MatMut {
+                ptr: a.at(col, col),
+                ..a
+            },
+        )
+        .map_err(|j| FactorizationError::NotPositiveDefinite { index: col + j })?;
+
+        let rest = n - col - nb;
+        if rest > 0 {
+            // a21 := a21×l11⁻ᴴ
+            trsm(
+                Side::Right,
+                Triangle::Upper,
+                Diag::NonUnit,
+                rest,
+                nb,
+                a.at(col, col),
+                a_rs,
+                a_cs,
+                true,
+                a.at(col + nb, col),
+                a_cs,
+                a_rs,
+                U::one(),
+                parallelism,
+            );
+            // a22 := a22 - a21×a21ᴴ
+            (if is_cplx { herk } else { syrk })(
+                rest,
+                nb,
+                a.at(col + nb, col + nb),
+                a_cs,
+                a_rs,
+                true,
+                a.at(col + nb, col),
+                a_cs,
+                a_rs,
+                U::one(),
+                -U::one(),
+                Triangle::Lower,
+                parallelism,
+            );
+        }
+        col += nb;
+    }
+    Ok(())
+}
+
+unsafe fn potrs_imp<T: 'static, U: Scalar>(
+    triangle: Triangle,
+    n: usize,
+    nrhs: usize,
+    a: *const T,
+    a_cs: isize,
+    a_rs: isize,
+    b: *mut T,
+    b_cs: isize,
+    b_rs: isize,
+    parallelism: Parallelism,
+) {
+    // the lower view holds conj(l) if the factor is upper
+    let (a_cs, a_rs) = lower_view(triangle, a_cs, a_rs);
+    let conj = triangle == Triangle::Upper;
+    let a = a as *const U;
+    let b = b as *mut U;
+
+    // l×y = b
+    trsm(
+        Side::Left,
+        Triangle::Lower,
+        Diag::NonUnit,
+        n,
+        nrhs,
+        a,
+        a_cs,
+        a_rs,
+        conj,
+        b,
+        b_cs,
+        b_rs,
+        U::one(),
+        parallelism,
+    );
+    // lᴴ×x = y
+    trsm(
+        Side::Left,
+        Triangle::Upper,
+        Diag::NonUnit,
+        n,
+        nrhs,
+        a,
+        a_rs,
+        a_cs,
+        !conj,
+        b,
+        b_cs,
+        b_rs,
+        U::one(),
+        parallelism,
+    );
+}
+
+/// Cholesky factorization of the `n×n` hermitian positive definite matrix `a`, in place.
+///
+/// Only the `triangle` of `a` is read, and it is overwritten by the factor `l` with a = l×lᴴ if
+/// `triangle` is [`Triangle::Lower`], or by `u` with a = uᴴ×u if it is [`Triangle::Upper`]. The
+/// other triangle is neither read nor written.
+///
+/// The factorization is right looking: each diagonal block is factored directly, then the block
+/// column below it is solved with [`trsm`], and the trailing matrix is updated with [`syrk`] or
+/// [`herk`].
+///
+/// # Errors
+///
+/// Returns [`FactorizationError::NotPositiveDefinite`] if `a` is not positive definite. The
+/// columns before `index` then hold the partial factor.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `c32` or `c64`
+pub unsafe fn potrf<T: 'static>(
+    triangle: Triangle,
+    n: usize,
+    a: *mut T,
+    a_cs: isize,
+    a_rs: isize,
+    parallelism: Parallelism,
+) -> Result<(), FactorizationError> {
+    dispatch_scalar!(
+        T,
+        [f64, f32, c64, c32],
+        potrf_imp,
+        (triangle, n, a, a_cs, a_rs, parallelism)
+    )
+}
+
+/// Solves a×x = b in place for the `n×nrhs` matrix `b`, where `a` was factored by [`potrf`] with
+/// the same `triangle`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `c32` or `c64`
+pub unsafe fn potrs<T: 'static>(
+    triangle: Triangle,
+    n: usize,
+    nrhs: usize,
+    a: *const T,
+    a_cs: isize,
+    a_rs: isize,
+    b: *mut T,
+    b_cs: isize,
+    b_rs: isize,
+    parallelism: Parallelism,
+) {
+    dispatch_scalar!(
+        T,
+        [f64, f32, c64, c32],
+        potrs_imp,
+        (triangle, n, nrhs, a, a_cs, a_rs, b, b_cs, b_rs, parallelism)
+    )
+}
@@ -0,0 +1,339 @@
+#![warn(rust_2018_idioms)]
+
+//! Blocked factorizations on top of the [`gemm`] kernels.
+//!
+//! The diagonal blocks are factored directly, and the trailing matrix is updated with
+//! [`gemm::gemm`], [`gemm::syrk`] or [`gemm::herk`], and [`gemm::trsm`].
+
+mod cholesky;
+mod lu;
+
+pub use crate::cholesky::{potrf, potrs};
+pub use crate::lu::{getrf, getrs};
+
+/// Error returned by a factorization
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FactorizationError {
+    /// the leading minor of order `index + 1` is not positive definite, and the factorization
+    /// stopped before that column
+    NotPositiveDefinite { index: usize },
+    /// the pivot of column `index` is exactly zero. the factorization is still completed, but the
+    /// factor `u` is singular
+    Singular { index: usize },
+}
+
+// columns of the diagonal blocks that are factored directly, the rest of the work goes to the
+// level 3 kernels
+const NB: usize = 64;
+
+// a matrix view with arbitrary strides
+struct MatMut<U> {
+    ptr: *mut U,
+    cs: isize,
+    rs: isize,
+}
+
+impl<U> Copy for MatMut<U> {}
+impl<U> Clone for MatMut<U> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<U> MatMut<U> {
+    #[inline(always)]
+    fn at(self, i: usize, j: usize) -> *mut U {
+        self.ptr
+            .wrapping_offset(i as isize * self.rs + j as isize * self.cs)
+    }
+
+    // swaps rows `i` and `p` on the columns `col_start..col_end`
+    #[inline(always)]
+    unsafe fn swap_rows(self, i: usize, p: usize, col_start: usize, col_end: usize) {
+        if i != p {
+            for j in col_start..col_end {
+                core::ptr::swap(self.at(i, j), self.at(p, j));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gemm::{c32, c64, gemm, Parallelism, Triangle};
+
+    // scalars of the tests, checked against a reference computed on `(re, im)` pairs of `f64`,
+    // where the real types drop the imaginary part
+    trait RefScalar: 'static + Copy {
+        fn from_pair(re: f64, im: f64) -> Self;
+        fn to_pair(self) -> (f64, f64);
+    }
+
+    impl RefScalar for f32 {
+        fn from_pair(re: f64, _: f64) -> Self {
+            re as f32
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self as f64, 0.0)
+        }
+    }
+    impl RefScalar for f64 {
+        fn from_pair(re: f64, _: f64) -> Self {
+            re
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self, 0.0)
+        }
+    }
+    impl RefScalar for c32 {
+        fn from_pair(re: f64, im: f64) -> Self {
+            c32::new(re as f32, im as f32)
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self.re as f64, self.im as f64)
+        }
+    }
+    impl RefScalar for c64 {
+        fn from_pair(re: f64, im: f64) -> Self {
+            c64::new(re, im)
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self.re, self.im)
+        }
+    }
+
+    // `x` rounded to the precision of `T`
+    fn cast<T: RefScalar>(x: (f64, f64)) -> (f64, f64) {
+        T::from_pair(x.0, x.1).to_pair()
+    }
+
+    // random entries with parts in `[-1, 1)` that are representable in `T`
+    fn random_matrix<T: RefScalar>(len: usize) -> Vec<(f64, f64)> {
+        (0..len)
+            .map(|_| {
+                cast::<T>((
+                    rand::random::<f64>() * 2.0 - 1.0,
+                    rand::random::<f64>() * 2.0 - 1.0,
+                ))
+            })
+            .collect()
+    }
+
+    fn from_pairs<T: RefScalar>(x: &[(f64, f64)]) -> Vec<T> {
+        x.iter().map(|&(re, im)| T::from_pair(re, im)).collect()
+    }
+
+    fn test_cholesky_ty<T: RefScalar>(eps: f64) {
+        for n in [0, 1, 5, 64, 65, 130, 300] {
+            dbg!(n);
+            // a×aᴴ + n×I is hermitian positive definite
+            let a = random_matrix::<T>(n * n);
+            let mut herm = vec![(0.0, 0.0); n * n];
+            for j in 0..n {
+                for i in 0..n {
+                    let mut acc = if i == j { (n as f64, 0.0) } else { (0.0, 0.0) };
+                    for p in 0..n {
+                        let x = a[i + p * n];
+                        let y = a[j + p * n];
+                        acc.0 += x.0 * y.0 + x.1 * y.1;
+                        acc.1 += x.1 * y.0 - x.0 * y.1;
+                    }
+                    herm[i + j * n] = cast::<T>(acc);
+                }
+            }
+            let herm: Vec<T> = from_pairs(&herm);
+            let rhs: Vec<T> = from_pairs(&random_matrix::<T>(n * n));
+            let nrhs = n.min(7);
+
+            for triangle in [Triangle::Lower, Triangle::Upper] {
+                for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                    let mut factor = herm.clone();
+                    let mut x = rhs.clone();
+                    unsafe {
+                        potrf(triangle, n, factor.as_mut_ptr(), n as isize, 1, parallelism)
+                            .unwrap();
+                        potrs(
+                            triangle,
+                            n,
+                            nrhs,
+                            factor.as_ptr(),
+                            n as isize,
+                            1,
+                            x.as_mut_ptr(),
+                            n as isize,
+                            1,
+                            parallelism,
+                        );
+                    }
+
+                    // the factor is triangular, and reproduces the matrix
+                    let l = |i: usize, j: usize| match triangle {
+                        Triangle::Lower if j <= i => factor[i + j * n].to_pair(),
+                        Triangle::Upper if j <= i => {
+                            let (re, im) = factor[j + i * n].to_pair();
+                            (re, -im)
+                        }
+                        _ => (0.0, 0.0),
+                    };
+                    for j in 0..n {
+                        for i in 0..n {
+                            let mut acc = (0.0, 0.0);
+                            for p in 0..n {
+                                let x = l(i, p);
+                                let y = l(j, p);
+                                acc.0 += x.0 * y.0 + x.1 * y.1;
+                                acc.1 += x.1 * y.0 - x.0 * y.1;
+                            }
+                            let target = herm[i + j * n].to_pair();
+                            let tol = eps * n as f64 * (1.0 + n as f64);
+                            assert!((acc.0 - target.0).abs() <= tol);
+                            assert!((acc.1 - target.1).abs() <= tol);
+                        }
+                    }
+
+                    check_solve(n, nrhs, &herm, &rhs, &x, eps);
+                }
+            }
+        }
+    }
+
+    fn test_lu_ty<T: RefScalar>(eps: f64) {
+        for n in [0, 1, 5, 64, 65, 130, 300] {
+            dbg!(n);
+            let a: Vec<T> = from_pairs(&random_matrix::<T>(n * n));
+            let rhs: Vec<T> = from_pairs(&random_matrix::<T>(n * n));
+            let nrhs = n.min(7);
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                let mut factor = a.clone();
+                let mut perm = vec![0usize; n];
+                let mut x = rhs.clone();
+                unsafe {
+                    getrf(
+                        n,
+                        n,
+                        factor.as_mut_ptr(),
+                        n as isize,
+                        1,
+                        &mut perm,
+                        parallelism,
+                    )
+                    .unwrap();
+                    getrs(
+                        n,
+                        nrhs,
+                        factor.as_ptr(),
+                        n as isize,
+                        1,
+                        &perm,
+                        x.as_mut_ptr(),
+                        n as isize,
+                        1,
+                        parallelism,
+                    );
+                }
+
+                // p×a = l×u, where p applies the row interchanges in order
+                let mut pa: Vec<(f64, f64)> = a.iter().map(|&x| x.to_pair()).collect();
+                for (i, &p) in perm.iter().enumerate() {
+                    assert!(p >= i && p < n);
+                    for j in 0..n {
+                        pa.swap(i + j * n, p + j * n);
+                    }
+                }
+                for j in 0..n {
+                    for i in 0..n {
+                        let mut acc = (0.0, 0.0);
+                        for p in 0..=i.min(j) {
+                            let x = if p == i {
+                                (1.0, 0.0)
+                            } else {
+                                factor[i + p * n].to_pair()
+                            };
+                            let y = factor[p + j * n].to_pair();
+                            // the pivots maximize |re| + |im|, which bounds the modulus of `l` by √2
+                            assert!(x.0.hypot(x.1) <= 2.0f64.sqrt() * (1.0 + eps));
+                            acc.0 += x.0 * y.0 - x.1 * y.1;
+                            acc.1 += x.0 * y.1 + x.1 * y.0;
+                        }
+                        let target = pa[i + j * n];
+                        let tol = eps * n as f64 * (1.0 + n as f64);
+                        assert!((acc.0 - target.0).abs() <= tol);
+                        assert!((acc.1 - target.1).abs() <= tol);
+                    }
+                }
+
+                check_solve(n, nrhs, &a, &rhs, &x, eps);
+            }
+        }
+    }
+
+    // checks the residual a×x - rhs, with the product computed by `gemm`
+    fn check_solve<T: RefScalar>(n: usize, nrhs: usize, a: &[T], rhs: &[T], x: &[T], eps: f64) {
+        let mut residual = rhs.to_vec();
+        unsafe {
+            gemm(
+                n,
+                nrhs,
+                n,
+                residual.as_mut_ptr(),
+                n as isize,
+                1,
+                true,
+                a.as_ptr(),
+                n as isize,
+                1,
+                x.as_ptr(),
+                n as isize,
+                1,
+                T::from_pair(-1.0, 0.0),
+                T::from_pair(1.0, 0.0),
+                false,
+                false,
+                false,
+                Parallelism::None,
+            );
+        }
+        for &x in &residual[..n * nrhs] {
+            let (re, im) = x.to_pair();
+            let tol = eps * n as f64 * (1.0 + n as f64);
+            assert!(re.abs() <= tol);
+            assert!(im.abs() <= tol);
+        }
+    }
+
+    #[test]
+    fn test_cholesky() {
+        test_cholesky_ty::<f32>(1e-6);
+        test_cholesky_ty::<f64>(1e-14);
+        test_cholesky_ty::<c32>(1e-6);
+        test_cholesky_ty::<c64>(1e-14);
+    }
+
+    #[test]
+    fn test_lu() {
+        test_lu_ty::<f32>(1e-6);
+        test_lu_ty::<f64>(1e-14);
+        test_lu_ty::<c32>(1e-6);
+        test_lu_ty::<c64>(1e-14);
+    }
+
+    #[test]
+    fn test_factorization_errors() {
+        let mut a = [1.0, 2.0, 2.0, 1.0f64];
+        assert_eq!(
+            unsafe { potrf(Triangle::Lower, 2, a.as_mut_ptr(), 2, 1, Parallelism::None) },
+            Err(FactorizationError::NotPositiveDefinite { index: 1 }),
+        );
+
+        let mut a = [1.0, 2.0, 2.0, 4.0f64];
+        let mut perm = [0; 2];
+        assert_eq!(
+            unsafe { getrf(2, 2, a.as_mut_ptr(), 2, 1, &mut perm, Parallelism::None) },
+            Err(FactorizationError::Singular { index: 1 }),
+        );
+        assert_eq!(perm, [1, 1]);
+    }
+}
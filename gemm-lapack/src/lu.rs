@@ -0,0 +1,248 @@
+use crate::{FactorizationError, MatMut, NB};
+use gemm::{c32, c64, dispatch_scalar, gemm, trsm, Diag, Parallelism, Scalar, Side, Triangle};
+
+// factors the `m×nb` panel starting at column `col`, swapping rows only within the panel. `perm`
+// holds the interchanges of the panel
+unsafe fn getrf_panel<U: Scalar>(
+    m: usize,
+    col: usize,
+    nb: usize,
+    a: MatMut<U>,
+    perm: &mut [usize],
+    singular: &mut Option<usize>,
+) {
+    for (c, perm) in (col..col + nb).zip(perm) {
+        let mut p = c;
+        let mut max = (*a.at(c, c)).abs1();
+        for i in c + 1..m {
+            let x = (*a.at(i, c)).abs1();
+            if x > max {
+                max = x;
+                p = i;
+            }
+        }
+        *perm = p;
+        a.swap_rows(c, p, col, col + nb);
+
+        if max == 0.0 {
+            // the column below the pivot is already zero
+            singular.get_or_insert(c);
+        } else {
+            let inv = U::one() / *a.at(c, c);
+            for i in c + 1..m {
+                *a.at(i, c) = *a.at(i, c) * inv;
+            }
+        }
+
+        for j in c + 1..col + nb {
+            let y = *a.at(c, j);
+            for i in c + 1..m {
+                *a.at(i, j) = *a.at(i, j) - *a.at(i, c) * y;
+            }
+        }
+    }
+}
+
+unsafe fn getrf_imp<T: 'static, U: Scalar>(
+    m: usize,
+    n: usize,
+    a: *mut T,
+    a_cs: isize,
+    a_rs: isize,
+    perm: &mut [usize],
+    parallelism: Parallelism,
+) -> Result<(), FactorizationError> {
+    let size = m.min(n);
+    assert!(perm.len() == size);
+    let a = MatMut {
+        ptr: a as *mut U,
+        cs: a_cs,
+        rs: a_rs,
+    };
+
+    let mut singular = None;
+    let mut col = 0;
+    while col < size {
+        let nb = NB.min(size - col);
+        let panel_perm = &mut perm[col..col + nb];
+        getrf_panel(m, col, nb, a, panel_perm, &mut singular);
+
+        for (c, &p) in (col..col + nb).zip(&*panel_perm) {
+            a.swap_rows(c, p, 0, col);
+            a.swap_rows(c, p, col + nb, n);
+        }
+
+        let rest_cols = n - col - nb;
+        let rest_rows = m - col - nb;
+        if rest_cols > 0 {
+            // a12 := l11⁻¹×a12
+            trsm(
+                Side::Left,
+                Triangle::Lower,
+                Diag::Unit,
+                nb,
+                rest_cols,
+                a.at(col, col),
+                a_cs,
+                a_rs,
+                false,
+                a.at(col, col + nb),
+                a_cs,
+                a_rs,
+                U::one(),
+                parallelism,
+            );
+            if rest_rows > 0 {
+                // a22 := a22 - a21×a12
+                gemm(
+                    rest_rows,
+                    rest_cols,
+                    nb,
+                    a.at(col + nb, col + nb),
+                    a_cs,
+                    a_rs,
+                    true,
+                    a.at(col + nb, col),
+                    a_cs,
+                    a_rs,
+                    a.at(col, col + nb),
+                    a_cs,
+                    a_rs,
+                    U::one(),
+                    -U::one(),
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                );
+            }
+        }
+        col += nb;
+    }
+
+    match singular {
+        Some(index) => Err(FactorizationError::Singular { index }),
+        None => Ok(()),
+    }
+}
+
+unsafe fn getrs_imp<T: 'static, U: Scalar>(
+    n: usize,
+    nrhs: usize,
+    a: *const T,
+    a_cs: isize,
+    a_rs: isize,
+    perm: &[usize],
+    b: *mut T,
+    b_cs: isize,
+    b_rs: isize,
+    parallelism: Parallelism,
+) {
+    assert!(perm.len() == n);
+    let a = a as *const U;
+    let b_mat = MatMut {
+        ptr: b as *mut U,
+        cs: b_cs,
+        rs: b_rs,
+    };
+    for (i, &p) in perm.iter().enumerate() {
+        b_mat.swap_rows(i, p, 0, nrhs);
+    }
+
+    // l×y = p×b
+    trsm(
+        Side::Left,
+        Triangle::Lower,
+        Diag::Unit,
+        n,
+        nrhs,
+        a,
+        a_cs,
+        a_rs,
+        false,
+        b_mat.ptr,
+        b_cs,
+        b_rs,
+        U::one(),
+        parallelism,
+    );
+    // u×x = y
+    trsm(
+        Side::Left,
+        Triangle::Upper,
+        Diag::NonUnit,
+        n,
+        nrhs,
+        a,
+        a_cs,
+        a_rs,
+        false,
+        b_mat.ptr,
+        b_cs,
+        b_rs,
+        U::one(),
+        parallelism,
+    );
+}
+
+/// LU factorization with partial pivoting of the `m×n` matrix `a`, in place: p×a = l×u, where
+/// `l` is unit lower triangular (or trapezoidal) and is stored below the diagonal, and `u` is upper
+/// triangular (or trapezoidal) and is stored on and above it.
+///
+/// `perm` has length `min(m, n)`, and row `i` was interchanged with row `perm[i]` (with
+/// `perm[i] >= i`), in increasing order of `i`.
+///
+/// The factorization is right looking: each block column is factored directly with row
+/// interchanges, then the block row to its right is solved with [`trsm`], and the trailing matrix
+/// is updated with [`gemm`].
+///
+/// # Errors
+///
+/// Returns [`FactorizationError::Singular`] if a pivot is exactly zero.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `c32` or `c64`, or if `perm` does not have length
+/// `min(m, n)`
+pub unsafe fn getrf<T: 'static>(
+    m: usize,
+    n: usize,
+    a: *mut T,
+    a_cs: isize,
+    a_rs: isize,
+    perm: &mut [usize],
+    parallelism: Parallelism,
+) -> Result<(), FactorizationError> {
+    dispatch_scalar!(
+        T,
+        [f64, f32, c64, c32],
+        getrf_imp,
+        (m, n, a, a_cs, a_rs, perm, parallelism)
+    )
+}
+
+/// Solves a×x = b in place for the `n×nrhs` matrix `b`, where the `n×n` matrix `a` was factored
+/// by [`getrf`] into `a` and `perm`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `c32` or `c64`, or if `perm` does not have length `n`
+pub unsafe fn getrs<T: 'static>(
+    n: usize,
+    nrhs: usize,
+    a: *const T,
+    a_cs: isize,
+    a_rs: isize,
+    perm: &[usize],
+    b: *mut T,
+    b_cs: isize,
+    b_rs: isize,
+    parallelism: Parallelism,
+) {
+    dispatch_scalar!(
+        T,
+        [f64, f32, c64, c32],
+        getrs_imp,
+        (n, nrhs, a, a_cs, a_rs, perm, b, b_cs, b_rs, parallelism)
+    )
+}
@@ -1,3 +1,5 @@
+extern crate candle_gemm as gemm;
+
 use criterion::{criterion_group, criterion_main, Criterion};
 use gemm::f16;
 use gemm::*;
@@ -256,6 +258,95 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     }
 }
 
+type GemmFn<T> = unsafe fn(
+    usize,
+    usize,
+    usize,
+    *mut T,
+    isize,
+    isize,
+    bool,
+    *const T,
+    isize,
+    isize,
+    *const T,
+    isize,
+    isize,
+    T,
+    T,
+    bool,
+    bool,
+    bool,
+    gemm::Parallelism,
+);
+
+fn bench_3m<T: 'static + Copy + Default>(
+    c: &mut Criterion,
+    ty: &str,
+    gemm_3m: GemmFn<T>,
+    a: T,
+    b: T,
+    one: T,
+) {
+    let default_threshold = gemm_common::gemm::DEFAULT_CPLX_3M_THRESHOLD;
+    for n in [
+        default_threshold,
+        2 * default_threshold,
+        4 * default_threshold,
+    ] {
+        let a_vec = vec![a; n * n];
+        let b_vec = vec![b; n * n];
+        let mut c_vec = vec![T::default(); n * n];
+        for (label, f) in [("cplx", gemm::<T> as GemmFn<T>), ("3m", gemm_3m)] {
+            c.bench_function(&format!("{}-{}-{}×{}×{}", ty, label, n, n, n), |bencher| {
+                bencher.iter(|| unsafe {
+                    f(
+                        n,
+                        n,
+                        n,
+                        c_vec.as_mut_ptr(),
+                        n as isize,
+                        1,
+                        false,
+                        a_vec.as_ptr(),
+                        n as isize,
+                        1,
+                        b_vec.as_ptr(),
+                        n as isize,
+                        1,
+                        T::default(),
+                        one,
+                        false,
+                        false,
+                        false,
+                        gemm::Parallelism::Rayon(0),
+                    )
+                })
+            });
+        }
+    }
+}
+
+// the complex kernels against the 3M method, from the default threshold of `gemm_3m` up
+pub fn criterion_benchmark_3m(c: &mut Criterion) {
+    bench_3m(
+        c,
+        "c64",
+        *gemm_f64::gemm::f64::GEMM_3M,
+        c64::new(1.0, 0.5),
+        c64::new(0.5, -1.0),
+        c64::new(1.0, 0.0),
+    );
+    bench_3m(
+        c,
+        "c32",
+        *gemm_f32::gemm::f32::GEMM_3M,
+        c32::new(1.0, 0.5),
+        c32::new(0.5, -1.0),
+        c32::new(1.0, 0.0),
+    );
+}
+
 pub fn criterion_benchmark_parallelism(c: &mut Criterion) {
     // let mnks = vec![(6, 768 * 3, 768)];
     let mnks = vec![(4096, 128, 11108)];
@@ -454,4 +545,12 @@ criterion_group!(
         .sample_size(10);
     targets = criterion_benchmark_parallelism
 );
-criterion_main!(benches, benches_parallelism);
+criterion_group!(
+    name = benches_3m;
+    config = Criterion::default()
+        .warm_up_time(Duration::from_secs(1))
+        .measurement_time(Duration::from_secs(2))
+        .sample_size(10);
+    targets = criterion_benchmark_3m
+);
+criterion_main!(benches, benches_parallelism, benches_3m);
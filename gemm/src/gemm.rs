@@ -1,5 +1,9 @@
-use crate::Parallelism;
+use crate::{
+    bf16, BlockMask, Epilogue, Fp8Format, Fp8Scale, GemmOutput, GemmTerm, Parallelism, Rounding,
+    Side, Triangle, TriangularMask,
+};
 use core::any::TypeId;
+use gemm_common::gemm::SymmetricLhs;
 
 #[allow(non_camel_case_types)]
 pub type c32 = num_complex::Complex32;
@@ -28,7 +32,15 @@ unsafe fn gemm_dispatch<T: 'static>(
     conj_lhs: bool,
     conj_rhs: bool,
     parallelism: Parallelism,
+    rounding: Rounding,
+    epilogue: Epilogue<T>,
 ) {
+    let epilogue = if epilogue.is_identity() {
+        core::ptr::null()
+    } else {
+        &epilogue as *const Epilogue<T>
+    };
+
     if TypeId::of::<T>() == TypeId::of::<f64>() {
         gemm_f64::gemm::f64::GEMM(
             m,
@@ -50,6 +62,7 @@ unsafe fn gemm_dispatch<T: 'static>(
             false,
             false,
             parallelism,
+            epilogue as *const Epilogue<f64>,
         )
     } else if TypeId::of::<T>() == TypeId::of::<f32>() {
         gemm_f32::gemm::f32::GEMM(
@@ -72,9 +85,12 @@ unsafe fn gemm_dispatch<T: 'static>(
             false,
             false,
             parallelism,
+            epilogue as *const Epilogue<f32>,
         )
+    } else if !epilogue.is_null() {
+        panic!("epilogues are only supported for f32 and f64");
     } else if TypeId::of::<T>() == TypeId::of::<f16>() {
-        gemm_f16::gemm::f16::GEMM(
+        gemm_f16::gemm::f16::GEMM_WITH_ROUNDING(
             m,
             n,
             k,
@@ -94,6 +110,7 @@ unsafe fn gemm_dispatch<T: 'static>(
             false,
             false,
             parallelism,
+            rounding,
         )
     } else if TypeId::of::<T>() == TypeId::of::<c64>() {
         gemm_c64::gemm::f64::GEMM_CPLX(
@@ -146,6 +163,8 @@ unsafe fn gemm_dispatch<T: 'static>(
 
 /// dst := alpha×dst + beta×lhs×rhs
 ///
+/// See [`gemm_generic`](crate::gemm_generic) for other scalar types.
+///
 /// # Panics
 ///
 /// Panics if `T` is not `f32` or `f64`
@@ -153,7 +172,7 @@ pub unsafe fn gemm<T: 'static>(
     m: usize,
     n: usize,
     k: usize,
-    mut dst: *mut T,
+    dst: *mut T,
     dst_cs: isize,
     dst_rs: isize,
     read_dst: bool,
@@ -170,48 +189,125 @@ pub unsafe fn gemm<T: 'static>(
     conj_rhs: bool,
     parallelism: Parallelism,
 ) {
-    // we want to transpose if the destination is column-oriented, since the microkernel prefers
-    // column major matrices.
-    let do_transpose = dst_cs.abs() < dst_rs.abs();
-
-    let (
+    gemm_with_rounding(
         m,
         n,
-        mut dst_cs,
-        mut dst_rs,
-        mut lhs,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
         lhs_cs,
-        mut lhs_rs,
-        mut rhs,
-        mut rhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
         rhs_rs,
+        alpha,
+        beta,
+        conj_dst,
         conj_lhs,
         conj_rhs,
-    ) = if do_transpose {
-        (
-            n, m, dst_rs, dst_cs, rhs, rhs_rs, rhs_cs, lhs, lhs_rs, lhs_cs, conj_rhs, conj_lhs,
-        )
-    } else {
-        (
-            m, n, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs, conj_lhs, conj_rhs,
-        )
-    };
+        parallelism,
+        Rounding::Nearest,
+    )
+}
 
-    if dst_rs < 0 && m > 0 {
-        dst = dst.wrapping_offset((m - 1) as isize * dst_rs);
-        dst_rs = -dst_rs;
-        lhs = lhs.wrapping_offset((m - 1) as isize * lhs_rs);
-        lhs_rs = -lhs_rs;
-    }
+/// dst := alpha×dst + beta×lhs×rhs, narrowing the result to `dst` with the given rounding
+///
+/// `rounding` only affects `f16` destinations, whose result is computed in `f32`. Other types
+/// are computed in their own precision and always rounded to nearest.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f16`, `f32`, `f64`, `c32` or `c64`
+pub unsafe fn gemm_with_rounding<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism,
+    rounding: Rounding,
+) {
+    gemm_impl(
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+        rounding,
+        Epilogue::identity(),
+    )
+}
 
-    if dst_cs < 0 && n > 0 {
-        dst = dst.wrapping_offset((n - 1) as isize * dst_cs);
-        dst_cs = -dst_cs;
-        rhs = rhs.wrapping_offset((n - 1) as isize * rhs_cs);
-        rhs_cs = -rhs_cs;
+/// dst := activation(alpha×dst + beta×diag(row_scale)×lhs×rhs×diag(col_scale) + bias)
+///
+/// The scaling, bias and activation of `epilogue` are applied by the microkernels while the
+/// output tile is still in registers, instead of in separate passes over `dst`.
+///
+/// The row reductions of `epilogue` are computed from each tile right after it is stored. If they
+/// are requested, `dst` may be null, in which case it is not written to and `read_dst` must be
+/// `false`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`, unless `epilogue` is the identity, or if `dst` is null
+/// while there are no reductions or `read_dst` is `true`
+pub unsafe fn gemm_with_epilogue<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism,
+    epilogue: Epilogue<T>,
+) {
+    if dst.is_null() {
+        assert!(epilogue.reductions.is_some());
+        assert!(!read_dst);
     }
-
-    gemm_dispatch(
+    gemm_impl(
         m,
         n,
         k,
@@ -231,9 +327,1431 @@ pub unsafe fn gemm<T: 'static>(
         conj_lhs,
         conj_rhs,
         parallelism,
+        Rounding::Nearest,
+        epilogue,
     )
 }
 
+/// dst := alpha×dst + beta×lhs×rhs, computing complex products with the 3M method if all of
+/// `m`, `n` and `k` are at least
+/// [`get_cplx_3m_threshold`](gemm_common::gemm::get_cplx_3m_threshold).
+///
+/// The 3M method replaces the four real products of a complex product with three products on the
+/// real kernels, on the real parts, on the imaginary parts, and on their sums. The parts and the
+/// sums are formed while the operands are packed, and each tile of the real products is
+/// accumulated into `dst` once it is computed. It is slightly less accurate than [`gemm`], as the
+/// imaginary part of the result is a difference of larger products.
+///
+/// Other types, and smaller complex products, are computed by [`gemm`].
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `c32` or `c64`
+pub unsafe fn gemm_3m<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism,
+) {
+    let threshold = gemm_common::gemm::get_cplx_3m_threshold();
+    let use_3m = m.min(n).min(k) >= threshold.max(1);
+
+    if use_3m && TypeId::of::<T>() == TypeId::of::<c64>() {
+        gemm_f64::gemm::f64::GEMM_3M(
+            m,
+            n,
+            k,
+            dst as *mut c64,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs as *const c64,
+            lhs_cs,
+            lhs_rs,
+            rhs as *const c64,
+            rhs_cs,
+            rhs_rs,
+            *(&alpha as *const T as *const c64),
+            *(&beta as *const T as *const c64),
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        )
+    } else if use_3m && TypeId::of::<T>() == TypeId::of::<c32>() {
+        gemm_f32::gemm::f32::GEMM_3M(
+            m,
+            n,
+            k,
+            dst as *mut c32,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs as *const c32,
+            lhs_cs,
+            lhs_rs,
+            rhs as *const c32,
+            rhs_cs,
+            rhs_rs,
+            *(&alpha as *const T as *const c32),
+            *(&beta as *const T as *const c32),
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        )
+    } else {
+        gemm(
+            m,
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        )
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs for complex matrices in planar storage, where `T` is `f32` or
+/// `f64`.
+///
+/// Each matrix is given by a real matrix and an imaginary matrix, with the same strides. The
+/// operands are interleaved while they are packed for the complex microkernels, so the result
+/// matches [`gemm`] on `c32` or `c64` with the same conjugation flags.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`
+pub unsafe fn gemm_planar<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst_re: *mut T,
+    dst_im: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs_re: *const T,
+    lhs_im: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs_re: *const T,
+    rhs_im: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: num_complex::Complex<T>,
+    beta: num_complex::Complex<T>,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism,
+) {
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        gemm_c64::gemm::f64::GEMM_PLANAR_CPLX(
+            m,
+            n,
+            k,
+            dst_re as *mut f64,
+            dst_im as *mut f64,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs_re as *const f64,
+            lhs_im as *const f64,
+            lhs_cs,
+            lhs_rs,
+            rhs_re as *const f64,
+            rhs_im as *const f64,
+            rhs_cs,
+            rhs_rs,
+            *(&alpha as *const num_complex::Complex<T> as *const c64),
+            *(&beta as *const num_complex::Complex<T> as *const c64),
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+        gemm_c32::gemm::f32::GEMM_PLANAR_CPLX(
+            m,
+            n,
+            k,
+            dst_re as *mut f32,
+            dst_im as *mut f32,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs_re as *const f32,
+            lhs_im as *const f32,
+            lhs_cs,
+            lhs_rs,
+            rhs_re as *const f32,
+            rhs_im as *const f32,
+            rhs_cs,
+            rhs_rs,
+            *(&alpha as *const num_complex::Complex<T> as *const c32),
+            *(&beta as *const num_complex::Complex<T> as *const c32),
+            conj_dst,
+            conj_lhs,
+            conj_rhs,
+            parallelism,
+        )
+    } else {
+        panic!();
+    }
+}
+
+unsafe fn pairwise_dispatch<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    cosine: bool,
+    clamp_to_zero: bool,
+    parallelism: Parallelism,
+) {
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        gemm_f64::gemm::f64::GEMM_PAIRWISE(
+            m,
+            n,
+            k,
+            dst as *mut f64,
+            dst_cs,
+            dst_rs,
+            lhs as *const f64,
+            lhs_cs,
+            lhs_rs,
+            rhs as *const f64,
+            rhs_cs,
+            rhs_rs,
+            cosine,
+            clamp_to_zero,
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+        gemm_f32::gemm::f32::GEMM_PAIRWISE(
+            m,
+            n,
+            k,
+            dst as *mut f32,
+            dst_cs,
+            dst_rs,
+            lhs as *const f32,
+            lhs_cs,
+            lhs_rs,
+            rhs as *const f32,
+            rhs_cs,
+            rhs_rs,
+            cosine,
+            clamp_to_zero,
+            parallelism,
+        )
+    } else {
+        panic!("pairwise distances are only supported for f32 and f64");
+    }
+}
+
+/// dst[i, j] := |x_i - y_j|², where `x_i` is the `i`-th row of `lhs` and `y_j` is the `j`-th
+/// column of `rhs`.
+///
+/// This is computed as `|x_i|² + |y_j|² - 2 x_i·y_j`. The squared norms are accumulated while
+/// `lhs` and `rhs` are packed, and added by the epilogue of each tile of `-2×lhs×rhs`.
+/// Because of cancellation, the result can be slightly negative for nearby points, unless
+/// `clamp_to_zero` is set.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`
+pub unsafe fn pairwise_sq_distances<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    clamp_to_zero: bool,
+    parallelism: Parallelism,
+) {
+    pairwise_dispatch(
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        false,
+        clamp_to_zero,
+        parallelism,
+    )
+}
+
+/// dst[i, j] := x_i·y_j / (|x_i| × |y_j|), where `x_i` is the `i`-th row of `lhs` and `y_j` is
+/// the `j`-th column of `rhs`, or zero if either of them is zero.
+///
+/// The norms are accumulated while `lhs` and `rhs` are packed, and each tile of `lhs×rhs` is
+/// scaled by their inverses once it is fully accumulated.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`
+pub unsafe fn pairwise_cosine_similarities<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    parallelism: Parallelism,
+) {
+    pairwise_dispatch(
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        true,
+        false,
+        parallelism,
+    )
+}
+
+unsafe fn gemm_impl<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    mut dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    conj_dst: bool,
+    conj_lhs: bool,
+    conj_rhs: bool,
+    parallelism: Parallelism,
+    rounding: Rounding,
+    epilogue: Epilogue<T>,
+) {
+    // we want to transpose if the destination is column-oriented, since the microkernel prefers
+    // column major matrices. the epilogue follows the transposition and the reversals below,
+    // including its row reductions
+    let do_transpose = dst_cs.abs() < dst_rs.abs();
+
+    let (
+        m,
+        n,
+        mut dst_cs,
+        mut dst_rs,
+        mut lhs,
+        lhs_cs,
+        mut lhs_rs,
+        mut rhs,
+        mut rhs_cs,
+        rhs_rs,
+        conj_lhs,
+        conj_rhs,
+    ) = if do_transpose {
+        (
+            n, m, dst_rs, dst_cs, rhs, rhs_rs, rhs_cs, lhs, lhs_rs, lhs_cs, conj_rhs, conj_lhs,
+        )
+    } else {
+        (
+            m, n, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs, conj_lhs, conj_rhs,
+        )
+    };
+
+    let mut epilogue = if do_transpose {
+        epilogue.transpose()
+    } else {
+        epilogue
+    };
+
+    if dst_rs < 0 && m > 0 {
+        epilogue = epilogue.reverse_rows(m);
+        dst = dst.wrapping_offset((m - 1) as isize * dst_rs);
+        dst_rs = -dst_rs;
+        lhs = lhs.wrapping_offset((m - 1) as isize * lhs_rs);
+        lhs_rs = -lhs_rs;
+    }
+
+    if dst_cs < 0 && n > 0 {
+        epilogue = epilogue.reverse_cols(n);
+        dst = dst.wrapping_offset((n - 1) as isize * dst_cs);
+        dst_cs = -dst_cs;
+        rhs = rhs.wrapping_offset((n - 1) as isize * rhs_cs);
+        rhs_cs = -rhs_cs;
+    }
+
+    gemm_dispatch(
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        alpha,
+        beta,
+        conj_dst,
+        conj_lhs,
+        conj_rhs,
+        parallelism,
+        rounding,
+        epilogue,
+    )
+}
+
+/// dst := alpha×dst + beta×lhs×rhs, with error-compensated accumulation
+///
+/// Each dot product is accumulated as an unevaluated sum `hi + lo`, where every product and
+/// every addition has its rounding error recovered exactly (TwoProduct and TwoSum). The pair is
+/// carried across `kc` blocks and only rounded once, when it is merged into `dst`.
+///
+/// # Error bounds
+///
+/// Let `u` be the unit roundoff of `T` (`2^-24` for `f32`, `2^-53` for `f64`),
+/// `γ(k) = k×u / (1 - k×u)`, `s = Σ_p lhs[i, p]×rhs[p, j]` and `|s|' = Σ_p |lhs[i, p]×rhs[p, j]|`.
+/// Ignoring underflow and overflow, the computed dot product `ŝ` satisfies
+/// - [`gemm`]: `|ŝ - s| <= γ(k)×|s|'`,
+/// - [`gemm_compensated`]: `|ŝ - s| <= u×|s| + γ(k)²×|s|'`,
+///
+/// i.e. the compensated result is as accurate as if it had been computed in twice the working
+/// precision, then rounded. Applying `alpha` and `beta` adds at most a few roundings relative to
+/// `|alpha×dst| + |beta×ŝ|`.
+///
+/// This requires scratch space for two values per element of a column block of `dst`, and is
+/// several times slower than [`gemm`].
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`
+pub unsafe fn gemm_compensated<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        gemm_f64::gemm::f64::GEMM_COMPENSATED(
+            m,
+            n,
+            k,
+            dst as *mut f64,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs as *mut f64,
+            lhs_cs,
+            lhs_rs,
+            rhs as *mut f64,
+            rhs_cs,
+            rhs_rs,
+            *(&alpha as *const T as *const f64),
+            *(&beta as *const T as *const f64),
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+        gemm_f32::gemm::f32::GEMM_COMPENSATED(
+            m,
+            n,
+            k,
+            dst as *mut f32,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs as *mut f32,
+            lhs_cs,
+            lhs_rs,
+            rhs as *mut f32,
+            rhs_cs,
+            rhs_rs,
+            *(&alpha as *const T as *const f32),
+            *(&beta as *const T as *const f32),
+            parallelism,
+        )
+    } else {
+        panic!();
+    }
+}
+
+/// For each of the `outputs`, dst := alpha×dst + beta×lhs×rhs, with the epilogue of that output
+/// applied as in [`gemm_with_epilogue`].
+///
+/// `lhs` is `m×k` and is shared by all the products, each `rhs` is `k×n` and each `dst` is `m×n`,
+/// where `n` may differ between the outputs. All the products are computed in the same blocked
+/// loop, so each block of `lhs` is only packed once instead of once per output.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`, or if an output has a null `dst` but either reads it or
+/// has no reductions.
+pub unsafe fn gemm_multi_rhs<T: 'static>(
+    m: usize,
+    k: usize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    outputs: &[GemmOutput<T>],
+    parallelism: Parallelism,
+) {
+    gemm_multi_dispatch(m, k, lhs, lhs_cs, lhs_rs, None, outputs, parallelism)
+}
+
+unsafe fn gemm_multi_dispatch<T: 'static>(
+    m: usize,
+    k: usize,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    lhs_blocks: Option<BlockMask>,
+    outputs: &[GemmOutput<T>],
+    parallelism: Parallelism,
+) {
+    for output in outputs {
+        if output.dst.is_null() {
+            assert!(output.epilogue.reductions.is_some());
+            assert!(!output.read_dst);
+        }
+    }
+
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        gemm_f64::gemm::f64::GEMM_MULTI(
+            m,
+            k,
+            lhs as *const f64,
+            lhs_cs,
+            lhs_rs,
+            lhs_blocks,
+            core::slice::from_raw_parts(outputs.as_ptr() as *const GemmOutput<f64>, outputs.len()),
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+        gemm_f32::gemm::f32::GEMM_MULTI(
+            m,
+            k,
+            lhs as *const f32,
+            lhs_cs,
+            lhs_rs,
+            lhs_blocks,
+            core::slice::from_raw_parts(outputs.as_ptr() as *const GemmOutput<f32>, outputs.len()),
+            parallelism,
+        )
+    } else {
+        panic!();
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs, followed by `epilogue`, on the triangle of `dst` kept by
+/// `mask`. The other entries are set to the fill value of the mask, or left untouched if it has
+/// none.
+///
+/// The microkernel tiles that are fully masked are skipped, and the remaining ones are split
+/// evenly between the threads. If `dst` is null, only the reductions of the epilogue are computed,
+/// as in [`gemm_with_epilogue`].
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`, or if `dst` is null while there are no reductions or
+/// `read_dst` is `true`
+pub unsafe fn gemm_triangular<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+    mask: TriangularMask<T>,
+    epilogue: Epilogue<T>,
+) {
+    gemm_multi_rhs(
+        m,
+        k,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        &[GemmOutput {
+            n,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            epilogue,
+            mask: Some(mask),
+            dst_blocks: None,
+        }],
+        parallelism,
+    )
+}
+
+/// dst := alpha×dst + beta×lhs×rhs, followed by `epilogue`, where `lhs` and `dst` may be
+/// block-sparse.
+///
+/// The inactive blocks of `lhs_blocks` are assumed to be zero, and are neither packed nor
+/// multiplied. The entries of `dst` outside of the active blocks of `dst_blocks` are set to zero
+/// instead of being computed. In both cases, only the microkernel tiles that have some work left
+/// are split between the threads. A block-sparse `rhs` can be handled by computing the transpose
+/// of the product instead.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`, or if `dst` is null while there are no reductions or
+/// `read_dst` is `true`
+pub unsafe fn gemm_block_sparse<T: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const T,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+    lhs_blocks: Option<BlockMask>,
+    dst_blocks: Option<BlockMask>,
+    epilogue: Epilogue<T>,
+) {
+    gemm_multi_dispatch(
+        m,
+        k,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        lhs_blocks,
+        &[GemmOutput {
+            n,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            epilogue,
+            mask: None,
+            dst_blocks,
+        }],
+        parallelism,
+    )
+}
+
+/// dst := alpha×dst + beta×Σ lhs_i×rhs_i, followed by `epilogue`, where the `(lhs_i, rhs_i)` are
+/// the `terms`.
+///
+/// `dst` is `m×n`, and each term has its own depth `k` and strides. Each output tile accumulates
+/// all the terms in registers and is stored once, instead of once per term as with repeated
+/// calls to [`gemm`].
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32` or `f64`, or if `epilogue` has reductions
+pub unsafe fn gemm_sum<T: 'static>(
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    terms: &[GemmTerm<T>],
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+    epilogue: Epilogue<T>,
+) {
+    assert!(epilogue.reductions.is_none());
+    let epilogue = if epilogue.is_identity() {
+        core::ptr::null()
+    } else {
+        &epilogue as *const Epilogue<T>
+    };
+
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        gemm_f64::gemm::f64::GEMM_SUM(
+            m,
+            n,
+            dst as *mut f64,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            core::slice::from_raw_parts(terms.as_ptr() as *const GemmTerm<f64>, terms.len()),
+            *(&alpha as *const T as *const f64),
+            *(&beta as *const T as *const f64),
+            parallelism,
+            epilogue as *const Epilogue<f64>,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+        gemm_f32::gemm::f32::GEMM_SUM(
+            m,
+            n,
+            dst as *mut f32,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            core::slice::from_raw_parts(terms.as_ptr() as *const GemmTerm<f32>, terms.len()),
+            *(&alpha as *const T as *const f32),
+            *(&beta as *const T as *const f32),
+            parallelism,
+            epilogue as *const Epilogue<f32>,
+        )
+    } else {
+        panic!();
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×lhsᵀ on the `triangle` of the `n×n` matrix `dst`, where `lhs` is
+/// `n×k`. The entries of the other triangle are neither read nor written.
+///
+/// Only the tiles that meet the triangle are computed, and each block of `lhs` is packed once for
+/// both sides of the product. The Gram matrix lhsᵀ×lhs of a `k×n` matrix is obtained by passing
+/// its transpose, i.e. swapping `lhs_cs` and `lhs_rs`.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `c32` or `c64`
+pub unsafe fn syrk<T: 'static>(
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    alpha: T,
+    beta: T,
+    triangle: Triangle,
+    parallelism: Parallelism,
+) {
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        gemm_f64::gemm::f64::GEMM_SYRK(
+            n,
+            k,
+            dst as *mut f64,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs as *const f64,
+            lhs_cs,
+            lhs_rs,
+            *(&alpha as *const T as *const f64),
+            *(&beta as *const T as *const f64),
+            triangle,
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+        gemm_f32::gemm::f32::GEMM_SYRK(
+            n,
+            k,
+            dst as *mut f32,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs as *const f32,
+            lhs_cs,
+            lhs_rs,
+            *(&alpha as *const T as *const f32),
+            *(&beta as *const T as *const f32),
+            triangle,
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<c64>() || TypeId::of::<T>() == TypeId::of::<c32>() {
+        syrk_cplx(
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            alpha,
+            beta,
+            triangle,
+            false,
+            parallelism,
+        )
+    } else {
+        panic!();
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×lhsᴴ on the `triangle` of the `n×n` matrix `dst`, where `lhs` is
+/// `n×k`. The entries of the other triangle are neither read nor written, and the imaginary parts
+/// of the diagonal are set to zero.
+///
+/// See [`syrk`] for the tiling and packing.
+///
+/// # Panics
+///
+/// Panics if `T` is not `c32` or `c64`, or if `alpha` or `beta` are not real
+pub unsafe fn herk<T: 'static>(
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    alpha: T,
+    beta: T,
+    triangle: Triangle,
+    parallelism: Parallelism,
+) {
+    syrk_cplx(
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        alpha,
+        beta,
+        triangle,
+        true,
+        parallelism,
+    )
+}
+
+unsafe fn syrk_cplx<T: 'static>(
+    n: usize,
+    k: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const T,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    alpha: T,
+    beta: T,
+    triangle: Triangle,
+    hermitian: bool,
+    parallelism: Parallelism,
+) {
+    if TypeId::of::<T>() == TypeId::of::<c64>() {
+        let dst = dst as *mut c64;
+        let alpha = *(&alpha as *const T as *const c64);
+        let beta = *(&beta as *const T as *const c64);
+        if hermitian {
+            assert!(alpha.im == 0.0 && beta.im == 0.0);
+        }
+        gemm_c64::gemm::f64::GEMM_SYRK_CPLX(
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs as *const c64,
+            lhs_cs,
+            lhs_rs,
+            alpha,
+            beta,
+            triangle,
+            false,
+            hermitian,
+            parallelism,
+        );
+        if hermitian {
+            for i in 0..n {
+                (*dst.offset(i as isize * (dst_rs + dst_cs))).im = 0.0;
+            }
+        }
+    } else if TypeId::of::<T>() == TypeId::of::<c32>() {
+        let dst = dst as *mut c32;
+        let alpha = *(&alpha as *const T as *const c32);
+        let beta = *(&beta as *const T as *const c32);
+        if hermitian {
+            assert!(alpha.im == 0.0 && beta.im == 0.0);
+        }
+        gemm_c32::gemm::f32::GEMM_SYRK_CPLX(
+            n,
+            k,
+            dst,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs as *const c32,
+            lhs_cs,
+            lhs_rs,
+            alpha,
+            beta,
+            triangle,
+            false,
+            hermitian,
+            parallelism,
+        );
+        if hermitian {
+            for i in 0..n {
+                (*dst.offset(i as isize * (dst_rs + dst_cs))).im = 0.0;
+            }
+        }
+    } else {
+        panic!();
+    }
+}
+
+/// Symmetric matrix product: dst := alpha×dst + beta×sym×b if `side` is [`Side::Left`], otherwise
+/// dst := alpha×dst + beta×b×sym, where `dst` and `b` are `m×n`, and `sym` is symmetric and `m×m`
+/// or `n×n`.
+///
+/// Only the `triangle` of `sym` is read. The other half is mirrored while `sym` is packed, so the
+/// full matrix is never built.
+///
+/// # Panics
+///
+/// Panics if `T` is not `f32`, `f64`, `c32` or `c64`
+pub unsafe fn symm<T: 'static>(
+    side: Side,
+    triangle: Triangle,
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    sym: *const T,
+    sym_cs: isize,
+    sym_rs: isize,
+    b: *const T,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    symm_dispatch(
+        side,
+        triangle,
+        m,
+        n,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        sym,
+        sym_cs,
+        sym_rs,
+        b,
+        b_cs,
+        b_rs,
+        alpha,
+        beta,
+        false,
+        parallelism,
+    )
+}
+
+/// Hermitian matrix product: dst := alpha×dst + beta×herm×b if `side` is [`Side::Left`],
+/// otherwise dst := alpha×dst + beta×b×herm, where `herm` is Hermitian.
+///
+/// Only the `triangle` of `herm` is read, and the other half is mirrored and conjugated while
+/// `herm` is packed. The imaginary part of the diagonal of `herm` is ignored. See [`symm`] for the
+/// shapes.
+///
+/// # Panics
+///
+/// Panics if `T` is not `c32` or `c64`
+pub unsafe fn hemm<T: 'static>(
+    side: Side,
+    triangle: Triangle,
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    herm: *const T,
+    herm_cs: isize,
+    herm_rs: isize,
+    b: *const T,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: T,
+    beta: T,
+    parallelism: Parallelism,
+) {
+    assert!(TypeId::of::<T>() == TypeId::of::<c32>() || TypeId::of::<T>() == TypeId::of::<c64>());
+    symm_dispatch(
+        side,
+        triangle,
+        m,
+        n,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        herm,
+        herm_cs,
+        herm_rs,
+        b,
+        b_cs,
+        b_rs,
+        alpha,
+        beta,
+        true,
+        parallelism,
+    )
+}
+
+unsafe fn symm_dispatch<T: 'static>(
+    side: Side,
+    triangle: Triangle,
+    m: usize,
+    n: usize,
+    dst: *mut T,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    sym: *const T,
+    sym_cs: isize,
+    sym_rs: isize,
+    b: *const T,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: T,
+    beta: T,
+    hermitian: bool,
+    parallelism: Parallelism,
+) {
+    // b×sym is the transpose of symᵀ×bᵀ, where symᵀ is sym, or its conjugate if it is Hermitian
+    let (m, n, dst_cs, dst_rs, b_cs, b_rs, conj_sym) = match side {
+        Side::Left => (m, n, dst_cs, dst_rs, b_cs, b_rs, false),
+        Side::Right => (n, m, dst_rs, dst_cs, b_rs, b_cs, hermitian),
+    };
+    let lhs_symmetric = SymmetricLhs {
+        triangle,
+        conj: hermitian,
+    };
+
+    if TypeId::of::<T>() == TypeId::of::<f64>() {
+        gemm_f64::gemm::f64::GEMM_SYMM(
+            m,
+            n,
+            dst as *mut f64,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            sym as *const f64,
+            sym_cs,
+            sym_rs,
+            b as *const f64,
+            b_cs,
+            b_rs,
+            *(&alpha as *const T as *const f64),
+            *(&beta as *const T as *const f64),
+            triangle,
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<f32>() {
+        gemm_f32::gemm::f32::GEMM_SYMM(
+            m,
+            n,
+            dst as *mut f32,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            sym as *const f32,
+            sym_cs,
+            sym_rs,
+            b as *const f32,
+            b_cs,
+            b_rs,
+            *(&alpha as *const T as *const f32),
+            *(&beta as *const T as *const f32),
+            triangle,
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<c64>() {
+        gemm_c64::gemm::f64::GEMM_SYMM_CPLX(
+            m,
+            n,
+            dst as *mut c64,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            sym as *const c64,
+            sym_cs,
+            sym_rs,
+            b as *const c64,
+            b_cs,
+            b_rs,
+            *(&alpha as *const T as *const c64),
+            *(&beta as *const T as *const c64),
+            lhs_symmetric,
+            conj_sym,
+            parallelism,
+        )
+    } else if TypeId::of::<T>() == TypeId::of::<c32>() {
+        gemm_c32::gemm::f32::GEMM_SYMM_CPLX(
+            m,
+            n,
+            dst as *mut c32,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            sym as *const c32,
+            sym_cs,
+            sym_rs,
+            b as *const c32,
+            b_cs,
+            b_rs,
+            *(&alpha as *const T as *const c32),
+            *(&beta as *const T as *const c32),
+            lhs_symmetric,
+            conj_sym,
+            parallelism,
+        )
+    } else {
+        panic!();
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs, with `f32` operands accumulated in `f64`
+///
+/// `lhs` and `rhs` are widened to `f64` while they are packed, then multiplied with the `f64`
+/// kernels. If `dst` is `f32`, the `f64` result is rounded once, when it is written to `dst`.
+///
+/// # Panics
+///
+/// Panics if `D` is not `f32` or `f64`
+pub unsafe fn gemm_widening<D: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut D,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const f32,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    rhs: *const f32,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    alpha: f64,
+    beta: f64,
+    parallelism: Parallelism,
+) {
+    // the microkernel prefers column major matrices, see `gemm`
+    let do_transpose = dst_cs.abs() < dst_rs.abs();
+
+    let (m, n, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs) = if do_transpose {
+        (
+            n, m, dst_rs, dst_cs, rhs, rhs_rs, rhs_cs, lhs, lhs_rs, lhs_cs,
+        )
+    } else {
+        (
+            m, n, dst_cs, dst_rs, lhs, lhs_cs, lhs_rs, rhs, rhs_cs, rhs_rs,
+        )
+    };
+
+    if TypeId::of::<D>() == TypeId::of::<f64>() {
+        gemm_f64::widening::f32::GEMM_F64_DST(
+            m,
+            n,
+            k,
+            dst as *mut f64,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            parallelism,
+        )
+    } else if TypeId::of::<D>() == TypeId::of::<f32>() {
+        gemm_f64::widening::f32::GEMM_F32_DST(
+            m,
+            n,
+            k,
+            dst as *mut f32,
+            dst_cs,
+            dst_rs,
+            read_dst,
+            lhs,
+            lhs_cs,
+            lhs_rs,
+            rhs,
+            rhs_cs,
+            rhs_rs,
+            alpha,
+            beta,
+            parallelism,
+        )
+    } else {
+        panic!();
+    }
+}
+
+/// dst := alpha×dst + beta×lhs×rhs, with FP8 operands accumulated in `f32`
+///
+/// Each operand is a matrix of FP8 codes in the given format. The codes are decoded to `f32` and
+/// multiplied by the operand scale while they are packed, then multiplied with the `f32` kernels.
+/// If `dst` is `f16` or `bf16`, the `f32` result is rounded once, when it is written to `dst`.
+///
+/// # Panics
+///
+/// Panics if `D` is not `f32`, `f16` or `bf16`
+pub unsafe fn gemm_fp8<D: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut D,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const u8,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    lhs_format: Fp8Format,
+    lhs_scale: Fp8Scale,
+    rhs: *const u8,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    rhs_format: Fp8Format,
+    rhs_scale: Fp8Scale,
+    alpha: f32,
+    beta: f32,
+    parallelism: Parallelism,
+) {
+    gemm_fp8_with_rounding(
+        m,
+        n,
+        k,
+        dst,
+        dst_cs,
+        dst_rs,
+        read_dst,
+        lhs,
+        lhs_cs,
+        lhs_rs,
+        lhs_format,
+        lhs_scale,
+        rhs,
+        rhs_cs,
+        rhs_rs,
+        rhs_format,
+        rhs_scale,
+        alpha,
+        beta,
+        parallelism,
+        Rounding::Nearest,
+    )
+}
+
+/// dst := alpha×dst + beta×lhs×rhs, with FP8 operands, narrowing the result to `dst` with the
+/// given rounding
+///
+/// See [`gemm_fp8`]. If `dst` is `f16` or `bf16`, the `f32` result is rounded once with the given
+/// rounding, when it is written to `dst`.
+///
+/// # Panics
+///
+/// Panics if `D` is not `f32`, `f16` or `bf16`
+pub unsafe fn gemm_fp8_with_rounding<D: 'static>(
+    m: usize,
+    n: usize,
+    k: usize,
+    dst: *mut D,
+    dst_cs: isize,
+    dst_rs: isize,
+    read_dst: bool,
+    lhs: *const u8,
+    lhs_cs: isize,
+    lhs_rs: isize,
+    lhs_format: Fp8Format,
+    lhs_scale: Fp8Scale,
+    rhs: *const u8,
+    rhs_cs: isize,
+    rhs_rs: isize,
+    rhs_format: Fp8Format,
+    rhs_scale: Fp8Scale,
+    alpha: f32,
+    beta: f32,
+    parallelism: Parallelism,
+    rounding: Rounding,
+) {
+    // the microkernel prefers column major matrices, see `gemm`
+    let do_transpose = dst_cs.abs() < dst_rs.abs();
+
+    let (m, n, dst_cs, dst_rs) = if do_transpose {
+        (n, m, dst_rs, dst_cs)
+    } else {
+        (m, n, dst_cs, dst_rs)
+    };
+    let (lhs, lhs_cs, lhs_rs, lhs_format, lhs_scale, rhs, rhs_cs, rhs_rs, rhs_format, rhs_scale) =
+        if do_transpose {
+            (
+                rhs, rhs_rs, rhs_cs, rhs_format, rhs_scale, lhs, lhs_rs, lhs_cs, lhs_format,
+                lhs_scale,
+            )
+        } else {
+            (
+                lhs, lhs_cs, lhs_rs, lhs_format, lhs_scale, rhs, rhs_cs, rhs_rs, rhs_format,
+                rhs_scale,
+            )
+        };
+
+    macro_rules! dispatch {
+        ($gemm: expr, $ty: ty) => {
+            $gemm(
+                m,
+                n,
+                k,
+                dst as *mut $ty,
+                dst_cs,
+                dst_rs,
+                read_dst,
+                lhs,
+                lhs_cs,
+                lhs_rs,
+                lhs_format,
+                lhs_scale,
+                rhs,
+                rhs_cs,
+                rhs_rs,
+                rhs_format,
+                rhs_scale,
+                alpha,
+                beta,
+                parallelism,
+                rounding,
+            )
+        };
+    }
+
+    if TypeId::of::<D>() == TypeId::of::<f32>() {
+        dispatch!(gemm_f16::fp8::f8::GEMM_F32_DST_WITH_ROUNDING, f32)
+    } else if TypeId::of::<D>() == TypeId::of::<f16>() {
+        dispatch!(gemm_f16::fp8::f8::GEMM_F16_DST_WITH_ROUNDING, f16)
+    } else if TypeId::of::<D>() == TypeId::of::<bf16>() {
+        dispatch!(gemm_f16::fp8::f8::GEMM_BF16_DST_WITH_ROUNDING, bf16)
+    } else {
+        panic!();
+    }
+}
+
 #[inline(never)]
 #[doc(hidden)]
 pub unsafe fn gemm_fallback<T>(
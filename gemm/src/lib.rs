@@ -3,11 +3,28 @@
 #![warn(rust_2018_idioms)]
 
 mod gemm;
+mod scalar;
+mod triangular;
 
 pub use crate::gemm::*;
+pub use crate::scalar::Scalar;
+pub use crate::triangular::{trmm, trsm, Diag, Side};
+pub use gemm_common::band::{band_gemm, BandMatrix};
+pub use gemm_common::bitmatrix::{gemm_and_or, gemm_xnor_popcount};
+pub use gemm_common::epilogue::{
+    Activation, Bias, Epilogue, ReductionView, Reductions, Triangle, TriangularMask,
+};
+pub use gemm_common::gemm::{GemmOutput, GemmTerm};
+pub use gemm_common::generic::{gemm_generic, GenericScalar};
+pub use gemm_common::modular::{gemm_modular, ModularScalar};
+pub use gemm_common::semiring::{gemm_semiring, LogSumExp, MaxPlus, MinPlus, Semiring};
+pub use gemm_common::sparse::{csc_gemm, csr_gemm, BlockMask, CscMatrix, CsrMatrix, SparseScalar};
+pub use gemm_common::sparse24::{gemm_sparse24, Sparse24Matrix};
 pub use gemm_common::Parallelism;
 
-pub use gemm_f16::f16;
+pub use gemm_f16::fp8::{Fp8Format, Fp8Scale};
+pub use gemm_f16::rounding::Rounding;
+pub use gemm_f16::{bf16, f16};
 
 #[cfg(test)]
 mod tests {
@@ -225,6 +242,3483 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_gemm_compensated() {
+        let mut mnks = vec![];
+        mnks.push((256, 256, 256));
+        mnks.push((64, 64, 4));
+        mnks.push((0, 64, 4));
+        mnks.push((64, 0, 4));
+        mnks.push((64, 64, 0));
+        mnks.push((16, 1, 1));
+        mnks.push((1, 1, 2));
+        mnks.push((4, 4, 4));
+        mnks.push((1024, 33, 1100));
+        mnks.push((63, 4, 10));
+        mnks.push((3, 63, 10));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for alpha in [0.0, 1.0, 2.3] {
+                    for beta in [0.0, 1.0, 2.3] {
+                        dbg!(alpha, beta, parallelism);
+                        let a_vec: Vec<f64> = (0..(m * k)).map(|_| rand::random()).collect();
+                        let b_vec: Vec<f64> = (0..(k * n)).map(|_| rand::random()).collect();
+                        let mut c_vec: Vec<f64> = (0..(m * n)).map(|_| rand::random()).collect();
+                        let mut d_vec = c_vec.clone();
+
+                        unsafe {
+                            gemm::gemm_compensated(
+                                m,
+                                n,
+                                k,
+                                c_vec.as_mut_ptr(),
+                                m as isize,
+                                1,
+                                true,
+                                a_vec.as_ptr(),
+                                m as isize,
+                                1,
+                                b_vec.as_ptr(),
+                                k as isize,
+                                1,
+                                alpha,
+                                beta,
+                                parallelism,
+                            );
+
+                            gemm::gemm_fallback(
+                                m,
+                                n,
+                                k,
+                                d_vec.as_mut_ptr(),
+                                m as isize,
+                                1,
+                                true,
+                                a_vec.as_ptr(),
+                                m as isize,
+                                1,
+                                b_vec.as_ptr(),
+                                k as isize,
+                                1,
+                                alpha,
+                                beta,
+                            );
+                        }
+                        for (c, d) in c_vec.iter().zip(d_vec.iter()) {
+                            assert_approx_eq::assert_approx_eq!(c, d);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_compensated_accuracy() {
+        // lhs×rhs sums `h` products, then the same products negated, then `1×(j + 1)`, so the
+        // exact result is `j + 1`, while plain accumulation loses it in the rounding noise.
+        let (m, n, h) = (13, 7, 20_000);
+        let k = 2 * h + 1;
+
+        let mut a_vec = vec![0.0f32; m * k];
+        let mut b_vec = vec![0.0f32; k * n];
+        for p in 0..h {
+            for i in 0..m {
+                let x = rand::random::<f32>();
+                a_vec[i + p * m] = x;
+                a_vec[i + (p + h) * m] = x;
+            }
+            for j in 0..n {
+                let y = rand::random::<f32>();
+                b_vec[p + j * k] = y;
+                b_vec[p + h + j * k] = -y;
+            }
+        }
+        for i in 0..m {
+            a_vec[i + 2 * h * m] = 1.0;
+        }
+        for j in 0..n {
+            b_vec[2 * h + j * k] = (j + 1) as f32;
+        }
+
+        for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+            let mut c_vec = vec![0.0f32; m * n];
+            unsafe {
+                gemm::gemm_compensated(
+                    m,
+                    n,
+                    k,
+                    c_vec.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    false,
+                    a_vec.as_ptr(),
+                    m as isize,
+                    1,
+                    b_vec.as_ptr(),
+                    k as isize,
+                    1,
+                    0.0,
+                    1.0,
+                    parallelism,
+                );
+            }
+            for j in 0..n {
+                for i in 0..m {
+                    assert_approx_eq::assert_approx_eq!(c_vec[i + j * m], (j + 1) as f32, 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_widening() {
+        let mut mnks = vec![];
+        mnks.push((256, 256, 256));
+        mnks.push((64, 64, 4));
+        mnks.push((0, 64, 4));
+        mnks.push((64, 0, 4));
+        mnks.push((64, 64, 0));
+        mnks.push((16, 1, 1));
+        mnks.push((1, 1, 2));
+        mnks.push((4, 4, 4));
+        mnks.push((1024, 33, 1100));
+        mnks.push((63, 4, 10));
+        mnks.push((3, 63, 10));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for alpha in [0.0, 1.0, 2.3] {
+                    for beta in [0.0, 1.0, 2.3] {
+                        dbg!(alpha, beta, parallelism);
+                        let a_vec: Vec<f32> = (0..(m * k)).map(|_| rand::random()).collect();
+                        let b_vec: Vec<f32> = (0..(k * n)).map(|_| rand::random()).collect();
+                        let c_vec: Vec<f32> = (0..(m * n)).map(|_| rand::random()).collect();
+
+                        let a_wide: Vec<f64> = a_vec.iter().map(|&x| x as f64).collect();
+                        let b_wide: Vec<f64> = b_vec.iter().map(|&x| x as f64).collect();
+                        let mut expected: Vec<f64> = c_vec.iter().map(|&x| x as f64).collect();
+
+                        let mut dst_f32 = c_vec.clone();
+                        let mut dst_f64: Vec<f64> = (0..(m * n))
+                            .map(|idx| c_vec[idx / n + (idx % n) * m] as f64)
+                            .collect();
+
+                        unsafe {
+                            gemm::gemm_widening(
+                                m,
+                                n,
+                                k,
+                                dst_f32.as_mut_ptr(),
+                                m as isize,
+                                1,
+                                true,
+                                a_vec.as_ptr(),
+                                m as isize,
+                                1,
+                                b_vec.as_ptr(),
+                                k as isize,
+                                1,
+                                alpha,
+                                beta,
+                                parallelism,
+                            );
+                            // row major destination
+                            gemm::gemm_widening(
+                                m,
+                                n,
+                                k,
+                                dst_f64.as_mut_ptr(),
+                                1,
+                                n as isize,
+                                true,
+                                a_vec.as_ptr(),
+                                m as isize,
+                                1,
+                                b_vec.as_ptr(),
+                                k as isize,
+                                1,
+                                alpha,
+                                beta,
+                                parallelism,
+                            );
+
+                            gemm::gemm_fallback(
+                                m,
+                                n,
+                                k,
+                                expected.as_mut_ptr(),
+                                m as isize,
+                                1,
+                                true,
+                                a_wide.as_ptr(),
+                                m as isize,
+                                1,
+                                b_wide.as_ptr(),
+                                k as isize,
+                                1,
+                                alpha,
+                                beta,
+                            );
+                        }
+                        for j in 0..n {
+                            for i in 0..m {
+                                let expected = expected[i + j * m];
+                                // rounded once, up to ties between the two summation orders
+                                assert_approx_eq::assert_approx_eq!(
+                                    dst_f32[i + j * m],
+                                    expected as f32,
+                                    f32::EPSILON * (expected as f32).abs().max(1.0)
+                                );
+                                assert_approx_eq::assert_approx_eq!(
+                                    dst_f64[i * n + j],
+                                    expected,
+                                    1e-12 * expected.abs().max(1.0)
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn fp8_reference(code: u8, exp_bits: i32, man_bits: i32, ieee_special: bool) -> f32 {
+        let sign = if code >> 7 == 1 { -1.0f32 } else { 1.0f32 };
+        let exp = ((code >> man_bits) & ((1 << exp_bits) - 1)) as i32;
+        let man = (code & ((1 << man_bits) - 1)) as i32;
+        let bias = (1 << (exp_bits - 1)) - 1;
+        let max_exp = (1 << exp_bits) - 1;
+        if exp == max_exp && ieee_special {
+            return if man == 0 {
+                sign * f32::INFINITY
+            } else {
+                f32::NAN
+            };
+        }
+        if exp == max_exp && man == (1 << man_bits) - 1 {
+            return f32::NAN;
+        }
+        let frac = man as f32 / (1 << man_bits) as f32;
+        if exp == 0 {
+            sign * frac * 2.0f32.powi(1 - bias)
+        } else {
+            sign * (1.0 + frac) * 2.0f32.powi(exp - bias)
+        }
+    }
+
+    #[test]
+    fn test_fp8_decode() {
+        for (format, exp_bits, man_bits, ieee_special) in [
+            (Fp8Format::E4M3, 4, 3, false),
+            (Fp8Format::E5M2, 5, 2, true),
+        ] {
+            for code in 0..=255u8 {
+                let expected = fp8_reference(code, exp_bits, man_bits, ieee_special);
+                let decoded = format.to_f32(code);
+                if expected.is_nan() {
+                    assert!(decoded.is_nan(), "{format:?} {code:#04x}");
+                } else {
+                    assert_eq!(
+                        decoded.to_bits(),
+                        expected.to_bits(),
+                        "{format:?} {code:#04x}"
+                    );
+                }
+            }
+        }
+
+        assert_eq!(Fp8Format::E4M3.to_f32(0x7e), 448.0);
+        assert_eq!(Fp8Format::E4M3.to_f32(0x01), 2.0f32.powi(-9));
+        assert_eq!(Fp8Format::E4M3.to_f32(0x80).to_bits(), (-0.0f32).to_bits());
+        assert_eq!(
+            Fp8Format::E4M3.lut().iter().filter(|x| x.is_nan()).count(),
+            2
+        );
+        assert_eq!(Fp8Format::E5M2.to_f32(0x7b), 57344.0);
+        assert_eq!(Fp8Format::E5M2.to_f32(0x7c), f32::INFINITY);
+        assert_eq!(Fp8Format::E5M2.to_f32(0xfc), f32::NEG_INFINITY);
+        assert_eq!(Fp8Format::E5M2.to_f32(0x01), 2.0f32.powi(-16));
+        assert_eq!(
+            Fp8Format::E5M2.lut().iter().filter(|x| x.is_nan()).count(),
+            6
+        );
+    }
+
+    fn test_gemm_fp8_dst<D: Copy + 'static + num_traits::AsPrimitive<f64>>(dst_eps: f64)
+    where
+        f64: num_traits::AsPrimitive<D>,
+    {
+        use num_traits::AsPrimitive;
+
+        // codes of small finite values, so that the products fit in `f16`
+        let random_codes = |format: Fp8Format, len: usize| -> Vec<u8> {
+            let mut codes = Vec::with_capacity(len);
+            while codes.len() < len {
+                let code: u8 = rand::random();
+                if format.to_f32(code).abs() <= 2.0 {
+                    codes.push(code);
+                }
+            }
+            codes
+        };
+
+        let mut mnks = vec![];
+        mnks.push((64, 64, 4));
+        mnks.push((0, 64, 4));
+        mnks.push((64, 0, 4));
+        mnks.push((64, 64, 0));
+        mnks.push((16, 1, 1));
+        mnks.push((1, 1, 2));
+        mnks.push((4, 4, 4));
+        mnks.push((1024, 33, 700));
+        mnks.push((63, 4, 10));
+        mnks.push((3, 63, 10));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for (lhs_format, rhs_format) in [
+                    (Fp8Format::E4M3, Fp8Format::E5M2),
+                    (Fp8Format::E5M2, Fp8Format::E4M3),
+                ] {
+                    for per_row in [false, true] {
+                        let alpha = 1.5;
+                        let beta = 0.75;
+
+                        let a_vec = random_codes(lhs_format, m * k);
+                        let b_vec = random_codes(rhs_format, k * n);
+                        let c_vec: Vec<f64> = (0..(m * n)).map(|_| rand::random()).collect();
+                        let lhs_scales: Vec<f32> =
+                            (0..m).map(|_| 0.5 + rand::random::<f32>()).collect();
+                        let rhs_scales: Vec<f32> =
+                            (0..n).map(|_| 0.5 + rand::random::<f32>()).collect();
+
+                        let (lhs_scale, rhs_scale) = if per_row {
+                            (
+                                Fp8Scale::PerRow(lhs_scales.as_ptr()),
+                                Fp8Scale::PerRow(rhs_scales.as_ptr()),
+                            )
+                        } else {
+                            (Fp8Scale::PerTensor(1.25), Fp8Scale::PerTensor(0.25))
+                        };
+                        let lhs_scale_at = |i: usize| match lhs_scale {
+                            Fp8Scale::PerTensor(scale) => scale as f64,
+                            Fp8Scale::PerRow(_) => lhs_scales[i] as f64,
+                        };
+                        let rhs_scale_at = |j: usize| match rhs_scale {
+                            Fp8Scale::PerTensor(scale) => scale as f64,
+                            Fp8Scale::PerRow(_) => rhs_scales[j] as f64,
+                        };
+
+                        // column major lhs, and row major lhs, which is packed without the
+                        // contiguous fast path
+                        let a_row_major: Vec<u8> = (0..(m * k))
+                            .map(|idx| a_vec[idx / k + (idx % k) * m])
+                            .collect();
+
+                        let mut expected = c_vec.clone();
+                        let mut expected_abs: Vec<f64> = c_vec.iter().map(|x| x.abs()).collect();
+                        for j in 0..n {
+                            for i in 0..m {
+                                let mut acc = 0.0;
+                                let mut acc_abs = 0.0;
+                                for depth in 0..k {
+                                    let a = lhs_format.to_f32(a_vec[i + depth * m]) as f64
+                                        * lhs_scale_at(i);
+                                    let b = rhs_format.to_f32(b_vec[depth + j * k]) as f64
+                                        * rhs_scale_at(j);
+                                    acc += a * b;
+                                    acc_abs += (a * b).abs();
+                                }
+                                expected[i + j * m] = alpha * expected[i + j * m] + beta * acc;
+                                expected_abs[i + j * m] =
+                                    alpha * expected_abs[i + j * m] + beta * acc_abs;
+                            }
+                        }
+
+                        let mut dst_col_major: Vec<D> = c_vec.iter().map(|&x| x.as_()).collect();
+                        let mut dst_row_major: Vec<D> = (0..(m * n))
+                            .map(|idx| c_vec[idx / n + (idx % n) * m].as_())
+                            .collect();
+
+                        unsafe {
+                            gemm::gemm_fp8(
+                                m,
+                                n,
+                                k,
+                                dst_col_major.as_mut_ptr(),
+                                m as isize,
+                                1,
+                                true,
+                                a_vec.as_ptr(),
+                                m as isize,
+                                1,
+                                lhs_format,
+                                lhs_scale,
+                                b_vec.as_ptr(),
+                                k as isize,
+                                1,
+                                rhs_format,
+                                rhs_scale,
+                                alpha as f32,
+                                beta as f32,
+                                parallelism,
+                            );
+                            gemm::gemm_fp8(
+                                m,
+                                n,
+                                k,
+                                dst_row_major.as_mut_ptr(),
+                                1,
+                                n as isize,
+                                true,
+                                a_row_major.as_ptr(),
+                                1,
+                                k as isize,
+                                lhs_format,
+                                lhs_scale,
+                                b_vec.as_ptr(),
+                                k as isize,
+                                1,
+                                rhs_format,
+                                rhs_scale,
+                                alpha as f32,
+                                beta as f32,
+                                parallelism,
+                            );
+                        }
+
+                        for j in 0..n {
+                            for i in 0..m {
+                                let expected_abs = expected_abs[i + j * m];
+                                let expected = expected[i + j * m];
+                                let tol = (dst_eps + (k + 4) as f64 * f32::EPSILON as f64)
+                                    * expected_abs.max(1e-3);
+                                assert_approx_eq::assert_approx_eq!(
+                                    dst_col_major[i + j * m].as_(),
+                                    expected,
+                                    tol
+                                );
+                                assert_approx_eq::assert_approx_eq!(
+                                    dst_row_major[i * n + j].as_(),
+                                    expected,
+                                    tol
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_fp8() {
+        test_gemm_fp8_dst::<f32>(f32::EPSILON as f64);
+        test_gemm_fp8_dst::<f16>(f16::EPSILON.to_f64());
+        test_gemm_fp8_dst::<bf16>(bf16::EPSILON.to_f64());
+    }
+
+    #[test]
+    fn test_gemm_stochastic_rounding() {
+        // 1 + 2^-13 lies 1/8 of the way between the neighboring f16 values 1 and 1 + 2^-10
+        let m = 256;
+        let n = 256;
+        let k = 1;
+        let update = 2.0f32.powi(-13);
+
+        let a_vec = vec![f16::from_f32(update); m * k];
+        let b_vec = vec![f16::ONE; k * n];
+
+        let run = |rounding: Rounding, parallelism: Parallelism| {
+            let mut dst = vec![f16::ONE; m * n];
+            unsafe {
+                gemm::gemm_with_rounding(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a_vec.as_ptr(),
+                    m as isize,
+                    1,
+                    b_vec.as_ptr(),
+                    k as isize,
+                    1,
+                    f16::ONE,
+                    f16::ONE,
+                    false,
+                    false,
+                    false,
+                    parallelism,
+                    rounding,
+                );
+            }
+            dst
+        };
+
+        let nearest = run(Rounding::Nearest, Parallelism::None);
+        assert!(nearest.iter().all(|&x| x == f16::ONE));
+
+        let stochastic = run(Rounding::Stochastic { seed: 0 }, Parallelism::None);
+        let up = f16::from_f32(1.0 + 2.0f32.powi(-10));
+        assert!(stochastic.iter().all(|&x| x == f16::ONE || x == up));
+        let frac_up =
+            stochastic.iter().filter(|&&x| x == up).count() as f64 / stochastic.len() as f64;
+        assert!((frac_up - 0.125).abs() < 0.01);
+
+        // reproducible, independently of the number of threads
+        assert_eq!(
+            stochastic,
+            run(Rounding::Stochastic { seed: 0 }, Parallelism::Rayon(0))
+        );
+        assert_eq!(
+            stochastic,
+            run(Rounding::Stochastic { seed: 0 }, Parallelism::None)
+        );
+        assert_ne!(
+            stochastic,
+            run(Rounding::Stochastic { seed: 1 }, Parallelism::None)
+        );
+    }
+
+    #[test]
+    fn test_gemm_fp8_stochastic_rounding() {
+        // 1 + 2^-9 lies 1/4 of the way between the neighboring bf16 values 1 and 1 + 2^-7
+        let m = 256;
+        let n = 256;
+        let k = 1;
+        let update = 0x01u8;
+        assert_eq!(Fp8Format::E4M3.to_f32(update), 2.0f32.powi(-9));
+        let one = 0x38u8;
+        assert_eq!(Fp8Format::E4M3.to_f32(one), 1.0);
+
+        let a_vec = vec![update; m * k];
+        let b_vec = vec![one; k * n];
+
+        let run = |rounding: Rounding| {
+            let mut dst = vec![bf16::ONE; m * n];
+            unsafe {
+                gemm::gemm_fp8_with_rounding(
+                    m,
+                    n,
+                    k,
+                    dst.as_mut_ptr(),
+                    m as isize,
+                    1,
+                    true,
+                    a_vec.as_ptr(),
+                    m as isize,
+                    1,
+                    Fp8Format::E4M3,
+                    Fp8Scale::PerTensor(1.0),
+                    b_vec.as_ptr(),
+                    k as isize,
+                    1,
+                    Fp8Format::E4M3,
+                    Fp8Scale::PerTensor(1.0),
+                    1.0,
+                    1.0,
+                    Parallelism::Rayon(0),
+                    rounding,
+                );
+            }
+            dst
+        };
+
+        assert!(run(Rounding::Nearest).iter().all(|&x| x == bf16::ONE));
+
+        let stochastic = run(Rounding::Stochastic { seed: 42 });
+        let up = bf16::from_f32(1.0 + 2.0f32.powi(-7));
+        assert!(stochastic.iter().all(|&x| x == bf16::ONE || x == up));
+        let frac_up =
+            stochastic.iter().filter(|&&x| x == up).count() as f64 / stochastic.len() as f64;
+        assert!((frac_up - 0.25).abs() < 0.01);
+        assert_eq!(stochastic, run(Rounding::Stochastic { seed: 42 }));
+    }
+
+    #[test]
+    fn test_gemm_generic() {
+        let mut mnks = vec![];
+        mnks.push((256, 256, 256));
+        mnks.push((64, 64, 4));
+        mnks.push((0, 64, 4));
+        mnks.push((64, 0, 4));
+        mnks.push((64, 64, 0));
+        mnks.push((16, 1, 1));
+        mnks.push((1, 1, 2));
+        mnks.push((4, 4, 4));
+        mnks.push((1024, 33, 1100));
+        mnks.push((63, 4, 10));
+        mnks.push((3, 63, 10));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for read_dst in [false, true] {
+                    for (alpha, beta) in [(0i64, 1i64), (1, 1), (-3, 2)] {
+                        let random = || rand::random::<i64>() % 1000;
+                        let a_vec: Vec<i64> = (0..(m * k)).map(|_| random()).collect();
+                        let b_vec: Vec<i64> = (0..(k * n)).map(|_| random()).collect();
+                        let c_vec: Vec<i64> = (0..(m * n)).map(|_| random()).collect();
+
+                        let mut dst = c_vec.clone();
+                        let mut expected = c_vec.clone();
+
+                        unsafe {
+                            gemm_generic(
+                                m,
+                                n,
+                                k,
+                                dst.as_mut_ptr(),
+                                1,
+                                n as isize,
+                                read_dst,
+                                a_vec.as_ptr(),
+                                m as isize,
+                                1,
+                                b_vec.as_ptr(),
+                                1,
+                                n as isize,
+                                alpha,
+                                beta,
+                                false,
+                                false,
+                                false,
+                                parallelism,
+                            );
+                            gemm::gemm_fallback(
+                                m,
+                                n,
+                                k,
+                                expected.as_mut_ptr(),
+                                1,
+                                n as isize,
+                                read_dst,
+                                a_vec.as_ptr(),
+                                m as isize,
+                                1,
+                                b_vec.as_ptr(),
+                                1,
+                                n as isize,
+                                alpha,
+                                beta,
+                            );
+                        }
+                        assert_eq!(dst, expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_generic_conj() {
+        type C = num_complex::Complex<i64>;
+
+        let m = 37;
+        let n = 21;
+        let k = 45;
+        let random = || C::new(rand::random::<i64>() % 100, rand::random::<i64>() % 100);
+        let a_vec: Vec<C> = (0..(m * k)).map(|_| random()).collect();
+        let b_vec: Vec<C> = (0..(k * n)).map(|_| random()).collect();
+        let c_vec: Vec<C> = (0..(m * n)).map(|_| random()).collect();
+        let alpha = C::new(2, -1);
+        let beta = C::new(-3, 4);
+
+        for conj_dst in [false, true] {
+            for conj_lhs in [false, true] {
+                for conj_rhs in [false, true] {
+                    let mut dst = c_vec.clone();
+                    unsafe {
+                        gemm_generic(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            true,
+                            a_vec.as_ptr(),
+                            m as isize,
+                            1,
+                            b_vec.as_ptr(),
+                            k as isize,
+                            1,
+                            alpha,
+                            beta,
+                            conj_dst,
+                            conj_lhs,
+                            conj_rhs,
+                            Parallelism::Rayon(0),
+                        );
+                    }
+
+                    let conj_if = |x: C, conj: bool| if conj { x.conj() } else { x };
+                    for j in 0..n {
+                        for i in 0..m {
+                            let mut acc = C::new(0, 0);
+                            for depth in 0..k {
+                                acc += conj_if(a_vec[i + depth * m], conj_lhs)
+                                    * conj_if(b_vec[depth + j * k], conj_rhs);
+                            }
+                            let expected = alpha * conj_if(c_vec[i + j * m], conj_dst) + beta * acc;
+                            assert_eq!(dst[i + j * m], expected);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_generic_user_type() {
+        // dual numbers, to differentiate a matrix product with respect to a scalar parameter
+        #[derive(Copy, Clone, Debug, PartialEq)]
+        struct Dual {
+            value: f64,
+            grad: f64,
+        }
+
+        unsafe impl GenericScalar for Dual {
+            fn zero() -> Self {
+                Dual {
+                    value: 0.0,
+                    grad: 0.0,
+                }
+            }
+            fn one() -> Self {
+                Dual {
+                    value: 1.0,
+                    grad: 0.0,
+                }
+            }
+            fn mul_add(self, a: Self, b: Self) -> Self {
+                Dual {
+                    value: self.value * a.value + b.value,
+                    grad: self.grad * a.value + self.value * a.grad + b.grad,
+                }
+            }
+            fn conj(self) -> Self {
+                self
+            }
+        }
+
+        let m = 45;
+        let n = 27;
+        let k = 300;
+        // lhs = t × a, rhs = b, so d(lhs×rhs)/dt = a×b
+        let a_vec: Vec<f64> = (0..(m * k)).map(|_| rand::random()).collect();
+        let b_vec: Vec<f64> = (0..(k * n)).map(|_| rand::random()).collect();
+        let t = 0.5;
+
+        let lhs: Vec<Dual> = a_vec
+            .iter()
+            .map(|&a| Dual {
+                value: t * a,
+                grad: a,
+            })
+            .collect();
+        let rhs: Vec<Dual> = b_vec
+            .iter()
+            .map(|&b| Dual {
+                value: b,
+                grad: 0.0,
+            })
+            .collect();
+        let mut dst = vec![Dual::zero(); m * n];
+        let mut expected = vec![0.0; m * n];
+
+        unsafe {
+            gemm_generic(
+                m,
+                n,
+                k,
+                dst.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                lhs.as_ptr(),
+                m as isize,
+                1,
+                rhs.as_ptr(),
+                k as isize,
+                1,
+                Dual::zero(),
+                Dual::one(),
+                false,
+                false,
+                false,
+                Parallelism::Rayon(0),
+            );
+            gemm::gemm_fallback(
+                m,
+                n,
+                k,
+                expected.as_mut_ptr(),
+                m as isize,
+                1,
+                false,
+                a_vec.as_ptr(),
+                m as isize,
+                1,
+                b_vec.as_ptr(),
+                k as isize,
+                1,
+                0.0,
+                1.0,
+            );
+        }
+
+        for (dst, expected) in dst.iter().zip(&expected) {
+            assert_approx_eq::assert_approx_eq!(dst.value, t * expected, 1e-10);
+            assert_approx_eq::assert_approx_eq!(dst.grad, *expected, 1e-10);
+        }
+    }
+
+    fn naive_semiring<T: Copy>(
+        m: usize,
+        n: usize,
+        k: usize,
+        dst: &mut [T],
+        read_dst: bool,
+        lhs: &[T],
+        rhs: &[T],
+        zero: T,
+        add: impl Fn(T, T) -> T,
+        mul: impl Fn(T, T) -> T,
+    ) {
+        // col-major dst and lhs, row-major rhs
+        for j in 0..n {
+            for i in 0..m {
+                let mut acc = zero;
+                for depth in 0..k {
+                    acc = add(acc, mul(lhs[i + depth * m], rhs[depth * n + j]));
+                }
+                let dst = &mut dst[i + j * m];
+                *dst = if read_dst { add(*dst, acc) } else { acc };
+            }
+        }
+    }
+
+    fn test_gemm_semiring_tropical<R: Semiring<f32> + Semiring<f64>>(
+        add: fn(f64, f64) -> f64,
+        add_f32: fn(f32, f32) -> f32,
+    ) {
+        let mut mnks = vec![];
+        mnks.push((64, 64, 4));
+        mnks.push((0, 64, 4));
+        mnks.push((64, 0, 4));
+        mnks.push((64, 64, 0));
+        mnks.push((16, 1, 1));
+        mnks.push((1, 1, 2));
+        mnks.push((1024, 33, 1100));
+        mnks.push((63, 4, 10));
+        mnks.push((3, 63, 10));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for read_dst in [false, true] {
+                    // small integers, so that the results are exact
+                    let random = || (rand::random::<u32>() % 1000) as f64;
+                    let a_vec: Vec<f64> = (0..(m * k)).map(|_| random()).collect();
+                    let b_vec: Vec<f64> = (0..(k * n)).map(|_| random()).collect();
+                    let c_vec: Vec<f64> = (0..(m * n)).map(|_| 2.0 * random()).collect();
+
+                    let mut dst = c_vec.clone();
+                    let mut expected = c_vec.clone();
+                    unsafe {
+                        gemm_semiring::<f64, R>(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            read_dst,
+                            a_vec.as_ptr(),
+                            m as isize,
+                            1,
+                            b_vec.as_ptr(),
+                            1,
+                            n as isize,
+                            parallelism,
+                        );
+                    }
+                    naive_semiring(
+                        m,
+                        n,
+                        k,
+                        &mut expected,
+                        read_dst,
+                        &a_vec,
+                        &b_vec,
+                        <R as Semiring<f64>>::zero(),
+                        add,
+                        |a, b| a + b,
+                    );
+                    assert_eq!(dst, expected);
+
+                    let a_vec: Vec<f32> = a_vec.iter().map(|&x| x as f32).collect();
+                    let b_vec: Vec<f32> = b_vec.iter().map(|&x| x as f32).collect();
+                    let mut dst: Vec<f32> = c_vec.iter().map(|&x| x as f32).collect();
+                    unsafe {
+                        gemm_semiring::<f32, R>(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            read_dst,
+                            a_vec.as_ptr(),
+                            m as isize,
+                            1,
+                            b_vec.as_ptr(),
+                            1,
+                            n as isize,
+                            parallelism,
+                        );
+                    }
+                    let mut expected: Vec<f32> = c_vec.iter().map(|&x| x as f32).collect();
+                    naive_semiring(
+                        m,
+                        n,
+                        k,
+                        &mut expected,
+                        read_dst,
+                        &a_vec,
+                        &b_vec,
+                        <R as Semiring<f32>>::zero(),
+                        add_f32,
+                        |a, b| a + b,
+                    );
+                    assert_eq!(dst, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_semiring_min_plus() {
+        test_gemm_semiring_tropical::<MinPlus>(f64::min, f32::min);
+    }
+
+    #[test]
+    fn test_gemm_semiring_max_plus() {
+        test_gemm_semiring_tropical::<MaxPlus>(f64::max, f32::max);
+    }
+
+    #[test]
+    fn test_gemm_semiring_log_sum_exp() {
+        // log(exp(a) + exp(b)) in f64, with the libm functions
+        let logaddexp = |a: f64, b: f64| {
+            if a == f64::NEG_INFINITY && b == f64::NEG_INFINITY {
+                return a;
+            }
+            let max = a.max(b);
+            max + ((a - max).exp() + (b - max).exp()).ln()
+        };
+
+        for (m, n, k) in [(64, 64, 4), (1, 1, 2), (257, 33, 300), (63, 4, 10)] {
+            for read_dst in [false, true] {
+                // log probabilities, with some impossible events
+                let random = || {
+                    if rand::random::<f64>() < 0.125 {
+                        f64::NEG_INFINITY
+                    } else {
+                        -30.0 * rand::random::<f64>()
+                    }
+                };
+                let a_vec: Vec<f64> = (0..(m * k)).map(|_| random()).collect();
+                let b_vec: Vec<f64> = (0..(k * n)).map(|_| random()).collect();
+                let c_vec: Vec<f64> = (0..(m * n)).map(|_| random()).collect();
+
+                let mut expected = c_vec.clone();
+                naive_semiring(
+                    m,
+                    n,
+                    k,
+                    &mut expected,
+                    read_dst,
+                    &a_vec,
+                    &b_vec,
+                    f64::NEG_INFINITY,
+                    logaddexp,
+                    |a, b| a + b,
+                );
+
+                let mut dst = c_vec.clone();
+                unsafe {
+                    gemm_semiring::<f64, LogSumExp>(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        read_dst,
+                        a_vec.as_ptr(),
+                        m as isize,
+                        1,
+                        b_vec.as_ptr(),
+                        1,
+                        n as isize,
+                        Parallelism::Rayon(0),
+                    );
+                }
+                for (&dst, &expected) in dst.iter().zip(&expected) {
+                    if expected == f64::NEG_INFINITY {
+                        assert_eq!(dst, expected);
+                    } else {
+                        assert_approx_eq::assert_approx_eq!(
+                            dst,
+                            expected,
+                            1e-12 * expected.abs().max(1.0)
+                        );
+                    }
+                }
+
+                let a_vec: Vec<f32> = a_vec.iter().map(|&x| x as f32).collect();
+                let b_vec: Vec<f32> = b_vec.iter().map(|&x| x as f32).collect();
+                let mut dst: Vec<f32> = c_vec.iter().map(|&x| x as f32).collect();
+                unsafe {
+                    gemm_semiring::<f32, LogSumExp>(
+                        m,
+                        n,
+                        k,
+                        dst.as_mut_ptr(),
+                        m as isize,
+                        1,
+                        read_dst,
+                        a_vec.as_ptr(),
+                        m as isize,
+                        1,
+                        b_vec.as_ptr(),
+                        1,
+                        n as isize,
+                        Parallelism::None,
+                    );
+                }
+                for (&dst, &expected) in dst.iter().zip(&expected) {
+                    if expected == f64::NEG_INFINITY {
+                        assert_eq!(dst, f32::NEG_INFINITY);
+                    } else {
+                        assert_approx_eq::assert_approx_eq!(
+                            dst as f64,
+                            expected,
+                            1e-5 * expected.abs().max(1.0)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // exact reference: the dot products are summed without any reduction, as 256-bit integers
+    fn reference_modular<T: ModularScalar + Into<u128> + TryFrom<u128>>(
+        m: usize,
+        n: usize,
+        k: usize,
+        dst: &mut [T],
+        read_dst: bool,
+        lhs: &[T],
+        rhs: &[T],
+        alpha: T,
+        beta: T,
+        modulus: T,
+    ) where
+        <T as TryFrom<u128>>::Error: core::fmt::Debug,
+    {
+        let p: u128 = modulus.into();
+        let pow2_128 = (u128::MAX % p + 1) % p;
+        let mul_mod = |a: u128, b: u128| {
+            let (a, b) = (a % p, b % p);
+            // p < 2^62, so a×b < 2^124
+            a * b % p
+        };
+
+        // col-major dst and lhs, row-major rhs
+        for j in 0..n {
+            for i in 0..m {
+                let (mut hi, mut lo) = (0u128, 0u128);
+                for depth in 0..k {
+                    let prod = lhs[i + depth * m].into() * rhs[depth * n + j].into();
+                    let (sum, carry) = lo.overflowing_add(prod);
+                    lo = sum;
+                    hi += carry as u128;
+                }
+                let acc = (mul_mod(hi, pow2_128) + lo % p) % p;
+                let dst = &mut dst[i + j * m];
+                let old = if read_dst {
+                    mul_mod(alpha.into(), (*dst).into())
+                } else {
+                    0
+                };
+                *dst = T::try_from((old + mul_mod(beta.into(), acc)) % p).unwrap();
+            }
+        }
+    }
+
+    fn test_gemm_modular_ty<
+        T: ModularScalar + Into<u128> + TryFrom<u128> + PartialEq + core::fmt::Debug,
+    >(
+        moduli: &[T],
+    ) where
+        <T as TryFrom<u128>>::Error: core::fmt::Debug,
+    {
+        let mut mnks = vec![];
+        mnks.push((64, 64, 4));
+        mnks.push((0, 64, 4));
+        mnks.push((64, 0, 4));
+        mnks.push((64, 64, 0));
+        mnks.push((16, 1, 1));
+        mnks.push((1, 1, 2));
+        mnks.push((257, 33, 1100));
+        mnks.push((63, 4, 10));
+        mnks.push((3, 63, 10));
+
+        for &modulus in moduli {
+            let p: u128 = modulus.into();
+            for &(m, n, k) in &mnks {
+                dbg!(p, m, n, k);
+                for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                    for read_dst in [false, true] {
+                        for worst_case in [false, true] {
+                            // the worst case uses the largest residue everywhere, to check
+                            // that the accumulators never overflow
+                            let random = || {
+                                let x = if worst_case {
+                                    p - 1
+                                } else {
+                                    rand::random::<u128>() % p
+                                };
+                                T::try_from(x).unwrap()
+                            };
+                            let a_vec: Vec<T> = (0..(m * k)).map(|_| random()).collect();
+                            let b_vec: Vec<T> = (0..(k * n)).map(|_| random()).collect();
+                            let c_vec: Vec<T> = (0..(m * n)).map(|_| random()).collect();
+                            let alpha = random();
+                            let beta = random();
+
+                            let mut dst = c_vec.clone();
+                            let mut expected = c_vec.clone();
+                            unsafe {
+                                gemm_modular(
+                                    m,
+                                    n,
+                                    k,
+                                    dst.as_mut_ptr(),
+                                    m as isize,
+                                    1,
+                                    read_dst,
+                                    a_vec.as_ptr(),
+                                    m as isize,
+                                    1,
+                                    b_vec.as_ptr(),
+                                    1,
+                                    n as isize,
+                                    alpha,
+                                    beta,
+                                    modulus,
+                                    parallelism,
+                                );
+                            }
+                            reference_modular(
+                                m,
+                                n,
+                                k,
+                                &mut expected,
+                                read_dst,
+                                &a_vec,
+                                &b_vec,
+                                alpha,
+                                beta,
+                                modulus,
+                            );
+                            assert_eq!(dst, expected);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_modular() {
+        test_gemm_modular_ty::<u32>(&[2, 65537, (1 << 31) - 1]);
+        test_gemm_modular_ty::<u64>(&[3, (1 << 61) - 1, (1 << 62) - 57]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_gemm_modular_modulus_too_large() {
+        let mut dst = [0u32];
+        unsafe {
+            gemm_modular(
+                1,
+                1,
+                1,
+                dst.as_mut_ptr(),
+                1,
+                1,
+                false,
+                [1u32].as_ptr(),
+                1,
+                1,
+                [1u32].as_ptr(),
+                1,
+                1,
+                0,
+                1,
+                1 << 31,
+                Parallelism::None,
+            );
+        }
+    }
+
+    #[test]
+    fn test_gemm_bit_matrix() {
+        let mut mnks = vec![];
+        mnks.push((64, 64, 64));
+        mnks.push((0, 64, 4));
+        mnks.push((64, 0, 4));
+        mnks.push((17, 70, 0));
+        mnks.push((1, 1, 1));
+        mnks.push((5, 130, 63));
+        mnks.push((33, 65, 129));
+        mnks.push((300, 200, 5000));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            // one extra word per row, and random bits past `k`, which must be ignored
+            let k_stride = k / 64 + 2;
+            let n_stride = n / 64 + 2;
+            let random_bits = |len: usize| (0..len).map(|_| rand::random::<u64>()).collect();
+            // sparse bits, so that the boolean products aren't all ones
+            let sparse_bits = |len: usize| {
+                (0..len)
+                    .map(|_| rand::random::<u64>() & rand::random::<u64>() & rand::random::<u64>())
+                    .collect()
+            };
+
+            let get = |bits: &[u64], row: usize, stride: usize, p: usize| {
+                (bits[row * stride + p / 64] >> (p % 64)) & 1 == 1
+            };
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for read_dst in [false, true] {
+                    for sparse in [false, true] {
+                        let generate = if sparse { sparse_bits } else { random_bits };
+                        let lhs: Vec<u64> = generate(m * k_stride);
+                        let rhs: Vec<u64> = generate(n * k_stride);
+                        let bool_dst: Vec<u64> = sparse_bits(m * n_stride);
+                        let count_dst: Vec<u32> =
+                            (0..m * n).map(|_| rand::random::<u32>() % 1000).collect();
+
+                        let mut dst = bool_dst.clone();
+                        let mut counts = count_dst.clone();
+                        unsafe {
+                            gemm_and_or(
+                                m,
+                                n,
+                                k,
+                                dst.as_mut_ptr(),
+                                n_stride as isize,
+                                read_dst,
+                                lhs.as_ptr(),
+                                k_stride as isize,
+                                rhs.as_ptr(),
+                                k_stride as isize,
+                                parallelism,
+                            );
+                            gemm_xnor_popcount(
+                                m,
+                                n,
+                                k,
+                                counts.as_mut_ptr(),
+                                1,
+                                n as isize,
+                                read_dst,
+                                lhs.as_ptr(),
+                                k_stride as isize,
+                                rhs.as_ptr(),
+                                k_stride as isize,
+                                parallelism,
+                            );
+                        }
+
+                        for i in 0..m {
+                            for j in 0..n_stride * 64 {
+                                let actual = get(&dst, i, n_stride, j);
+                                let old = get(&bool_dst, i, n_stride, j);
+                                if j >= n {
+                                    assert_eq!(actual, old);
+                                    continue;
+                                }
+
+                                let mut any = read_dst && old;
+                                let mut count = if read_dst { count_dst[i * n + j] } else { 0 };
+                                for p in 0..k {
+                                    let a = get(&lhs, i, k_stride, p);
+                                    let b = get(&rhs, j, k_stride, p);
+                                    any |= a && b;
+                                    count += (a == b) as u32;
+                                }
+                                assert_eq!(actual, any);
+                                assert_eq!(counts[i * n + j], count);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // random column major operands of an `m×n` product of depth `k`, with the product computed
+    // in `f64` and the sum of the absolute values of its terms, which bounds the rounding error
+    struct RealOperands<T> {
+        a: Vec<T>,
+        b: Vec<T>,
+        c: Vec<T>,
+        product: Vec<f64>,
+        abs_product: Vec<f64>,
+    }
+
+    fn random_vec<T: Float>(len: usize) -> Vec<T> {
+        (0..len)
+            .map(|_| T::from(rand::random::<f64>() * 2.0 - 1.0).unwrap())
+            .collect()
+    }
+
+    impl<T: Float> RealOperands<T> {
+        fn random(m: usize, n: usize, k: usize) -> Self {
+            Self::new(
+                m,
+                n,
+                k,
+                random_vec(m * k),
+                random_vec(k * n),
+                random_vec(m * n),
+            )
+        }
+
+        fn new(m: usize, n: usize, k: usize, a: Vec<T>, b: Vec<T>, c: Vec<T>) -> Self {
+            let mut product = vec![0.0; m * n];
+            let mut abs_product = vec![0.0; m * n];
+            for j in 0..n {
+                for i in 0..m {
+                    for depth in 0..k {
+                        let x =
+                            a[i + depth * m].to_f64().unwrap() * b[depth + j * k].to_f64().unwrap();
+                        product[i + j * m] += x;
+                        abs_product[i + j * m] += x.abs();
+                    }
+                }
+            }
+            Self {
+                a,
+                b,
+                c,
+                product,
+                abs_product,
+            }
+        }
+    }
+
+    // strides of an `m×n` matrix stored in column major or row major order
+    fn strides(m: usize, n: usize, colmajor: bool) -> (isize, isize) {
+        if colmajor {
+            (m as isize, 1)
+        } else {
+            (1, n as isize)
+        }
+    }
+
+    fn test_gemm_epilogue_ty<T: Float + 'static + core::fmt::Debug>(eps: f64) {
+        let mnks = [
+            (64, 64, 64),
+            (1, 64, 17),
+            (64, 1, 17),
+            (64, 64, 2),
+            (17, 13, 0),
+            (63, 31, 10),
+            (1024, 33, 1100),
+        ];
+
+        let activate = |x: f64, activation: Activation| match activation {
+            Activation::Identity => x,
+            Activation::Relu => x.max(0.0),
+            Activation::Gelu => {
+                let y = (2.0 / core::f64::consts::PI).sqrt() * (x + 0.044715 * x * x * x);
+                0.5 * x * (1.0 + y.tanh())
+            }
+            Activation::Silu => x / (1.0 + (-x).exp()),
+        };
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            let cast = |x: f64| T::from(x).unwrap();
+            let RealOperands {
+                a: a_vec,
+                b: b_vec,
+                c: c_vec,
+                product,
+                ..
+            } = RealOperands::<T>::random(m, n, k);
+            // strided, to check that the stride is respected
+            let row_bias: Vec<T> = random_vec(2 * m);
+            let col_bias: Vec<T> = random_vec(n);
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for read_dst in [false, true] {
+                    for colmajor in [true, false] {
+                        for bias in [
+                            Bias::None,
+                            Bias::PerRow(row_bias.as_ptr(), 2),
+                            Bias::PerCol(col_bias.as_ptr(), 1),
+                        ] {
+                            for activation in [
+                                Activation::Identity,
+                                Activation::Relu,
+                                Activation::Gelu,
+                                Activation::Silu,
+                            ] {
+                                let (dst_cs, dst_rs) = strides(m, n, colmajor);
+                                let alpha = cast(0.5);
+                                let beta = cast(2.5);
+
+                                let mut dst = c_vec.clone();
+                                unsafe {
+                                    gemm_with_epilogue(
+                                        m,
+                                        n,
+                                        k,
+                                        dst.as_mut_ptr(),
+                                        dst_cs,
+                                        dst_rs,
+                                        read_dst,
+                                        a_vec.as_ptr(),
+                                        m as isize,
+                                        1,
+                                        b_vec.as_ptr(),
+                                        k as isize,
+                                        1,
+                                        alpha,
+                                        beta,
+                                        false,
+                                        false,
+                                        false,
+                                        parallelism,
+                                        Epilogue {
+                                            bias,
+                                            activation,
+                                            ..Epilogue::identity()
+                                        },
+                                    );
+                                }
+
+                                for j in 0..n {
+                                    for i in 0..m {
+                                        let idx =
+                                            (i as isize * dst_rs + j as isize * dst_cs) as usize;
+                                        let bias = match bias {
+                                            Bias::None => 0.0,
+                                            Bias::PerRow(..) => row_bias[2 * i].to_f64().unwrap(),
+                                            Bias::PerCol(..) => col_bias[j].to_f64().unwrap(),
+                                            Bias::PerRowAndCol(..) => unreachable!(),
+                                        };
+                                        let acc = product[i + j * m];
+                                        let old = if read_dst {
+                                            alpha.to_f64().unwrap() * c_vec[idx].to_f64().unwrap()
+                                        } else {
+                                            0.0
+                                        };
+                                        let expected = activate(
+                                            old + beta.to_f64().unwrap() * acc + bias,
+                                            activation,
+                                        );
+                                        assert_approx_eq::assert_approx_eq!(
+                                            dst[idx].to_f64().unwrap(),
+                                            expected,
+                                            eps * (k as f64 + 4.0) * expected.abs().max(1.0)
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_epilogue() {
+        test_gemm_epilogue_ty::<f32>(f32::EPSILON as f64);
+        test_gemm_epilogue_ty::<f64>(f64::EPSILON);
+    }
+
+    fn test_gemm_scale_epilogue_ty<T: Float + 'static + core::fmt::Debug>(eps: f64) {
+        let mnks = [
+            (64, 64, 64),
+            (1, 64, 17),
+            (64, 1, 17),
+            (17, 13, 0),
+            (63, 31, 10),
+            (1024, 33, 1100),
+        ];
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            let cast = |x: f64| T::from(x).unwrap();
+            let RealOperands {
+                a: a_vec,
+                b: b_vec,
+                c: c_vec,
+                product,
+                ..
+            } = RealOperands::<T>::random(m, n, k);
+            // strided, to check that the stride is respected
+            let row_scale: Vec<T> = random_vec(2 * m);
+            let col_scale: Vec<T> = random_vec(n);
+            let col_bias: Vec<T> = random_vec(n);
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for read_dst in [false, true] {
+                    for colmajor in [true, false] {
+                        for row_stride in [0, 1, 2] {
+                            for (with_col_scale, with_bias) in
+                                [(false, false), (true, false), (true, true)]
+                            {
+                                let (dst_cs, dst_rs) = strides(m, n, colmajor);
+                                let alpha = cast(0.5);
+                                let beta = cast(2.5);
+                                let epilogue = Epilogue {
+                                    row_scale: if row_stride == 0 {
+                                        None
+                                    } else {
+                                        Some((row_scale.as_ptr(), row_stride as isize))
+                                    },
+                                    col_scale: with_col_scale.then_some((col_scale.as_ptr(), 1)),
+                                    bias: if with_bias {
+                                        Bias::PerCol(col_bias.as_ptr(), 1)
+                                    } else {
+                                        Bias::None
+                                    },
+                                    activation: Activation::Identity,
+                                    ..Epilogue::identity()
+                                };
+
+                                let mut dst = c_vec.clone();
+                                unsafe {
+                                    gemm_with_epilogue(
+                                        m,
+                                        n,
+                                        k,
+                                        dst.as_mut_ptr(),
+                                        dst_cs,
+                                        dst_rs,
+                                        read_dst,
+                                        a_vec.as_ptr(),
+                                        m as isize,
+                                        1,
+                                        b_vec.as_ptr(),
+                                        k as isize,
+                                        1,
+                                        alpha,
+                                        beta,
+                                        false,
+                                        false,
+                                        false,
+                                        parallelism,
+                                        epilogue,
+                                    );
+                                }
+
+                                for j in 0..n {
+                                    for i in 0..m {
+                                        let idx =
+                                            (i as isize * dst_rs + j as isize * dst_cs) as usize;
+                                        let mut scale = 1.0;
+                                        if row_stride != 0 {
+                                            scale *= row_scale[row_stride * i].to_f64().unwrap();
+                                        }
+                                        if with_col_scale {
+                                            scale *= col_scale[j].to_f64().unwrap();
+                                        }
+                                        let bias = if with_bias {
+                                            col_bias[j].to_f64().unwrap()
+                                        } else {
+                                            0.0
+                                        };
+                                        let old = if read_dst {
+                                            alpha.to_f64().unwrap() * c_vec[idx].to_f64().unwrap()
+                                        } else {
+                                            0.0
+                                        };
+                                        let expected = old
+                                            + beta.to_f64().unwrap() * scale * product[i + j * m]
+                                            + bias;
+                                        assert_approx_eq::assert_approx_eq!(
+                                            dst[idx].to_f64().unwrap(),
+                                            expected,
+                                            eps * (k as f64 + 4.0) * expected.abs().max(1.0)
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_scale_epilogue() {
+        test_gemm_scale_epilogue_ty::<f32>(f32::EPSILON as f64);
+        test_gemm_scale_epilogue_ty::<f64>(f64::EPSILON);
+    }
+
+    fn test_gemm_row_reductions_ty<T: Float + 'static + core::fmt::Debug>(eps: f64) {
+        let mnks = [
+            (64, 64, 64),
+            (4, 3000, 64),
+            (1, 64, 17),
+            (64, 1, 17),
+            (17, 13, 0),
+            (63, 31, 10),
+            (1024, 33, 1100),
+        ];
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            let cast = |x: f64| T::from(x).unwrap();
+            let a_vec: Vec<T> = random_vec(m * k);
+            let b_vec: Vec<T> = random_vec(k * n);
+            let c_vec: Vec<T> = random_vec(m * n);
+            let col_bias: Vec<T> = random_vec(n);
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for read_dst in [false, true] {
+                    // column major, row major, and column major with the columns reversed
+                    for layout in 0..3 {
+                        for activation in [Activation::Identity, Activation::Relu] {
+                            let (dst_cs, dst_rs, offset) = match layout {
+                                0 => (m as isize, 1, 0),
+                                1 => (1, n as isize, 0),
+                                _ => (-(m as isize), 1, ((n - 1) * m) as isize),
+                            };
+                            let alpha = cast(0.5);
+                            let beta = cast(2.5);
+
+                            let mut max = vec![cast(0.0); m];
+                            let mut argmax = vec![0usize; m];
+                            let mut sum = vec![cast(0.0); m];
+                            let mut sum_sq = vec![cast(0.0); m];
+                            let mut epilogue = Epilogue {
+                                bias: Bias::PerCol(col_bias.as_ptr(), 1),
+                                activation,
+                                reductions: Some(Reductions {
+                                    max: max.as_mut_ptr(),
+                                    argmax: argmax.as_mut_ptr(),
+                                    sum: sum.as_mut_ptr(),
+                                    sum_sq: sum_sq.as_mut_ptr(),
+                                }),
+                                ..Epilogue::identity()
+                            };
+
+                            let mut dst = c_vec.clone();
+                            unsafe {
+                                gemm_with_epilogue(
+                                    m,
+                                    n,
+                                    k,
+                                    dst.as_mut_ptr().offset(offset),
+                                    dst_cs,
+                                    dst_rs,
+                                    read_dst,
+                                    a_vec.as_ptr(),
+                                    m as isize,
+                                    1,
+                                    b_vec.as_ptr(),
+                                    k as isize,
+                                    1,
+                                    alpha,
+                                    beta,
+                                    false,
+                                    false,
+                                    false,
+                                    parallelism,
+                                    epilogue,
+                                );
+                            }
+
+                            let at = |i: usize, j: usize| {
+                                dst[(offset + i as isize * dst_rs + j as isize * dst_cs) as usize]
+                                    .to_f64()
+                                    .unwrap()
+                            };
+                            let tol = |x: f64| eps * (n as f64 + 4.0) * x.abs().max(1.0);
+                            for i in 0..m {
+                                let mut expected_argmax = 0;
+                                for j in 0..n {
+                                    if at(i, j) > at(i, expected_argmax) {
+                                        expected_argmax = j;
+                                    }
+                                }
+                                let expected_sum: f64 = (0..n).map(|j| at(i, j)).sum();
+                                let abs_sum: f64 = (0..n).map(|j| at(i, j).abs()).sum();
+                                let expected_sum_sq: f64 = (0..n).map(|j| at(i, j).powi(2)).sum();
+                                assert_eq!(argmax[i], expected_argmax);
+                                assert_eq!(max[i].to_f64().unwrap(), at(i, expected_argmax));
+                                assert_approx_eq::assert_approx_eq!(
+                                    sum[i].to_f64().unwrap(),
+                                    expected_sum,
+                                    tol(abs_sum)
+                                );
+                                assert_approx_eq::assert_approx_eq!(
+                                    sum_sq[i].to_f64().unwrap(),
+                                    expected_sum_sq,
+                                    tol(expected_sum_sq)
+                                );
+                            }
+
+                            if read_dst || layout != 0 {
+                                continue;
+                            }
+
+                            // without dst, the values may round differently, so argmax may pick
+                            // a different column among near-ties
+                            let mut max_no_dst = vec![cast(0.0); m];
+                            let mut argmax_no_dst = vec![0usize; m];
+                            let mut sum_no_dst = vec![cast(0.0); m];
+                            epilogue.reductions = Some(Reductions {
+                                max: max_no_dst.as_mut_ptr(),
+                                argmax: argmax_no_dst.as_mut_ptr(),
+                                sum: sum_no_dst.as_mut_ptr(),
+                                sum_sq: core::ptr::null_mut(),
+                            });
+                            unsafe {
+                                gemm_with_epilogue(
+                                    m,
+                                    n,
+                                    k,
+                                    core::ptr::null_mut(),
+                                    0,
+                                    0,
+                                    false,
+                                    a_vec.as_ptr(),
+                                    m as isize,
+                                    1,
+                                    b_vec.as_ptr(),
+                                    k as isize,
+                                    1,
+                                    alpha,
+                                    beta,
+                                    false,
+                                    false,
+                                    false,
+                                    parallelism,
+                                    epilogue,
+                                );
+                            }
+                            let abs_sum: Vec<f64> = (0..m)
+                                .map(|i| (0..n).map(|j| at(i, j).abs()).sum())
+                                .collect();
+                            for i in 0..m {
+                                let max = max[i].to_f64().unwrap();
+                                assert_approx_eq::assert_approx_eq!(
+                                    max_no_dst[i].to_f64().unwrap(),
+                                    max,
+                                    tol(max)
+                                );
+                                assert_approx_eq::assert_approx_eq!(
+                                    at(i, argmax_no_dst[i]),
+                                    max,
+                                    tol(max)
+                                );
+                                assert_approx_eq::assert_approx_eq!(
+                                    sum_no_dst[i].to_f64().unwrap(),
+                                    sum[i].to_f64().unwrap(),
+                                    tol(abs_sum[i])
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_row_reductions() {
+        test_gemm_row_reductions_ty::<f32>(f32::EPSILON as f64);
+        test_gemm_row_reductions_ty::<f64>(f64::EPSILON);
+    }
+
+    fn test_pairwise_distances_ty<T: Float + 'static + core::fmt::Debug>(eps: f64) {
+        let mnks = [
+            (64, 64, 64),
+            (1, 64, 17),
+            (64, 1, 17),
+            (17, 13, 0),
+            (63, 31, 10),
+            (300, 200, 600),
+            // several column blocks, each of which packs the row blocks again
+            (40, 1100, 70),
+        ];
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            let cast = |x: f64| T::from(x).unwrap();
+            // points stored by rows
+            let mut x: Vec<T> = random_vec(m * k);
+            let mut y: Vec<T> = random_vec(n * k);
+            // a duplicate point, whose distance should be zero, and a zero vector
+            if k > 0 {
+                y[..k].copy_from_slice(&x[..k]);
+                if m > 1 {
+                    x[k..2 * k].fill(cast(0.0));
+                }
+            }
+            let x_at = |i: usize, p: usize| x[i * k + p].to_f64().unwrap();
+            let y_at = |j: usize, p: usize| y[j * k + p].to_f64().unwrap();
+
+            for parallelism in [
+                Parallelism::None,
+                Parallelism::Rayon(0),
+                Parallelism::Rayon(3),
+            ] {
+                for colmajor in [true, false] {
+                    let (dst_cs, dst_rs) = strides(m, n, colmajor);
+                    for clamp_to_zero in [false, true] {
+                        let mut dst = vec![cast(0.0); m * n];
+                        unsafe {
+                            pairwise_sq_distances(
+                                m,
+                                n,
+                                k,
+                                dst.as_mut_ptr(),
+                                dst_cs,
+                                dst_rs,
+                                x.as_ptr(),
+                                1,
+                                k as isize,
+                                y.as_ptr(),
+                                k as isize,
+                                1,
+                                clamp_to_zero,
+                                parallelism,
+                            );
+                        }
+                        for j in 0..n {
+                            for i in 0..m {
+                                let got = dst[(i as isize * dst_rs + j as isize * dst_cs) as usize]
+                                    .to_f64()
+                                    .unwrap();
+                                let expected: f64 =
+                                    (0..k).map(|p| (x_at(i, p) - y_at(j, p)).powi(2)).sum();
+                                let norms: f64 = (0..k)
+                                    .map(|p| x_at(i, p).powi(2) + y_at(j, p).powi(2))
+                                    .sum();
+                                assert_approx_eq::assert_approx_eq!(
+                                    got,
+                                    expected,
+                                    eps * (k as f64 + 4.0) * norms.max(1.0)
+                                );
+                                if clamp_to_zero {
+                                    assert!(got >= 0.0);
+                                }
+                            }
+                        }
+                    }
+
+                    let mut dst = vec![cast(0.0); m * n];
+                    unsafe {
+                        pairwise_cosine_similarities(
+                            m,
+                            n,
+                            k,
+                            dst.as_mut_ptr(),
+                            dst_cs,
+                            dst_rs,
+                            x.as_ptr(),
+                            1,
+                            k as isize,
+                            y.as_ptr(),
+                            k as isize,
+                            1,
+                            parallelism,
+                        );
+                    }
+                    for j in 0..n {
+                        for i in 0..m {
+                            let got = dst[(i as isize * dst_rs + j as isize * dst_cs) as usize]
+                                .to_f64()
+                                .unwrap();
+                            let dot: f64 = (0..k).map(|p| x_at(i, p) * y_at(j, p)).sum();
+                            let x_norm = (0..k).map(|p| x_at(i, p).powi(2)).sum::<f64>().sqrt();
+                            let y_norm = (0..k).map(|p| y_at(j, p).powi(2)).sum::<f64>().sqrt();
+                            let expected = if x_norm == 0.0 || y_norm == 0.0 {
+                                0.0
+                            } else {
+                                dot / (x_norm * y_norm)
+                            };
+                            assert_approx_eq::assert_approx_eq!(
+                                got,
+                                expected,
+                                eps * (k as f64 + 4.0)
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pairwise_distances() {
+        test_pairwise_distances_ty::<f32>(f32::EPSILON as f64);
+        test_pairwise_distances_ty::<f64>(f64::EPSILON);
+    }
+
+    fn test_gemm_multi_rhs_ty<T: Float + 'static + core::fmt::Debug>(eps: f64) {
+        let mnks = [
+            (64, 64, 64),
+            (1, 64, 17),
+            (17, 13, 0),
+            (63, 31, 10),
+            (300, 700, 600),
+        ];
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            let cast = |x: f64| T::from(x).unwrap();
+            let a_vec: Vec<T> = random_vec(m * k);
+            // (n, rhs column major, dst column major, read_dst, alpha, beta, with epilogue)
+            let configs = [
+                (n, true, true, true, 0.5, 2.5, false),
+                (n / 2 + 3, false, false, false, 0.0, -1.0, true),
+                (1, true, true, true, 1.0, 1.0, false),
+            ];
+            let b_vecs: Vec<Vec<T>> = configs.iter().map(|&(n, ..)| random_vec(k * n)).collect();
+            let c_vecs: Vec<Vec<T>> = configs.iter().map(|&(n, ..)| random_vec(m * n)).collect();
+            let col_bias: Vec<T> = random_vec(n / 2 + 3);
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                let mut dsts = c_vecs.clone();
+                let mut outputs = vec![];
+                for (r, &(n, rhs_colmajor, dst_colmajor, read_dst, alpha, beta, with_epilogue)) in
+                    configs.iter().enumerate()
+                {
+                    let (rhs_cs, rhs_rs) = strides(k, n, rhs_colmajor);
+                    let (dst_cs, dst_rs) = strides(m, n, dst_colmajor);
+                    outputs.push(GemmOutput {
+                        n,
+                        dst: dsts[r].as_mut_ptr(),
+                        dst_cs,
+                        dst_rs,
+                        read_dst,
+                        rhs: b_vecs[r].as_ptr(),
+                        rhs_cs,
+                        rhs_rs,
+                        alpha: cast(alpha),
+                        beta: cast(beta),
+                        epilogue: if with_epilogue {
+                            Epilogue {
+                                bias: Bias::PerCol(col_bias.as_ptr(), 1),
+                                activation: Activation::Relu,
+                                ..Epilogue::identity()
+                            }
+                        } else {
+                            Epilogue::identity()
+                        },
+                        mask: None,
+                        dst_blocks: None,
+                    });
+                }
+                unsafe {
+                    gemm_multi_rhs(m, k, a_vec.as_ptr(), m as isize, 1, &outputs, parallelism);
+                }
+
+                for (r, output) in outputs.iter().enumerate() {
+                    let (n, _, _, read_dst, alpha, beta, with_epilogue) = configs[r];
+                    for j in 0..n {
+                        for i in 0..m {
+                            let mut sum = 0.0;
+                            let mut abs_sum = 0.0;
+                            for depth in 0..k {
+                                let x = a_vec[i + depth * m].to_f64().unwrap()
+                                    * b_vecs[r][(depth as isize * output.rhs_rs
+                                        + j as isize * output.rhs_cs)
+                                        as usize]
+                                        .to_f64()
+                                        .unwrap();
+                                sum += x;
+                                abs_sum += x.abs();
+                            }
+                            let idx =
+                                (i as isize * output.dst_rs + j as isize * output.dst_cs) as usize;
+                            let mut expected = beta * sum;
+                            if read_dst {
+                                expected += alpha * c_vecs[r][idx].to_f64().unwrap();
+                            }
+                            if with_epilogue {
+                                expected = (expected + col_bias[j].to_f64().unwrap()).max(0.0);
+                            }
+                            assert_approx_eq::assert_approx_eq!(
+                                dsts[r][idx].to_f64().unwrap(),
+                                expected,
+                                eps * (k as f64 + 4.0) * (abs_sum + 4.0)
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_multi_rhs() {
+        test_gemm_multi_rhs_ty::<f32>(f32::EPSILON as f64);
+        test_gemm_multi_rhs_ty::<f64>(f64::EPSILON);
+    }
+
+    fn test_gemm_sum_ty<T: Float + 'static + core::fmt::Debug>(eps: f64) {
+        let mnks = [
+            (64, 64, vec![17, 0, 40]),
+            (1, 33, vec![5, 3]),
+            (17, 13, vec![0]),
+            (63, 31, vec![10, 10, 10]),
+            (300, 200, vec![600, 7, 300]),
+        ];
+
+        for (m, n, ks) in mnks {
+            dbg!(m, n, &ks);
+            let cast = |x: f64| T::from(x).unwrap();
+            let operands: Vec<RealOperands<T>> =
+                ks.iter().map(|&k| RealOperands::random(m, n, k)).collect();
+            // column major lhs, alternately column major and row major rhs
+            let b_vecs: Vec<Vec<T>> = ks
+                .iter()
+                .zip(&operands)
+                .enumerate()
+                .map(|(t, (&k, operands))| {
+                    if t % 2 == 0 {
+                        operands.b.clone()
+                    } else {
+                        (0..(k * n))
+                            .map(|idx| operands.b[idx / n + (idx % n) * k])
+                            .collect()
+                    }
+                })
+                .collect();
+            let c_vec: Vec<T> = random_vec(m * n);
+            let col_bias: Vec<T> = random_vec(n);
+            let terms: Vec<GemmTerm<T>> = ks
+                .iter()
+                .enumerate()
+                .map(|(t, &k)| {
+                    let (rhs_cs, rhs_rs) = strides(k, n, t % 2 == 0);
+                    GemmTerm {
+                        k,
+                        lhs: operands[t].a.as_ptr(),
+                        lhs_cs: m as isize,
+                        lhs_rs: 1,
+                        rhs: b_vecs[t].as_ptr(),
+                        rhs_cs,
+                        rhs_rs,
+                    }
+                })
+                .collect();
+
+            let mut product = vec![0.0; m * n];
+            let mut abs_product = vec![0.0; m * n];
+            for operands in &operands {
+                for idx in 0..m * n {
+                    product[idx] += operands.product[idx];
+                    abs_product[idx] += operands.abs_product[idx];
+                }
+            }
+            let k: usize = ks.iter().sum();
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for read_dst in [false, true] {
+                    for with_epilogue in [false, true] {
+                        let alpha = 0.5;
+                        let beta = -1.5;
+                        let mut dst = c_vec.clone();
+                        let epilogue = if with_epilogue {
+                            Epilogue {
+                                bias: Bias::PerCol(col_bias.as_ptr(), 1),
+                                activation: Activation::Relu,
+                                ..Epilogue::identity()
+                            }
+                        } else {
+                            Epilogue::identity()
+                        };
+                        unsafe {
+                            gemm_sum(
+                                m,
+                                n,
+                                dst.as_mut_ptr(),
+                                m as isize,
+                                1,
+                                read_dst,
+                                &terms,
+                                cast(alpha),
+                                cast(beta),
+                                parallelism,
+                                epilogue,
+                            );
+                        }
+                        for j in 0..n {
+                            for i in 0..m {
+                                let mut expected = beta * product[i + j * m];
+                                if read_dst {
+                                    expected += alpha * c_vec[i + j * m].to_f64().unwrap();
+                                }
+                                if with_epilogue {
+                                    expected = (expected + col_bias[j].to_f64().unwrap()).max(0.0);
+                                }
+                                assert_approx_eq::assert_approx_eq!(
+                                    dst[i + j * m].to_f64().unwrap(),
+                                    expected,
+                                    eps * (k as f64 + 4.0) * (abs_product[i + j * m] + 4.0)
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_sum() {
+        test_gemm_sum_ty::<f32>(f32::EPSILON as f64);
+        test_gemm_sum_ty::<f64>(f64::EPSILON);
+    }
+
+    fn test_gemm_triangular_ty<T: Float + 'static + core::fmt::Debug>(eps: f64) {
+        let mnks = [
+            (64, 64, 64),
+            (1, 64, 17),
+            (17, 13, 0),
+            (63, 31, 10),
+            (300, 200, 600),
+        ];
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            let cast = |x: f64| T::from(x).unwrap();
+            let RealOperands {
+                a: a_vec,
+                b: b_vec,
+                c: c_vec,
+                product,
+                abs_product,
+            } = RealOperands::<T>::random(m, n, k);
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for colmajor in [true, false] {
+                    for triangle in [Triangle::Lower, Triangle::Upper] {
+                        for diag_offset in [-5, 0, 7] {
+                            for fill in [Some(T::infinity()), None] {
+                                let (dst_cs, dst_rs) = strides(m, n, colmajor);
+                                let alpha = 0.5;
+                                let beta = 2.5;
+                                // the fill value must not be part of the reductions
+                                let mask = TriangularMask {
+                                    triangle,
+                                    diag_offset,
+                                    fill,
+                                };
+                                let mut dst = c_vec.clone();
+                                let mut max = vec![cast(0.0); m];
+                                let mut argmax = vec![0usize; m];
+                                unsafe {
+                                    gemm_triangular(
+                                        m,
+                                        n,
+                                        k,
+                                        dst.as_mut_ptr(),
+                                        dst_cs,
+                                        dst_rs,
+                                        true,
+                                        a_vec.as_ptr(),
+                                        m as isize,
+                                        1,
+                                        b_vec.as_ptr(),
+                                        k as isize,
+                                        1,
+                                        cast(alpha),
+                                        cast(beta),
+                                        parallelism,
+                                        mask,
+                                        Epilogue {
+                                            reductions: Some(Reductions {
+                                                max: max.as_mut_ptr(),
+                                                argmax: argmax.as_mut_ptr(),
+                                                sum: core::ptr::null_mut(),
+                                                sum_sq: core::ptr::null_mut(),
+                                            }),
+                                            ..Epilogue::identity()
+                                        },
+                                    );
+                                }
+
+                                for i in 0..m {
+                                    let mut expected_argmax = usize::MAX;
+                                    for j in 0..n {
+                                        let idx =
+                                            (i as isize * dst_rs + j as isize * dst_cs) as usize;
+                                        let got = dst[idx];
+                                        if mask.keeps(i, j) {
+                                            assert_approx_eq::assert_approx_eq!(
+                                                got.to_f64().unwrap(),
+                                                alpha * c_vec[idx].to_f64().unwrap()
+                                                    + beta * product[i + j * m],
+                                                eps * (k as f64 + 4.0)
+                                                    * (abs_product[i + j * m] + 4.0)
+                                            );
+                                            if expected_argmax == usize::MAX
+                                                || got
+                                                    > dst[(i as isize * dst_rs
+                                                        + expected_argmax as isize * dst_cs)
+                                                        as usize]
+                                            {
+                                                expected_argmax = j;
+                                            }
+                                        } else {
+                                            assert!(got == fill.unwrap_or(c_vec[idx]));
+                                        }
+                                    }
+                                    assert!(argmax[i] == expected_argmax);
+                                    if expected_argmax != usize::MAX {
+                                        assert!(
+                                            max[i]
+                                                == dst[(i as isize * dst_rs
+                                                    + expected_argmax as isize * dst_cs)
+                                                    as usize]
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_triangular() {
+        test_gemm_triangular_ty::<f32>(f32::EPSILON as f64);
+        test_gemm_triangular_ty::<f64>(f64::EPSILON);
+    }
+
+    fn test_gemm_block_sparse_ty<T: Float + 'static + core::fmt::Debug>(eps: f64) {
+        let mnks = [
+            (64, 64, 64, (8, 16)),
+            (1, 64, 17, (1, 4)),
+            (17, 13, 0, (4, 4)),
+            (63, 31, 10, (5, 7)),
+            (300, 200, 600, (32, 64)),
+        ];
+
+        for (m, n, k, (block_rows, block_cols)) in mnks {
+            dbg!(m, n, k, block_rows, block_cols);
+            let cast = |x: f64| T::from(x).unwrap();
+            let random_blocks = |nrows: usize, ncols: usize| {
+                let mut blocks = vec![];
+                for i in 0..nrows.div_ceil(block_rows) {
+                    for j in 0..ncols.div_ceil(block_cols) {
+                        if rand::random::<f64>() < 0.4 {
+                            blocks.push((i, j));
+                        }
+                    }
+                }
+                BlockMask::bits_from_blocks(nrows, ncols, block_rows, block_cols, &blocks)
+            };
+            let (lhs_bits, lhs_stride) = random_blocks(m, k);
+            let (dst_bits, dst_stride) = random_blocks(m, n);
+            let lhs_blocks = BlockMask {
+                block_rows,
+                block_cols,
+                bits: lhs_bits.as_ptr(),
+                row_stride: lhs_stride,
+            };
+            let dst_blocks = BlockMask {
+                block_rows,
+                block_cols,
+                bits: dst_bits.as_ptr(),
+                row_stride: dst_stride,
+            };
+
+            // the inactive blocks of lhs are zero
+            let mut a_vec: Vec<T> = random_vec(m * k);
+            for (idx, a) in a_vec.iter_mut().enumerate() {
+                if !unsafe { lhs_blocks.is_active((idx % m) / block_rows, (idx / m) / block_cols) }
+                {
+                    *a = cast(0.0);
+                }
+            }
+            let RealOperands {
+                a: a_vec,
+                b: b_vec,
+                c: c_vec,
+                product,
+                abs_product,
+            } = RealOperands::new(m, n, k, a_vec, random_vec(k * n), random_vec(m * n));
+            let col_bias: Vec<T> = random_vec(n);
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for (with_lhs_blocks, with_dst_blocks) in
+                    [(true, false), (false, true), (true, true)]
+                {
+                    for read_dst in [false, true] {
+                        for with_epilogue in [false, true] {
+                            let alpha = 0.5;
+                            let beta = 2.5;
+                            let mut dst = c_vec.clone();
+                            unsafe {
+                                gemm_block_sparse(
+                                    m,
+                                    n,
+                                    k,
+                                    dst.as_mut_ptr(),
+                                    m as isize,
+                                    1,
+                                    read_dst,
+                                    a_vec.as_ptr(),
+                                    m as isize,
+                                    1,
+                                    b_vec.as_ptr(),
+                                    k as isize,
+                                    1,
+                                    cast(alpha),
+                                    cast(beta),
+                                    parallelism,
+                                    with_lhs_blocks.then_some(lhs_blocks),
+                                    with_dst_blocks.then_some(dst_blocks),
+                                    if with_epilogue {
+                                        Epilogue {
+                                            bias: Bias::PerCol(col_bias.as_ptr(), 1),
+                                            activation: Activation::Relu,
+                                            ..Epilogue::identity()
+                                        }
+                                    } else {
+                                        Epilogue::identity()
+                                    },
+                                );
+                            }
+
+                            for j in 0..n {
+                                for i in 0..m {
+                                    let got = dst[i + j * m].to_f64().unwrap();
+                                    if with_dst_blocks
+                                        && unsafe {
+                                            !dst_blocks.is_active(i / block_rows, j / block_cols)
+                                        }
+                                    {
+                                        assert!(got == 0.0);
+                                        continue;
+                                    }
+                                    let mut expected = beta * product[i + j * m];
+                                    if read_dst {
+                                        expected += alpha * c_vec[i + j * m].to_f64().unwrap();
+                                    }
+                                    if with_epilogue {
+                                        expected =
+                                            (expected + col_bias[j].to_f64().unwrap()).max(0.0);
+                                    }
+                                    assert_approx_eq::assert_approx_eq!(
+                                        got,
+                                        expected,
+                                        eps * (k as f64 + 4.0) * (abs_product[i + j * m] + 4.0)
+                                    );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_block_sparse() {
+        test_gemm_block_sparse_ty::<f32>(f32::EPSILON as f64);
+        test_gemm_block_sparse_ty::<f64>(f64::EPSILON);
+    }
+
+    // scalars of the tests that are checked against a reference computed on `(re, im)` pairs of
+    // `f64`, where the real types drop the imaginary part
+    trait RefScalar: Copy {
+        fn from_pair(re: f64, im: f64) -> Self;
+        fn to_pair(self) -> (f64, f64);
+    }
+
+    impl RefScalar for f16 {
+        fn from_pair(re: f64, _: f64) -> Self {
+            f16::from_f64(re)
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self.to_f64(), 0.0)
+        }
+    }
+    impl RefScalar for f32 {
+        fn from_pair(re: f64, _: f64) -> Self {
+            re as f32
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self as f64, 0.0)
+        }
+    }
+    impl RefScalar for f64 {
+        fn from_pair(re: f64, _: f64) -> Self {
+            re
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self, 0.0)
+        }
+    }
+    impl RefScalar for c32 {
+        fn from_pair(re: f64, im: f64) -> Self {
+            c32::new(re as f32, im as f32)
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self.re as f64, self.im as f64)
+        }
+    }
+    impl RefScalar for c64 {
+        fn from_pair(re: f64, im: f64) -> Self {
+            c64::new(re, im)
+        }
+        fn to_pair(self) -> (f64, f64) {
+            (self.re, self.im)
+        }
+    }
+
+    // `x` rounded to the precision of `T`
+    fn ref_cast<T: RefScalar>(x: (f64, f64)) -> (f64, f64) {
+        T::from_pair(x.0, x.1).to_pair()
+    }
+
+    // random value with parts in `[-1, 1)` that is representable in `T`
+    fn ref_random<T: RefScalar>() -> (f64, f64) {
+        ref_cast::<T>((
+            rand::random::<f64>() * 2.0 - 1.0,
+            rand::random::<f64>() * 2.0 - 1.0,
+        ))
+    }
+
+    fn ref_mul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+        (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+    }
+
+    fn from_pairs<T: RefScalar>(x: &[(f64, f64)]) -> Vec<T> {
+        x.iter().map(|&(re, im)| T::from_pair(re, im)).collect()
+    }
+
+    fn test_sparse_gemm_ty<T: SparseScalar + RefScalar>(eps: f64) {
+        let mut mnks = vec![];
+        mnks.push((64, 64, 64, 0.1));
+        mnks.push((1, 300, 17, 0.5));
+        mnks.push((17, 13, 0, 0.5));
+        mnks.push((63, 1, 10, 0.3));
+        mnks.push((300, 200, 600, 0.02));
+
+        for (m, n, k, density) in mnks {
+            dbg!(m, n, k, density);
+
+            // dense copy of the sparse lhs, with some empty rows and columns
+            let mut a_dense = vec![(0.0, 0.0); m * k];
+            for x in &mut a_dense {
+                if rand::random::<f64>() < density {
+                    *x = ref_random::<T>();
+                }
+            }
+            let mut row_ptr = vec![0];
+            let mut col_indices = vec![];
+            let mut csr_values = vec![];
+            for i in 0..m {
+                for j in 0..k {
+                    if a_dense[i * k + j] != (0.0, 0.0) {
+                        col_indices.push(j);
+                        csr_values.push(T::from_pair(a_dense[i * k + j].0, a_dense[i * k + j].1));
+                    }
+                }
+                row_ptr.push(col_indices.len());
+            }
+            let mut col_ptr = vec![0];
+            let mut row_indices = vec![];
+            let mut csc_values = vec![];
+            for j in 0..k {
+                for i in 0..m {
+                    if a_dense[i * k + j] != (0.0, 0.0) {
+                        row_indices.push(i);
+                        csc_values.push(T::from_pair(a_dense[i * k + j].0, a_dense[i * k + j].1));
+                    }
+                }
+                col_ptr.push(row_indices.len());
+            }
+            let csr = CsrMatrix {
+                nrows: m,
+                ncols: k,
+                row_ptr: &row_ptr,
+                col_indices: &col_indices,
+                values: &csr_values,
+            };
+            let csc = CscMatrix {
+                nrows: m,
+                ncols: k,
+                col_ptr: &col_ptr,
+                row_indices: &row_indices,
+                values: &csc_values,
+            };
+
+            let b_dense: Vec<(f64, f64)> = (0..(k * n)).map(|_| ref_random::<T>()).collect();
+            let c_dense: Vec<(f64, f64)> = (0..(m * n)).map(|_| ref_random::<T>()).collect();
+            let alpha = ref_cast::<T>((0.5, -1.5));
+            let beta = ref_cast::<T>((2.5, 0.25));
+
+            // row major `rhs` and `dst`, then column major
+            for row_major in [true, false] {
+                let (rhs_cs, rhs_rs) = if row_major { (1, n) } else { (k, 1) };
+                let (dst_cs, dst_rs) = if row_major { (1, n) } else { (m, 1) };
+                let mut b_vec = vec![T::from_pair(0.0, 0.0); k * n];
+                for p in 0..k {
+                    for j in 0..n {
+                        let b = b_dense[p * n + j];
+                        b_vec[p * rhs_rs + j * rhs_cs] = T::from_pair(b.0, b.1);
+                    }
+                }
+                let mut c_vec = vec![T::from_pair(0.0, 0.0); m * n];
+                for i in 0..m {
+                    for j in 0..n {
+                        let c = c_dense[i * n + j];
+                        c_vec[i * dst_rs + j * dst_cs] = T::from_pair(c.0, c.1);
+                    }
+                }
+
+                for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                    for read_dst in [false, true] {
+                        for use_csc in [false, true] {
+                            let mut dst = c_vec.clone();
+                            unsafe {
+                                if use_csc {
+                                    csc_gemm(
+                                        n,
+                                        dst.as_mut_ptr(),
+                                        dst_cs as isize,
+                                        dst_rs as isize,
+                                        read_dst,
+                                        csc,
+                                        b_vec.as_ptr(),
+                                        rhs_cs as isize,
+                                        rhs_rs as isize,
+                                        T::from_pair(alpha.0, alpha.1),
+                                        T::from_pair(beta.0, beta.1),
+                                        parallelism,
+                                    );
+                                } else {
+                                    csr_gemm(
+                                        n,
+                                        dst.as_mut_ptr(),
+                                        dst_cs as isize,
+                                        dst_rs as isize,
+                                        read_dst,
+                                        csr,
+                                        b_vec.as_ptr(),
+                                        rhs_cs as isize,
+                                        rhs_rs as isize,
+                                        T::from_pair(alpha.0, alpha.1),
+                                        T::from_pair(beta.0, beta.1),
+                                        parallelism,
+                                    );
+                                }
+                            }
+
+                            for i in 0..m {
+                                for j in 0..n {
+                                    let mut acc = (0.0, 0.0);
+                                    let mut abs_acc = 0.0;
+                                    for p in 0..k {
+                                        let x = ref_mul(a_dense[i * k + p], b_dense[p * n + j]);
+                                        acc = (acc.0 + x.0, acc.1 + x.1);
+                                        abs_acc += x.0.abs() + x.1.abs();
+                                    }
+                                    let mut expected = ref_mul(beta, acc);
+                                    if read_dst {
+                                        let c = ref_mul(alpha, c_dense[i * n + j]);
+                                        expected = (expected.0 + c.0, expected.1 + c.1);
+                                    }
+                                    let (re, im) = dst[i * dst_rs + j * dst_cs].to_pair();
+                                    let tol = eps * (1.0 + 4.0 * abs_acc);
+                                    assert!((re - expected.0).abs() <= tol);
+                                    assert!((im - expected.1).abs() <= tol);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_gemm_f32() {
+        test_sparse_gemm_ty::<f32>(1e-5);
+    }
+
+    #[test]
+    fn test_sparse_gemm_f64() {
+        test_sparse_gemm_ty::<f64>(1e-10);
+    }
+
+    #[test]
+    fn test_sparse_gemm_c32() {
+        test_sparse_gemm_ty::<c32>(1e-5);
+    }
+
+    #[test]
+    fn test_sparse_gemm_c64() {
+        test_sparse_gemm_ty::<c64>(1e-10);
+    }
+
+    fn test_band_gemm_ty<T: SparseScalar + RefScalar>(eps: f64) {
+        let mut mnks = vec![];
+        mnks.push((64, 64, 64, 1, 1));
+        mnks.push((1, 300, 17, 0, 3));
+        mnks.push((17, 13, 0, 2, 2));
+        mnks.push((63, 1, 10, 5, 0));
+        mnks.push((40, 30, 20, 50, 50));
+        mnks.push((300, 200, 250, 7, 12));
+
+        for (m, n, k, kl, ku) in mnks {
+            dbg!(m, n, k, kl, ku);
+
+            // the entries of the band storage outside of the matrix are never read
+            let ld = kl + ku + 3;
+            let mut a_dense = vec![(0.0, 0.0); m * k];
+            let mut band_values = vec![T::from_pair(f64::NAN, f64::NAN); ld * k];
+            for j in 0..k {
+                for i in j.saturating_sub(ku)..m.min(j + kl + 1) {
+                    let x = ref_random::<T>();
+                    a_dense[i * k + j] = x;
+                    band_values[ku + i - j + j * ld] = T::from_pair(x.0, x.1);
+                }
+            }
+            let band = BandMatrix {
+                nrows: m,
+                ncols: k,
+                kl,
+                ku,
+                ld,
+                values: &band_values,
+            };
+
+            let b_dense: Vec<(f64, f64)> = (0..(k * n)).map(|_| ref_random::<T>()).collect();
+            let c_dense: Vec<(f64, f64)> = (0..(m * n)).map(|_| ref_random::<T>()).collect();
+            let alpha = ref_cast::<T>((0.5, -1.5));
+            let beta = ref_cast::<T>((2.5, 0.25));
+
+            // row major `rhs` and `dst`, then column major
+            for row_major in [true, false] {
+                let (rhs_cs, rhs_rs) = if row_major { (1, n) } else { (k, 1) };
+                let (dst_cs, dst_rs) = if row_major { (1, n) } else { (m, 1) };
+                let mut b_vec = vec![T::from_pair(0.0, 0.0); k * n];
+                for p in 0..k {
+                    for j in 0..n {
+                        let b = b_dense[p * n + j];
+                        b_vec[p * rhs_rs + j * rhs_cs] = T::from_pair(b.0, b.1);
+                    }
+                }
+                let mut c_vec = vec![T::from_pair(0.0, 0.0); m * n];
+                for i in 0..m {
+                    for j in 0..n {
+                        let c = c_dense[i * n + j];
+                        c_vec[i * dst_rs + j * dst_cs] = T::from_pair(c.0, c.1);
+                    }
+                }
+
+                for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                    for read_dst in [false, true] {
+                        let mut dst = c_vec.clone();
+                        unsafe {
+                            band_gemm(
+                                n,
+                                dst.as_mut_ptr(),
+                                dst_cs as isize,
+                                dst_rs as isize,
+                                read_dst,
+                                band,
+                                b_vec.as_ptr(),
+                                rhs_cs as isize,
+                                rhs_rs as isize,
+                                T::from_pair(alpha.0, alpha.1),
+                                T::from_pair(beta.0, beta.1),
+                                parallelism,
+                            );
+                        }
+
+                        for i in 0..m {
+                            for j in 0..n {
+                                let mut acc = (0.0, 0.0);
+                                let mut abs_acc = 0.0;
+                                for p in 0..k {
+                                    let x = ref_mul(a_dense[i * k + p], b_dense[p * n + j]);
+                                    acc = (acc.0 + x.0, acc.1 + x.1);
+                                    abs_acc += x.0.abs() + x.1.abs();
+                                }
+                                let mut expected = ref_mul(beta, acc);
+                                if read_dst {
+                                    let c = ref_mul(alpha, c_dense[i * n + j]);
+                                    expected = (expected.0 + c.0, expected.1 + c.1);
+                                }
+                                let (re, im) = dst[i * dst_rs + j * dst_cs].to_pair();
+                                let tol = eps * (1.0 + 4.0 * abs_acc);
+                                assert!((re - expected.0).abs() <= tol);
+                                assert!((im - expected.1).abs() <= tol);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_band_gemm() {
+        test_band_gemm_ty::<f32>(1e-5);
+        test_band_gemm_ty::<f64>(1e-10);
+        test_band_gemm_ty::<c32>(1e-5);
+        test_band_gemm_ty::<c64>(1e-10);
+    }
+
+    fn test_gemm_sparse24_ty<T: SparseScalar + RefScalar>(eps: f64) {
+        let mut mnks = vec![];
+        mnks.push((64, 64, 64));
+        mnks.push((1, 13, 17));
+        mnks.push((17, 13, 0));
+        mnks.push((63, 1, 3));
+        mnks.push((300, 200, 1001));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+
+            // column major weights, with at most two random nonzero values in each group of four
+            let mut b_dense = vec![(0.0, 0.0); k * n];
+            for j in 0..n {
+                for p in 0..k {
+                    if rand::random::<f64>() < 0.5 {
+                        b_dense[p + j * k] = ref_random::<T>();
+                    }
+                }
+                for g in 0..k.div_ceil(4) {
+                    let group = &mut b_dense[4 * g + j * k..(4 * g + 4).min(k) + j * k];
+                    let mut n_nonzero = 0;
+                    for x in group {
+                        if *x != (0.0, 0.0) {
+                            n_nonzero += 1;
+                            if n_nonzero > 2 {
+                                *x = (0.0, 0.0);
+                            }
+                        }
+                    }
+                }
+            }
+            let b_vec: Vec<T> = from_pairs(&b_dense);
+            let rhs = unsafe { Sparse24Matrix::from_dense(k, n, b_vec.as_ptr(), k as isize, 1) };
+
+            let a_dense: Vec<(f64, f64)> = (0..(m * k)).map(|_| ref_random::<T>()).collect();
+            let c_dense: Vec<(f64, f64)> = (0..(m * n)).map(|_| ref_random::<T>()).collect();
+            let a_vec: Vec<T> = from_pairs(&a_dense);
+            let c_vec: Vec<T> = from_pairs(&c_dense);
+            let alpha = ref_cast::<T>((0.5, -1.5));
+            let beta = ref_cast::<T>((2.5, 0.25));
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for read_dst in [false, true] {
+                    let mut dst = c_vec.clone();
+                    unsafe {
+                        gemm_sparse24(
+                            m,
+                            dst.as_mut_ptr(),
+                            m as isize,
+                            1,
+                            read_dst,
+                            a_vec.as_ptr(),
+                            m as isize,
+                            1,
+                            &rhs,
+                            T::from_pair(alpha.0, alpha.1),
+                            T::from_pair(beta.0, beta.1),
+                            parallelism,
+                        );
+                    }
+
+                    for j in 0..n {
+                        for i in 0..m {
+                            let mut acc = (0.0, 0.0);
+                            let mut abs_acc = 0.0;
+                            for p in 0..k {
+                                let x = ref_mul(a_dense[i + p * m], b_dense[p + j * k]);
+                                acc = (acc.0 + x.0, acc.1 + x.1);
+                                abs_acc += x.0.abs() + x.1.abs();
+                            }
+                            let mut expected = ref_mul(beta, acc);
+                            if read_dst {
+                                let c = ref_mul(alpha, c_dense[i + j * m]);
+                                expected = (expected.0 + c.0, expected.1 + c.1);
+                            }
+                            let (re, im) = dst[i + j * m].to_pair();
+                            let tol = eps * (1.0 + 4.0 * abs_acc);
+                            assert!((re - expected.0).abs() <= tol);
+                            assert!((im - expected.1).abs() <= tol);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_sparse24_f32() {
+        test_gemm_sparse24_ty::<f32>(1e-5);
+    }
+
+    #[test]
+    fn test_gemm_sparse24_f64() {
+        test_gemm_sparse24_ty::<f64>(1e-10);
+    }
+
+    #[test]
+    fn test_gemm_sparse24_c32() {
+        test_gemm_sparse24_ty::<c32>(1e-5);
+    }
+
+    #[test]
+    fn test_gemm_sparse24_c64() {
+        test_gemm_sparse24_ty::<c64>(1e-10);
+    }
+
+    fn test_syrk_ty<T: 'static + RefScalar + PartialEq + core::fmt::Debug>(
+        eps: f64,
+        hermitian: bool,
+    ) {
+        let mut nks = vec![];
+        nks.push((64, 64));
+        nks.push((1, 17));
+        nks.push((17, 0));
+        nks.push((63, 3));
+        nks.push((300, 1100));
+
+        for (n, k) in nks {
+            dbg!(n, k);
+            let rhs_conj = |x: (f64, f64)| if hermitian { (x.0, -x.1) } else { x };
+
+            let a_dense: Vec<(f64, f64)> = (0..(n * k)).map(|_| ref_random::<T>()).collect();
+            let c_dense: Vec<(f64, f64)> = (0..(n * n)).map(|_| ref_random::<T>()).collect();
+            let a_vec: Vec<T> = from_pairs(&a_dense);
+            let c_vec: Vec<T> = from_pairs(&c_dense);
+            let alpha = ref_cast::<T>(if hermitian { (0.5, 0.0) } else { (0.5, -1.5) });
+            let beta = ref_cast::<T>(if hermitian { (2.5, 0.0) } else { (2.5, 0.25) });
+
+            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                for triangle in [Triangle::Lower, Triangle::Upper] {
+                    for read_dst in [false, true] {
+                        let mut dst = c_vec.clone();
+                        unsafe {
+                            (if hermitian { herk } else { syrk })(
+                                n,
+                                k,
+                                dst.as_mut_ptr(),
+                                n as isize,
+                                1,
+                                read_dst,
+                                a_vec.as_ptr(),
+                                n as isize,
+                                1,
+                                T::from_pair(alpha.0, alpha.1),
+                                T::from_pair(beta.0, beta.1),
+                                triangle,
+                                parallelism,
+                            );
+                        }
+
+                        for j in 0..n {
+                            for i in 0..n {
+                                let kept = match triangle {
+                                    Triangle::Lower => j <= i,
+                                    Triangle::Upper => j >= i,
+                                };
+                                if !kept {
+                                    assert!(dst[i + j * n] == c_vec[i + j * n]);
+                                    continue;
+                                }
+
+                                let mut acc = (0.0, 0.0);
+                                let mut abs_acc = 0.0;
+                                for p in 0..k {
+                                    let x =
+                                        ref_mul(a_dense[i + p * n], rhs_conj(a_dense[j + p * n]));
+                                    acc = (acc.0 + x.0, acc.1 + x.1);
+                                    abs_acc += x.0.abs() + x.1.abs();
+                                }
+                                let mut expected = ref_mul(beta, acc);
+                                if read_dst {
+                                    let c = ref_mul(alpha, c_dense[i + j * n]);
+                                    expected = (expected.0 + c.0, expected.1 + c.1);
+                                }
+                                if hermitian && i == j {
+                                    expected.1 = 0.0;
+                                }
+                                let (re, im) = dst[i + j * n].to_pair();
+                                let tol = eps * (1.0 + 4.0 * abs_acc);
+                                assert!((re - expected.0).abs() <= tol);
+                                assert!((im - expected.1).abs() <= tol);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_syrk() {
+        test_syrk_ty::<f32>(1e-5, false);
+        test_syrk_ty::<f64>(1e-10, false);
+        test_syrk_ty::<c32>(1e-5, false);
+        test_syrk_ty::<c64>(1e-10, false);
+    }
+
+    #[test]
+    fn test_herk() {
+        test_syrk_ty::<c32>(1e-5, true);
+        test_syrk_ty::<c64>(1e-10, true);
+    }
+
+    fn test_trmm_trsm_ty<T: 'static + RefScalar>(eps: f64) {
+        let mut mns = vec![];
+        mns.push((1, 1));
+        mns.push((5, 3));
+        mns.push((64, 1));
+        mns.push((130, 70));
+
+        for (m, n) in mns {
+            dbg!(m, n);
+            let abs = |a: (f64, f64)| a.0.abs() + a.1.abs();
+
+            let b_dense: Vec<(f64, f64)> = (0..(m * n)).map(|_| ref_random::<T>()).collect();
+            let b_vec: Vec<T> = from_pairs(&b_dense);
+            let alpha = ref_cast::<T>((0.5, -1.5));
+
+            for side in [Side::Left, Side::Right] {
+                let size = if side == Side::Left { m } else { n };
+                // diagonally dominant, so that the solve is well conditioned
+                let t_dense: Vec<(f64, f64)> = (0..(size * size))
+                    .map(|idx| {
+                        let x = ref_random::<T>();
+                        if idx % (size + 1) == 0 {
+                            ref_cast::<T>((x.0.abs() + 2.0, x.1))
+                        } else {
+                            ref_cast::<T>((x.0 / size as f64, x.1 / size as f64))
+                        }
+                    })
+                    .collect();
+                let t_vec: Vec<T> = from_pairs(&t_dense);
+
+                for triangle in [Triangle::Lower, Triangle::Upper] {
+                    for diag in [Diag::Unit, Diag::NonUnit] {
+                        for conj_tri in [false, true] {
+                            for transpose in [false, true] {
+                                // op(tri), dense
+                                let op = |i: usize, j: usize| {
+                                    let (r, c) = if transpose { (j, i) } else { (i, j) };
+                                    let kept = match triangle {
+                                        Triangle::Lower => c <= r,
+                                        Triangle::Upper => c >= r,
+                                    };
+                                    if !kept {
+                                        (0.0, 0.0)
+                                    } else if r == c && diag == Diag::Unit {
+                                        (1.0, 0.0)
+                                    } else {
+                                        let x = t_dense[r + c * size];
+                                        if conj_tri {
+                                            (x.0, -x.1)
+                                        } else {
+                                            x
+                                        }
+                                    }
+                                };
+                                let (tri_cs, tri_rs, op_triangle) = if transpose {
+                                    let flipped = match triangle {
+                                        Triangle::Lower => Triangle::Upper,
+                                        Triangle::Upper => Triangle::Lower,
+                                    };
+                                    (1, size as isize, flipped)
+                                } else {
+                                    (size as isize, 1, triangle)
+                                };
+                                // (op(tri)×x or x×op(tri))[i, j], and the sum of the absolute
+                                // values of its terms
+                                let product = |x: &[(f64, f64)], i: usize, j: usize| {
+                                    let mut acc = (0.0, 0.0);
+                                    let mut abs_acc = 0.0;
+                                    for p in 0..size {
+                                        let y = if side == Side::Left {
+                                            ref_mul(op(i, p), x[p + j * m])
+                                        } else {
+                                            ref_mul(x[i + p * m], op(p, j))
+                                        };
+                                        acc = (acc.0 + y.0, acc.1 + y.1);
+                                        abs_acc += abs(y);
+                                    }
+                                    (acc, abs_acc)
+                                };
+
+                                for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                                    for solve in [false, true] {
+                                        let mut dst = b_vec.clone();
+                                        unsafe {
+                                            (if solve { trsm } else { trmm })(
+                                                side,
+                                                op_triangle,
+                                                diag,
+                                                m,
+                                                n,
+                                                t_vec.as_ptr(),
+                                                tri_cs,
+                                                tri_rs,
+                                                conj_tri,
+                                                dst.as_mut_ptr(),
+                                                m as isize,
+                                                1,
+                                                T::from_pair(alpha.0, alpha.1),
+                                                parallelism,
+                                            );
+                                        }
+                                        let dst: Vec<(f64, f64)> =
+                                            dst.iter().map(|&x| x.to_pair()).collect();
+
+                                        for j in 0..n {
+                                            for i in 0..m {
+                                                // trmm: dst = alpha × op(tri)·b
+                                                // trsm: op(tri)·dst = alpha × b
+                                                let (actual, expected, scale) = if solve {
+                                                    let (acc, abs_acc) = product(&dst, i, j);
+                                                    let b = ref_mul(alpha, b_dense[i + j * m]);
+                                                    (acc, b, abs_acc + abs(b))
+                                                } else {
+                                                    let (acc, abs_acc) = product(&b_dense, i, j);
+                                                    (
+                                                        dst[i + j * m],
+                                                        ref_mul(alpha, acc),
+                                                        abs(alpha) * abs_acc,
+                                                    )
+                                                };
+                                                let tol = eps * (1.0 + 4.0 * scale);
+                                                assert!((actual.0 - expected.0).abs() <= tol);
+                                                assert!((actual.1 - expected.1).abs() <= tol);
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trmm_trsm() {
+        test_trmm_trsm_ty::<f16>(1e-2);
+        test_trmm_trsm_ty::<f32>(1e-5);
+        test_trmm_trsm_ty::<f64>(1e-10);
+        test_trmm_trsm_ty::<c32>(1e-5);
+        test_trmm_trsm_ty::<c64>(1e-10);
+    }
+
+    fn test_symm_ty<T: 'static + RefScalar>(eps: f64, hermitian: bool) {
+        let mut mns = vec![];
+        mns.push((1, 1));
+        mns.push((5, 3));
+        mns.push((70, 130));
+        mns.push((300, 200));
+
+        for (m, n) in mns {
+            dbg!(m, n);
+
+            let b_dense: Vec<(f64, f64)> = (0..(m * n)).map(|_| ref_random::<T>()).collect();
+            let c_dense: Vec<(f64, f64)> = (0..(m * n)).map(|_| ref_random::<T>()).collect();
+            let b_vec: Vec<T> = from_pairs(&b_dense);
+            let c_vec: Vec<T> = from_pairs(&c_dense);
+            let alpha = ref_cast::<T>((0.5, -1.5));
+            let beta = ref_cast::<T>((2.5, 0.25));
+
+            for side in [Side::Left, Side::Right] {
+                let size = if side == Side::Left { m } else { n };
+                let s_dense: Vec<(f64, f64)> = (0..(size * size))
+                    .map(|idx| {
+                        let x = ref_random::<T>();
+                        if hermitian && idx % (size + 1) == 0 {
+                            (x.0, 0.0)
+                        } else {
+                            x
+                        }
+                    })
+                    .collect();
+
+                for triangle in [Triangle::Lower, Triangle::Upper] {
+                    let stored = |i: usize, j: usize| match triangle {
+                        Triangle::Lower => j <= i,
+                        Triangle::Upper => j >= i,
+                    };
+                    // the other triangle and the imaginary part of the Hermitian diagonal are
+                    // never read
+                    let s_vec: Vec<T> = (0..(size * size))
+                        .map(|idx| {
+                            if hermitian && idx % (size + 1) == 0 {
+                                T::from_pair(s_dense[idx].0, f64::NAN)
+                            } else if stored(idx % size, idx / size) {
+                                T::from_pair(s_dense[idx].0, s_dense[idx].1)
+                            } else {
+                                T::from_pair(f64::NAN, f64::NAN)
+                            }
+                        })
+                        .collect();
+                    let full = |i: usize, j: usize| {
+                        if stored(i, j) {
+                            s_dense[i + j * size]
+                        } else {
+                            let x = s_dense[j + i * size];
+                            if hermitian {
+                                (x.0, -x.1)
+                            } else {
+                                x
+                            }
+                        }
+                    };
+
+                    let mut product = vec![(0.0, 0.0); m * n];
+                    let mut abs_product = vec![0.0; m * n];
+                    for j in 0..n {
+                        for i in 0..m {
+                            for p in 0..size {
+                                let x = if side == Side::Left {
+                                    ref_mul(full(i, p), b_dense[p + j * m])
+                                } else {
+                                    ref_mul(b_dense[i + p * m], full(p, j))
+                                };
+                                product[i + j * m].0 += x.0;
+                                product[i + j * m].1 += x.1;
+                                abs_product[i + j * m] += x.0.abs() + x.1.abs();
+                            }
+                        }
+                    }
+
+                    for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                        for read_dst in [false, true] {
+                            let mut dst = c_vec.clone();
+                            unsafe {
+                                (if hermitian { hemm } else { symm })(
+                                    side,
+                                    triangle,
+                                    m,
+                                    n,
+                                    dst.as_mut_ptr(),
+                                    m as isize,
+                                    1,
+                                    read_dst,
+                                    s_vec.as_ptr(),
+                                    size as isize,
+                                    1,
+                                    b_vec.as_ptr(),
+                                    m as isize,
+                                    1,
+                                    T::from_pair(alpha.0, alpha.1),
+                                    T::from_pair(beta.0, beta.1),
+                                    parallelism,
+                                );
+                            }
+
+                            for idx in 0..m * n {
+                                let mut expected = ref_mul(beta, product[idx]);
+                                if read_dst {
+                                    let c = ref_mul(alpha, c_dense[idx]);
+                                    expected = (expected.0 + c.0, expected.1 + c.1);
+                                }
+                                let (re, im) = dst[idx].to_pair();
+                                let tol = eps * (1.0 + 4.0 * abs_product[idx]);
+                                assert!((re - expected.0).abs() <= tol);
+                                assert!((im - expected.1).abs() <= tol);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_symm() {
+        test_symm_ty::<f32>(1e-5, false);
+        test_symm_ty::<f64>(1e-10, false);
+        test_symm_ty::<c32>(1e-5, false);
+        test_symm_ty::<c64>(1e-10, false);
+    }
+
+    #[test]
+    fn test_hemm() {
+        test_symm_ty::<c32>(1e-5, true);
+        test_symm_ty::<c64>(1e-10, true);
+    }
+
+    type GemmFn<T> = unsafe fn(
+        usize,
+        usize,
+        usize,
+        *mut T,
+        isize,
+        isize,
+        bool,
+        *const T,
+        isize,
+        isize,
+        *const T,
+        isize,
+        isize,
+        T,
+        T,
+        bool,
+        bool,
+        bool,
+        Parallelism,
+    );
+
+    // `gemm_3m` is the 3M kernel itself, so the test does not depend on the threshold
+    fn test_gemm_3m_ty<T: 'static + RefScalar>(gemm_3m: GemmFn<T>, eps: f64) {
+        let mut mnks = vec![];
+        mnks.push((1, 1, 1));
+        mnks.push((17, 5, 3));
+        mnks.push((64, 64, 64));
+        mnks.push((130, 70, 300));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            // values representable in `T`
+            let conj = |a: (f64, f64), conj: bool| if conj { (a.0, -a.1) } else { a };
+
+            // row major `lhs`, column major `rhs` and `dst`
+            let a_dense: Vec<(f64, f64)> = (0..(m * k)).map(|_| ref_random::<T>()).collect();
+            let b_dense: Vec<(f64, f64)> = (0..(k * n)).map(|_| ref_random::<T>()).collect();
+            let c_dense: Vec<(f64, f64)> = (0..(m * n)).map(|_| ref_random::<T>()).collect();
+            let a_vec: Vec<T> = from_pairs(&a_dense);
+            let b_vec: Vec<T> = from_pairs(&b_dense);
+            let c_vec: Vec<T> = from_pairs(&c_dense);
+            let alpha = ref_cast::<T>((0.5, -1.5));
+            let beta = ref_cast::<T>((2.5, 0.25));
+
+            for conj_dst in [false, true] {
+                for conj_lhs in [false, true] {
+                    for conj_rhs in [false, true] {
+                        for read_dst in [false, true] {
+                            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                                let mut dst = c_vec.clone();
+                                unsafe {
+                                    gemm_3m(
+                                        m,
+                                        n,
+                                        k,
+                                        dst.as_mut_ptr(),
+                                        m as isize,
+                                        1,
+                                        read_dst,
+                                        a_vec.as_ptr(),
+                                        1,
+                                        k as isize,
+                                        b_vec.as_ptr(),
+                                        k as isize,
+                                        1,
+                                        T::from_pair(alpha.0, alpha.1),
+                                        T::from_pair(beta.0, beta.1),
+                                        conj_dst,
+                                        conj_lhs,
+                                        conj_rhs,
+                                        parallelism,
+                                    );
+                                }
+
+                                for j in 0..n {
+                                    for i in 0..m {
+                                        let mut acc = (0.0, 0.0);
+                                        let mut abs_acc = 0.0;
+                                        for p in 0..k {
+                                            let x = ref_mul(
+                                                conj(a_dense[i * k + p], conj_lhs),
+                                                conj(b_dense[p + j * k], conj_rhs),
+                                            );
+                                            acc = (acc.0 + x.0, acc.1 + x.1);
+                                            abs_acc += x.0.abs() + x.1.abs();
+                                        }
+                                        let mut expected = ref_mul(beta, acc);
+                                        if read_dst {
+                                            let c =
+                                                ref_mul(alpha, conj(c_dense[i + j * m], conj_dst));
+                                            expected = (expected.0 + c.0, expected.1 + c.1);
+                                        }
+                                        let (re, im) = dst[i + j * m].to_pair();
+                                        let tol = eps * (1.0 + 8.0 * abs_acc);
+                                        assert!((re - expected.0).abs() <= tol);
+                                        assert!((im - expected.1).abs() <= tol);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_3m() {
+        test_gemm_3m_ty::<c32>(*gemm_f32::gemm::f32::GEMM_3M, 1e-5);
+        test_gemm_3m_ty::<c64>(*gemm_f64::gemm::f64::GEMM_3M, 1e-10);
+    }
+
+    fn test_gemm_planar_ty<T: 'static + Copy>(
+        from: impl Fn(f64) -> T,
+        to: impl Fn(T) -> f64,
+        eps: f64,
+    ) {
+        let mut mnks = vec![];
+        mnks.push((1, 1, 1));
+        mnks.push((17, 5, 0));
+        mnks.push((17, 5, 3));
+        mnks.push((64, 64, 64));
+        mnks.push((130, 70, 300));
+        mnks.push((300, 200, 600));
+
+        for (m, n, k) in mnks {
+            dbg!(m, n, k);
+            // values representable in `T`
+            let random = || {
+                (
+                    to(from(rand::random::<f64>() * 2.0 - 1.0)),
+                    to(from(rand::random::<f64>() * 2.0 - 1.0)),
+                )
+            };
+            let mul = |a: (f64, f64), b: (f64, f64)| (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0);
+            let conj = |a: (f64, f64), conj: bool| if conj { (a.0, -a.1) } else { a };
+            let split = |x: &[(f64, f64)]| -> (Vec<T>, Vec<T>) {
+                (
+                    x.iter().map(|x| from(x.0)).collect(),
+                    x.iter().map(|x| from(x.1)).collect(),
+                )
+            };
+
+            // row major `lhs` and `dst`, column major `rhs`
+            let a_dense: Vec<(f64, f64)> = (0..(m * k)).map(|_| random()).collect();
+            let b_dense: Vec<(f64, f64)> = (0..(k * n)).map(|_| random()).collect();
+            let c_dense: Vec<(f64, f64)> = (0..(m * n)).map(|_| random()).collect();
+            let (a_re, a_im) = split(&a_dense);
+            let (b_re, b_im) = split(&b_dense);
+            let (c_re, c_im) = split(&c_dense);
+            let alpha = num_complex::Complex::new(from(0.5), from(-1.5));
+            let beta = num_complex::Complex::new(from(2.5), from(0.25));
+            let alpha_f64 = (to(alpha.re), to(alpha.im));
+            let beta_f64 = (to(beta.re), to(beta.im));
+
+            for conj_dst in [false, true] {
+                for conj_lhs in [false, true] {
+                    for conj_rhs in [false, true] {
+                        for read_dst in [false, true] {
+                            for parallelism in [Parallelism::None, Parallelism::Rayon(0)] {
+                                let mut dst_re = c_re.clone();
+                                let mut dst_im = c_im.clone();
+                                unsafe {
+                                    gemm_planar(
+                                        m,
+                                        n,
+                                        k,
+                                        dst_re.as_mut_ptr(),
+                                        dst_im.as_mut_ptr(),
+                                        1,
+                                        n as isize,
+                                        read_dst,
+                                        a_re.as_ptr(),
+                                        a_im.as_ptr(),
+                                        1,
+                                        k as isize,
+                                        b_re.as_ptr(),
+                                        b_im.as_ptr(),
+                                        k as isize,
+                                        1,
+                                        alpha,
+                                        beta,
+                                        conj_dst,
+                                        conj_lhs,
+                                        conj_rhs,
+                                        parallelism,
+                                    );
+                                }
+
+                                for i in 0..m {
+                                    for j in 0..n {
+                                        let mut acc = (0.0, 0.0);
+                                        let mut abs_acc = 0.0;
+                                        for p in 0..k {
+                                            let x = mul(
+                                                conj(a_dense[i * k + p], conj_lhs),
+                                                conj(b_dense[p + j * k], conj_rhs),
+                                            );
+                                            acc = (acc.0 + x.0, acc.1 + x.1);
+                                            abs_acc += x.0.abs() + x.1.abs();
+                                        }
+                                        let mut expected = mul(beta_f64, acc);
+                                        if read_dst {
+                                            let c =
+                                                mul(alpha_f64, conj(c_dense[i * n + j], conj_dst));
+                                            expected = (expected.0 + c.0, expected.1 + c.1);
+                                        }
+                                        let re = to(dst_re[i * n + j]);
+                                        let im = to(dst_im[i * n + j]);
+                                        let tol = eps * (1.0 + 4.0 * abs_acc);
+                                        assert!((re - expected.0).abs() <= tol);
+                                        assert!((im - expected.1).abs() <= tol);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gemm_planar() {
+        test_gemm_planar_ty::<f32>(|x| x as f32, |x| x as f64, 1e-5);
+        test_gemm_planar_ty::<f64>(|x| x, |x| x, 1e-10);
+    }
+
     #[test]
     fn test_gemm_cplx() {
         let mut mnks = vec![];
@@ -0,0 +1,131 @@
+use crate::{c32, c64, f16};
+use core::ops::{Add, Div, Mul, Neg, Sub};
+use num_traits::{One, Zero};
+
+/// Scalar types of the routines that are written on top of [`gemm`](crate::gemm), such as
+/// [`trmm`](crate::trmm) and [`trsm`](crate::trsm)
+pub trait Scalar:
+    'static
+    + Copy
+    + Zero
+    + One
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    fn conj(self) -> Self;
+    fn real(self) -> f64;
+    /// `|re| + |im|`
+    fn abs1(self) -> f64;
+    fn from_real(x: f64) -> Self;
+}
+
+impl Scalar for f16 {
+    #[inline(always)]
+    fn conj(self) -> Self {
+        self
+    }
+    #[inline(always)]
+    fn real(self) -> f64 {
+        self.to_f64()
+    }
+    #[inline(always)]
+    fn abs1(self) -> f64 {
+        self.to_f64().abs()
+    }
+    #[inline(always)]
+    fn from_real(x: f64) -> Self {
+        f16::from_f64(x)
+    }
+}
+impl Scalar for f32 {
+    #[inline(always)]
+    fn conj(self) -> Self {
+        self
+    }
+    #[inline(always)]
+    fn real(self) -> f64 {
+        self as f64
+    }
+    #[inline(always)]
+    fn abs1(self) -> f64 {
+        self.abs() as f64
+    }
+    #[inline(always)]
+    fn from_real(x: f64) -> Self {
+        x as f32
+    }
+}
+impl Scalar for f64 {
+    #[inline(always)]
+    fn conj(self) -> Self {
+        self
+    }
+    #[inline(always)]
+    fn real(self) -> f64 {
+        self
+    }
+    #[inline(always)]
+    fn abs1(self) -> f64 {
+        self.abs()
+    }
+    #[inline(always)]
+    fn from_real(x: f64) -> Self {
+        x
+    }
+}
+impl Scalar for c32 {
+    #[inline(always)]
+    fn conj(self) -> Self {
+        c32::conj(&self)
+    }
+    #[inline(always)]
+    fn real(self) -> f64 {
+        self.re as f64
+    }
+    #[inline(always)]
+    fn abs1(self) -> f64 {
+        (self.re.abs() + self.im.abs()) as f64
+    }
+    #[inline(always)]
+    fn from_real(x: f64) -> Self {
+        c32::new(x as f32, 0.0)
+    }
+}
+impl Scalar for c64 {
+    #[inline(always)]
+    fn conj(self) -> Self {
+        c64::conj(&self)
+    }
+    #[inline(always)]
+    fn real(self) -> f64 {
+        self.re
+    }
+    #[inline(always)]
+    fn abs1(self) -> f64 {
+        self.re.abs() + self.im.abs()
+    }
+    #[inline(always)]
+    fn from_real(x: f64) -> Self {
+        c64::new(x, 0.0)
+    }
+}
+
+/// Calls `$func::<$T, U>($args)` with `U` the first of the listed [`Scalar`] types that is `$T`,
+/// and panics if there is none
+#[doc(hidden)]
+#[macro_export]
+macro_rules! dispatch_scalar {
+    ($T: ty, [], $func: ident, ($($arg: expr),* $(,)?)) => {
+        panic!("unsupported scalar type `{}`", ::core::any::type_name::<$T>())
+    };
+    ($T: ty, [$ty: ty $(, $rest: ty)*], $func: ident, ($($arg: expr),* $(,)?)) => {
+        if ::core::any::TypeId::of::<$T>() == ::core::any::TypeId::of::<$ty>() {
+            $func::<$T, $ty>($($arg),*)
+        } else {
+            $crate::dispatch_scalar!($T, [$($rest),*], $func, ($($arg),*))
+        }
+    };
+}
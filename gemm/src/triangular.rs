@@ -0,0 +1,431 @@
+use crate::{c32, c64, dispatch_scalar, f16, gemm, Parallelism, Scalar, Triangle};
+
+/// Side of the triangular factor in [`trmm`] and [`trsm`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// `op(tri)×b`
+    Left,
+    /// `b×op(tri)`
+    Right,
+}
+
+/// Diagonal of the triangular factor in [`trmm`] and [`trsm`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Diag {
+    /// the diagonal is taken to be all ones, and is not read
+    Unit,
+    NonUnit,
+}
+
+// rows of the diagonal blocks that are handled directly, the rest of the work goes to `gemm`
+const NB: usize = 64;
+
+// the `n×n` triangular factor, with the conjugation and the implicit unit diagonal applied
+#[derive(Copy, Clone)]
+struct Tri<U> {
+    ptr: *const U,
+    cs: isize,
+    rs: isize,
+    conj: bool,
+    unit: bool,
+}
+
+impl<U: Scalar> Tri<U> {
+    #[inline(always)]
+    unsafe fn get(&self, i: usize, j: usize) -> U {
+        let x = *self.ptr.offset(i as isize * self.rs + j as isize * self.cs);
+        if self.conj {
+            x.conj()
+        } else {
+            x
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn diag(&self, i: usize) -> U {
+        if self.unit {
+            U::one()
+        } else {
+            self.get(i, i)
+        }
+    }
+
+    #[inline(always)]
+    fn offset(self, row: usize, col: usize) -> Self {
+        Self {
+            ptr: self
+                .ptr
+                .wrapping_offset(row as isize * self.rs + col as isize * self.cs),
+            ..self
+        }
+    }
+}
+
+// b := alpha×tri×b, for an `nb×nb` diagonal block of the factor and `nb×n` block of `b`
+unsafe fn trmm_diag_block<U: Scalar>(
+    nb: usize,
+    n: usize,
+    tri: Tri<U>,
+    triangle: Triangle,
+    b: *mut U,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: U,
+) {
+    for j in 0..n {
+        let x = b.offset(j as isize * b_cs);
+        let x = |i: usize| x.offset(i as isize * b_rs);
+        match triangle {
+            // each row only depends on the ones above it, which are still unchanged when the
+            // rows are updated from the bottom
+            Triangle::Lower => {
+                for i in (0..nb).rev() {
+                    let mut acc = tri.diag(i) * *x(i);
+                    for p in 0..i {
+                        acc = acc + tri.get(i, p) * *x(p);
+                    }
+                    *x(i) = alpha * acc;
+                }
+            }
+            Triangle::Upper => {
+                for i in 0..nb {
+                    let mut acc = tri.diag(i) * *x(i);
+                    for p in i + 1..nb {
+                        acc = acc + tri.get(i, p) * *x(p);
+                    }
+                    *x(i) = alpha * acc;
+                }
+            }
+        }
+    }
+}
+
+// b := tri⁻¹×(alpha×b), for an `nb×nb` diagonal block of the factor and `nb×n` block of `b`
+unsafe fn trsm_diag_block<U: Scalar>(
+    nb: usize,
+    n: usize,
+    tri: Tri<U>,
+    triangle: Triangle,
+    b: *mut U,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: U,
+) {
+    for j in 0..n {
+        let x = b.offset(j as isize * b_cs);
+        let x = |i: usize| x.offset(i as isize * b_rs);
+        let solve_row = |i: usize, range: core::ops::Range<usize>| {
+            let mut acc = alpha * *x(i);
+            for p in range {
+                acc = acc - tri.get(i, p) * *x(p);
+            }
+            *x(i) = if tri.unit { acc } else { acc / tri.get(i, i) };
+        };
+        match triangle {
+            Triangle::Lower => {
+                for i in 0..nb {
+                    solve_row(i, 0..i);
+                }
+            }
+            Triangle::Upper => {
+                for i in (0..nb).rev() {
+                    solve_row(i, i + 1..nb);
+                }
+            }
+        }
+    }
+}
+
+// the product on the right side is the transpose of a product on the left side, with the
+// transposed factor
+#[inline(always)]
+fn to_left(
+    side: Side,
+    triangle: Triangle,
+    m: usize,
+    n: usize,
+    tri_cs: isize,
+    tri_rs: isize,
+    b_cs: isize,
+    b_rs: isize,
+) -> (Triangle, usize, usize, isize, isize, isize, isize) {
+    match side {
+        Side::Left => (triangle, m, n, tri_cs, tri_rs, b_cs, b_rs),
+        Side::Right => (
+            match triangle {
+                Triangle::Lower => Triangle::Upper,
+                Triangle::Upper => Triangle::Lower,
+            },
+            n,
+            m,
+            tri_rs,
+            tri_cs,
+            b_rs,
+            b_cs,
+        ),
+    }
+}
+
+unsafe fn trmm_imp<T: 'static, U: Scalar>(
+    side: Side,
+    triangle: Triangle,
+    diag: Diag,
+    m: usize,
+    n: usize,
+    tri: *const T,
+    tri_cs: isize,
+    tri_rs: isize,
+    conj_tri: bool,
+    b: *mut T,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: T,
+    parallelism: Parallelism,
+) {
+    let (triangle, m, n, tri_cs, tri_rs, b_cs, b_rs) =
+        to_left(side, triangle, m, n, tri_cs, tri_rs, b_cs, b_rs);
+    let tri = Tri {
+        ptr: tri as *const U,
+        cs: tri_cs,
+        rs: tri_rs,
+        conj: conj_tri,
+        unit: diag == Diag::Unit,
+    };
+    let b = b as *mut U;
+    let alpha = *(&alpha as *const T as *const U);
+
+    // the block rows of `b` are updated in the order that leaves the rows they depend on
+    // unchanged until they are read
+    let n_blocks = m.div_ceil(NB);
+    for block in 0..n_blocks {
+        let block = match triangle {
+            Triangle::Lower => n_blocks - 1 - block,
+            Triangle::Upper => block,
+        };
+        let row = block * NB;
+        let nb = NB.min(m - row);
+        let b_block = b.wrapping_offset(row as isize * b_rs);
+
+        trmm_diag_block(
+            nb,
+            n,
+            tri.offset(row, row),
+            triangle,
+            b_block,
+            b_cs,
+            b_rs,
+            alpha,
+        );
+
+        let (depth_start, depth_end) = match triangle {
+            Triangle::Lower => (0, row),
+            Triangle::Upper => (row + nb, m),
+        };
+        if depth_start < depth_end {
+            gemm(
+                nb,
+                n,
+                depth_end - depth_start,
+                b_block,
+                b_cs,
+                b_rs,
+                true,
+                tri.offset(row, depth_start).ptr,
+                tri_cs,
+                tri_rs,
+                b.wrapping_offset(depth_start as isize * b_rs),
+                b_cs,
+                b_rs,
+                U::one(),
+                alpha,
+                false,
+                conj_tri,
+                false,
+                parallelism,
+            );
+        }
+    }
+}
+
+unsafe fn trsm_imp<T: 'static, U: Scalar>(
+    side: Side,
+    triangle: Triangle,
+    diag: Diag,
+    m: usize,
+    n: usize,
+    tri: *const T,
+    tri_cs: isize,
+    tri_rs: isize,
+    conj_tri: bool,
+    b: *mut T,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: T,
+    parallelism: Parallelism,
+) {
+    let (triangle, m, n, tri_cs, tri_rs, b_cs, b_rs) =
+        to_left(side, triangle, m, n, tri_cs, tri_rs, b_cs, b_rs);
+    let tri = Tri {
+        ptr: tri as *const U,
+        cs: tri_cs,
+        rs: tri_rs,
+        conj: conj_tri,
+        unit: diag == Diag::Unit,
+    };
+    let b = b as *mut U;
+    let alpha = *(&alpha as *const T as *const U);
+
+    // the block rows of the solution are computed in order of dependency, each one after
+    // subtracting the contribution of the ones already solved
+    let n_blocks = m.div_ceil(NB);
+    for block in 0..n_blocks {
+        let block = match triangle {
+            Triangle::Lower => block,
+            Triangle::Upper => n_blocks - 1 - block,
+        };
+        let row = block * NB;
+        let nb = NB.min(m - row);
+        let b_block = b.wrapping_offset(row as isize * b_rs);
+
+        let (depth_start, depth_end) = match triangle {
+            Triangle::Lower => (0, row),
+            Triangle::Upper => (row + nb, m),
+        };
+        let scale = if depth_start < depth_end {
+            gemm(
+                nb,
+                n,
+                depth_end - depth_start,
+                b_block,
+                b_cs,
+                b_rs,
+                true,
+                tri.offset(row, depth_start).ptr,
+                tri_cs,
+                tri_rs,
+                b.wrapping_offset(depth_start as isize * b_rs),
+                b_cs,
+                b_rs,
+                alpha,
+                U::zero() - U::one(),
+                false,
+                conj_tri,
+                false,
+                parallelism,
+            );
+            U::one()
+        } else {
+            alpha
+        };
+
+        trsm_diag_block(
+            nb,
+            n,
+            tri.offset(row, row),
+            triangle,
+            b_block,
+            b_cs,
+            b_rs,
+            scale,
+        );
+    }
+}
+
+/// Triangular matrix product: b := alpha×op(tri)×b if `side` is [`Side::Left`], otherwise
+/// b := alpha×b×op(tri), where `b` is `m×n` and `tri` is `m×m` or `n×n`.
+///
+/// Only the `triangle` of `tri` is read, and its diagonal is taken to be all ones for
+/// [`Diag::Unit`]. op(tri) is `tri`, conjugated if `conj_tri`. The transpose is obtained by swapping
+/// `tri_cs` and `tri_rs` along with the `triangle`.
+///
+/// The diagonal blocks are multiplied directly, and the off-diagonal blocks with [`gemm`].
+///
+/// # Panics
+///
+/// Panics if `T` is not `f16`, `f32`, `f64`, `c32` or `c64`
+pub unsafe fn trmm<T: 'static>(
+    side: Side,
+    triangle: Triangle,
+    diag: Diag,
+    m: usize,
+    n: usize,
+    tri: *const T,
+    tri_cs: isize,
+    tri_rs: isize,
+    conj_tri: bool,
+    b: *mut T,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: T,
+    parallelism: Parallelism,
+) {
+    dispatch_scalar!(
+        T,
+        [f64, f32, f16, c64, c32],
+        trmm_imp,
+        (
+            side,
+            triangle,
+            diag,
+            m,
+            n,
+            tri,
+            tri_cs,
+            tri_rs,
+            conj_tri,
+            b,
+            b_cs,
+            b_rs,
+            alpha,
+            parallelism,
+        )
+    )
+}
+
+/// Triangular solve: b := alpha×op(tri)⁻¹×b if `side` is [`Side::Left`], otherwise
+/// b := alpha×b×op(tri)⁻¹, where `b` is `m×n` and `tri` is `m×m` or `n×n`.
+///
+/// `tri` is read as in [`trmm`]. The diagonal blocks are solved directly, and the solved rows
+/// are subtracted from the remaining ones with [`gemm`].
+///
+/// # Panics
+///
+/// Panics if `T` is not `f16`, `f32`, `f64`, `c32` or `c64`
+pub unsafe fn trsm<T: 'static>(
+    side: Side,
+    triangle: Triangle,
+    diag: Diag,
+    m: usize,
+    n: usize,
+    tri: *const T,
+    tri_cs: isize,
+    tri_rs: isize,
+    conj_tri: bool,
+    b: *mut T,
+    b_cs: isize,
+    b_rs: isize,
+    alpha: T,
+    parallelism: Parallelism,
+) {
+    dispatch_scalar!(
+        T,
+        [f64, f32, f16, c64, c32],
+        trsm_imp,
+        (
+            side,
+            triangle,
+            diag,
+            m,
+            n,
+            tri,
+            tri_cs,
+            tri_rs,
+            conj_tri,
+            b,
+            b_cs,
+            b_rs,
+            alpha,
+            parallelism,
+        )
+    )
+}